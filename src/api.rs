@@ -0,0 +1,111 @@
+use serde::Serialize;
+
+use crate::refresh::Trigger;
+use crate::status::Status;
+
+#[cfg(test)]
+#[path = "api_test.rs"]
+mod api_test;
+
+/// A response of the status API.
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    /// JSON encoded body.
+    pub body: String,
+}
+
+/// Error body of failed requests.
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+/// Handle a request to the status API. GET /packages and GET /builds
+/// are public, POST /refresh requires the token as bearer token and
+/// triggers a refresh cycle.
+#[cfg_attr(not(feature = "api"), allow(dead_code))]
+pub fn handle(
+    status: &Status,
+    refresh_trigger: &Trigger,
+    token: &str,
+    (method, path): (&str, &str),
+    authorization: Option<&str>,
+) -> Response {
+    match (method, path.trim_end_matches('/')) {
+        ("GET", "/packages") => json(200, &status.packages()),
+        ("GET", "/builds") => json(200, &status.builds()),
+        ("POST", "/refresh") => {
+            if authorization != Some(format!("Bearer {}", token).as_str()) {
+                return error(401, "invalid token");
+            }
+
+            refresh_trigger.all();
+            json(202, &serde_json::json!({ "refresh": "triggered" }))
+        }
+        (_, "/packages") | (_, "/builds") | (_, "/refresh") => error(405, "method not allowed"),
+        _ => error(404, "not found"),
+    }
+}
+
+/// Respond with value encoded as JSON.
+pub fn json<T: Serialize + ?Sized>(status: u16, value: &T) -> Response {
+    match serde_json::to_string(value) {
+        Ok(body) => Response { status, body },
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+/// Respond with a JSON error message.
+pub fn error(status: u16, error: &str) -> Response {
+    Response {
+        status,
+        body: serde_json::to_string(&ErrorBody { error }).unwrap_or_default(),
+    }
+}
+
+/// Serve the status API at the given port.
+#[cfg(feature = "api")]
+pub async fn serve(
+    status: std::sync::Arc<Status>,
+    refresh_trigger: std::sync::Arc<Trigger>,
+    port: u16,
+    token: String,
+) -> Result<(), hyper::Error> {
+    use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Server, StatusCode};
+    use std::convert::Infallible;
+    use std::sync::Arc;
+
+    let token = Arc::new(token);
+    let make_service = make_service_fn(move |_| {
+        let (status, refresh_trigger, token) =
+            (status.clone(), refresh_trigger.clone(), token.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let res = handle(
+                    &status,
+                    &refresh_trigger,
+                    &token,
+                    (req.method().as_str(), req.uri().path()),
+                    req.headers()
+                        .get(AUTHORIZATION)
+                        .and_then(|i| i.to_str().ok()),
+                );
+
+                let mut response = hyper::Response::new(Body::from(res.body));
+                *response.status_mut() =
+                    StatusCode::from_u16(res.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                response
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+
+    Server::bind(&([0, 0, 0, 0], port).into())
+        .serve(make_service)
+        .await
+}
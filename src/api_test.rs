@@ -0,0 +1,37 @@
+use super::*;
+
+use crate::refresh::Scope;
+
+fn get(status: &Status, path: &str) -> Response {
+    handle(status, &Trigger::default(), "secret", ("GET", path), None)
+}
+
+#[test]
+fn list_packages() {
+    let status = Status::default();
+    assert_eq!(get(&status, "/packages").body, "[]");
+
+    status.package_checked("foo", "1.0-1", "1.1-1");
+    let res = get(&status, "/packages/");
+    assert_eq!(res.status, 200);
+    assert!(res
+        .body
+        .contains(r#""name":"foo","local_version":"1.0-1","aur_version":"1.1-1""#));
+
+    assert_eq!(get(&status, "/builds").status, 200);
+    assert_eq!(get(&status, "/missing").status, 404);
+}
+
+#[test]
+fn refresh_requires_token() {
+    let status = Status::default();
+    let trigger = Trigger::default();
+    let refresh = |auth| handle(&status, &trigger, "secret", ("POST", "/refresh"), auth);
+
+    assert_eq!(refresh(None).status, 401);
+    assert_eq!(refresh(Some("Bearer wrong")).status, 401);
+    assert_eq!(refresh(Some("Bearer secret")).status, 202);
+    assert_eq!(trigger.take(false), Scope::All);
+
+    assert_eq!(get(&status, "/refresh").status, 405);
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use aur_client_fork::aur;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[cfg(test)]
+#[path = "aur_rpc_test.rs"]
+mod aur_rpc_test;
+
+/// Version of the AUR RPC interface.
+const AUR_RPC_VER: &str = "5";
+
+/// Response of an info request with the packages kept as JSON.
+#[derive(Debug, Deserialize)]
+struct InfoResponse {
+    #[serde(rename = "type", default)]
+    kind: String,
+    error: Option<String>,
+    #[serde(default)]
+    results: Vec<Value>,
+}
+
+impl InfoResponse {
+    /// Returns the found packages. Failed requests come back with
+    /// no results, they must not be taken as packages not existing.
+    fn into_results(self) -> Result<Vec<Value>, Box<dyn Error>> {
+        match self.error {
+            Some(e) => Err(format!("AUR RPC error: {}", e).into()),
+            None if self.kind == "error" => Err("AUR RPC error".into()),
+            None => Ok(self.results),
+        }
+    }
+}
+
+/// Cache keeps looked up packages for ttl. The packages are stored
+/// as JSON since aur::Package can't be cloned.
+#[derive(Debug)]
+pub struct Cache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Value)>>,
+}
+
+impl Cache {
+    /// Create a new cache. A ttl of 0 disables it.
+    pub fn new(ttl: Duration) -> Self {
+        Cache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cached package if it isn't expired.
+    pub fn get(&self, name: &str) -> Option<aur::Package> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(name) {
+            Some((added, value)) if added.elapsed() < self.ttl => {
+                serde_json::from_value(value.clone()).ok()
+            }
+            Some(_) => {
+                entries.remove(name);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Store a package looked up from the AUR.
+    fn insert(&self, value: &Value) {
+        if self.ttl == Duration::from_secs(0) {
+            return;
+        }
+
+        if let Some(name) = value.get("Name").and_then(|i| i.as_str()) {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(name.to_owned(), (Instant::now(), value.clone()));
+        }
+    }
+
+    /// Forget all cached packages.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Look up packages using the AUR RPC interface at rpc_url.
+/// The found packages are added to cache.
+pub async fn info(
+    rpc_url: &str,
+    names: &[&str],
+    cache: &Cache,
+) -> Result<Vec<aur::Package>, Box<dyn Error>> {
+    let mut args = vec![("v", AUR_RPC_VER), ("type", "info")];
+    args.extend(names.iter().map(|i| ("arg[]", *i)));
+
+    let url = Url::parse_with_params(rpc_url, &args)?;
+    let res: InfoResponse = Client::new()
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut packages = Vec::new();
+    for value in res.into_results()? {
+        cache.insert(&value);
+        packages.push(serde_json::from_value(value)?);
+    }
+
+    Ok(packages)
+}
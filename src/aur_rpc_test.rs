@@ -0,0 +1,69 @@
+use super::*;
+
+fn package(name: &str, version: &str) -> Value {
+    serde_json::json!({
+        "ID": 1,
+        "Name": name,
+        "PackageBaseID": 1,
+        "PackageBase": name,
+        "Version": version,
+        "NumVotes": 0,
+        "Popularity": 0.0,
+        "FirstSubmitted": 0,
+        "LastModified": 0,
+        "URLPath": format!("/cgit/aur.git/snapshot/{}.tar.gz", name),
+    })
+}
+
+#[test]
+fn cache_packages() {
+    let cache = Cache::new(Duration::from_secs(60));
+    cache.insert(&package("foo", "1.0-1"));
+
+    assert_eq!(cache.get("foo").unwrap().Version, "1.0-1");
+    assert!(cache.get("bar").is_none());
+
+    cache.insert(&package("foo", "1.1-1"));
+    assert_eq!(cache.get("foo").unwrap().Version, "1.1-1");
+
+    cache.clear();
+    assert!(cache.get("foo").is_none());
+}
+
+#[test]
+fn expire_packages() {
+    let cache = Cache::new(Duration::from_millis(1));
+    cache.insert(&package("foo", "1.0-1"));
+    std::thread::sleep(Duration::from_millis(5));
+    assert!(cache.get("foo").is_none());
+
+    let disabled = Cache::new(Duration::from_secs(0));
+    disabled.insert(&package("foo", "1.0-1"));
+    assert!(disabled.get("foo").is_none());
+}
+
+#[test]
+fn info_response_errors() {
+    let res: InfoResponse = serde_json::from_value(serde_json::json!({
+        "version": 5,
+        "type": "multiinfo",
+        "resultcount": 1,
+        "results": [package("foo", "1.0-1")],
+    }))
+    .unwrap();
+    assert_eq!(res.into_results().unwrap().len(), 1);
+
+    let res: InfoResponse = serde_json::from_value(serde_json::json!({
+        "version": 5,
+        "type": "error",
+        "resultcount": 0,
+        "results": [],
+        "error": "Too many package results.",
+    }))
+    .unwrap();
+    let err = res.into_results().unwrap_err();
+    assert_eq!(err.to_string(), "AUR RPC error: Too many package results.");
+
+    let res: InfoResponse = serde_json::from_value(serde_json::json!({ "type": "error" })).unwrap();
+    assert!(res.into_results().is_err());
+}
@@ -0,0 +1,307 @@
+//! Interfaces of the services an update talks to. The build service
+//! only uses these, so its flow can be tested against fakes.
+
+use std::error::Error as stdErr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use aur_client_fork::aur;
+use git2::Repository;
+use lib_remotebuild_rs::jobs::Status as jobStatus;
+use lib_remotebuild_rs::librb::LibRb;
+use lib_remotebuild_rs::request_error::Error as RequestError;
+use log::info;
+use tokio::process::Command;
+
+use crate::aur_rpc;
+use crate::build_state::BuildState;
+use crate::command;
+use crate::config::{self, Config};
+use crate::dmanager::DManager;
+use crate::error::Error;
+use crate::git_cache;
+
+/// Looks up packages in the AUR.
+#[async_trait(?Send)]
+pub trait AurClient {
+    /// Look up packages by name. Found packages are added to cache.
+    async fn info(
+        &self,
+        names: &[&str],
+        cache: &aur_rpc::Cache,
+    ) -> Result<Vec<aur::Package>, Box<dyn stdErr>>;
+}
+
+/// Builds packages. The .SRCINFO is generated locally, the
+/// packages are built by remote build jobs.
+#[async_trait(?Send)]
+pub trait Builder {
+    /// Returns the .SRCINFO of the PKGBUILD in dir.
+    async fn print_srcinfo(&self, pkg_name: &str, dir: &Path) -> Result<String, Error>;
+
+    /// Create a build job for an update. Returns the id of the job.
+    async fn create_job(&self, state: &BuildState) -> Result<u32, Error>;
+
+    /// Returns the status of a build job.
+    async fn job_status(&self, job_id: u32) -> Result<jobStatus, Error>;
+
+    async fn cancel_job(&self, job_id: u32) -> Result<(), Error>;
+
+    /// Download the package built by a job into dir.
+    /// Returns the path of the downloaded file.
+    async fn download(&self, job_id: u32, dir: &Path) -> Result<PathBuf, Error>;
+}
+
+/// Clones and pushes git repositories.
+pub trait GitOps {
+    /// Clone url into dest. With a cache_dir configured the clone is
+    /// made from the persistent checkout at cache_name inside of it.
+    /// Authenticated clones use the credentials of the custom repositories.
+    fn clone(
+        &self,
+        url: &str,
+        dest: &Path,
+        cache_name: &Path,
+        branch: Option<&str>,
+        authenticated: bool,
+    ) -> Result<Repository, git2::Error>;
+
+    /// Push refspec to the remote of repo.
+    fn push(&self, repo: &Repository, remote: &str, refspec: &str) -> Result<(), git2::Error>;
+}
+
+/// Looks up packages using the AUR RPC interface.
+pub struct AurRpc {
+    rpc_url: String,
+}
+
+impl AurRpc {
+    pub fn new(config: &Config) -> Self {
+        AurRpc {
+            rpc_url: config.aur_rpc_url().to_owned(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl AurClient for AurRpc {
+    async fn info(
+        &self,
+        names: &[&str],
+        cache: &aur_rpc::Cache,
+    ) -> Result<Vec<aur::Package>, Box<dyn stdErr>> {
+        aur_rpc::info(&self.rpc_url, names, cache).await
+    }
+}
+
+/// Builds packages with makepkg and RemoteBuild, which uploads
+/// them to the dmanager.
+pub struct RemoteBuilder {
+    rbuild: LibRb,
+    dmanager: config::TokenConfig,
+}
+
+impl RemoteBuilder {
+    pub fn new(config: &Config) -> Self {
+        RemoteBuilder {
+            rbuild: config.as_rbuild(),
+            dmanager: config.dmanager.clone(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Builder for RemoteBuilder {
+    async fn print_srcinfo(&self, pkg_name: &str, dir: &Path) -> Result<String, Error> {
+        let output = match Command::new("makepkg")
+            .arg("--printsrcinfo")
+            .current_dir(dir)
+            .output()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::SrcinfoFailed(format!(
+                    "{}: makepkg not found in PATH",
+                    pkg_name
+                )));
+            }
+            Err(e) => return Err(Error::SrcinfoFailed(format!("{}: {}", pkg_name, e))),
+        };
+
+        if !output.status.success() {
+            return Err(Error::SrcinfoFailed(format!(
+                "{}: makepkg {}: {}",
+                pkg_name,
+                output.status,
+                command::stderr(&output)
+            )));
+        }
+
+        String::from_utf8(output.stdout)
+            .map_err(|_| Error::SrcinfoFailed(format!("{}: .SRCINFO isn't UTF-8", pkg_name)))
+    }
+
+    async fn create_job(&self, state: &BuildState) -> Result<u32, Error> {
+        let pkg_name = state.pkg_name.as_str();
+        let mut aurbuild = self.rbuild.new_aurbuild(pkg_name).with_dmanager(
+            self.dmanager.user_name.clone(),
+            self.dmanager.token.clone(),
+            self.dmanager.url.clone(),
+            state.upload_namespace.clone(),
+        );
+        aurbuild.args.extend(state.build_env.clone());
+
+        let job_id = match aurbuild.create_job().await {
+            Ok(build_job) => match build_job.response {
+                Some(response) => response.id,
+                None => {
+                    return Err(Error::AurJobError(
+                        pkg_name.to_owned(),
+                        RequestError::Error("empty response".to_owned()),
+                    ))
+                }
+            },
+            Err(e) => return Err(Error::AurJobError(pkg_name.to_owned(), e)),
+        };
+
+        info!("{}: Created Job with ID: {}", pkg_name, job_id);
+        Ok(job_id)
+    }
+
+    async fn job_status(&self, job_id: u32) -> Result<jobStatus, Error> {
+        let info = match self.rbuild.job_info(job_id).await {
+            Ok(info) => info.response,
+            Err(e) => return Err(Error::JobInfoError(format!("job {}", job_id), Some(e))),
+        };
+
+        match info {
+            Some(info) => Ok(info.status),
+            None => Err(Error::JobInfoError(
+                format!("job {}: empty response", job_id),
+                None,
+            )),
+        }
+    }
+
+    async fn cancel_job(&self, job_id: u32) -> Result<(), Error> {
+        self.rbuild
+            .cancel_job(job_id)
+            .await
+            .map_err(|e| Error::JobInfoError(format!("job {}", job_id), Some(e)))
+    }
+
+    async fn download(&self, job_id: u32, dir: &Path) -> Result<PathBuf, Error> {
+        let dmanager = DManager::new(&self.dmanager);
+        let artifact_url = dmanager.artifact_url(job_id).await?;
+        dmanager.download(&artifact_url, dir).await
+    }
+}
+
+/// Clones and pushes using git2. Authentication happens with
+/// the ssh-agent first and with the configured priv_key if
+/// the agent gets rejected.
+pub struct Git2 {
+    git: config::Git,
+    config_dir: PathBuf,
+    cache_dir: Option<String>,
+}
+
+impl Git2 {
+    pub fn new(config: &Config) -> Self {
+        Git2 {
+            git: config.git.clone(),
+            config_dir: config.config_dir.clone(),
+            cache_dir: config.cache_dir.clone(),
+        }
+    }
+
+    /// Remote callbacks authenticating with the ssh-agent first
+    /// and with the configured priv_key if the agent gets rejected.
+    fn ssh_callbacks(&self) -> git2::RemoteCallbacks<'_> {
+        let mut tried_agent = false;
+        let mut tried_key = false;
+
+        let mut cb = git2::RemoteCallbacks::new();
+        cb.credentials(move |_, username, allowed| {
+            let username = username.unwrap_or("git");
+            if allowed.contains(git2::CredentialType::USERNAME) {
+                return git2::Cred::username(username);
+            }
+
+            if !tried_agent {
+                tried_agent = true;
+                if let Ok(cred) = git2::Cred::ssh_key_from_agent(username) {
+                    return Ok(cred);
+                }
+            }
+
+            if !tried_key && !self.git.priv_key.is_empty() {
+                tried_key = true;
+                return self.get_ssh_auth(username);
+            }
+
+            Err(git2::Error::from_str(
+                "SSH authentication failed: neither the ssh-agent nor priv_key were accepted",
+            ))
+        });
+
+        cb
+    }
+
+    fn get_ssh_auth(&self, username: &str) -> Result<git2::Cred, git2::Error> {
+        let key = self
+            .git
+            .read_priv_key(&self.config_dir)
+            .map_err(|e| git2::Error::from_str(&format!("Can't read priv_key: {}", e)))?;
+
+        git2::Cred::ssh_key_from_memory(username, None, &key, self.git.passphrase().as_deref())
+    }
+
+    fn fetch_options(&self, authenticated: bool) -> git2::FetchOptions<'_> {
+        let mut fo = git2::FetchOptions::new();
+        if authenticated {
+            fo.remote_callbacks(self.ssh_callbacks());
+        }
+        fo
+    }
+}
+
+impl GitOps for Git2 {
+    fn clone(
+        &self,
+        url: &str,
+        dest: &Path,
+        cache_name: &Path,
+        branch: Option<&str>,
+        authenticated: bool,
+    ) -> Result<Repository, git2::Error> {
+        match self.cache_dir {
+            Some(ref cache_dir) => git_cache::clone(
+                url,
+                &Path::new(cache_dir).join(cache_name),
+                dest,
+                branch,
+                || self.fetch_options(authenticated),
+            ),
+            None => {
+                let mut repo_builder = git2::build::RepoBuilder::new();
+                repo_builder.fetch_options(self.fetch_options(authenticated));
+                if let Some(branch) = branch {
+                    repo_builder.branch(branch);
+                }
+
+                repo_builder.clone(url, dest)
+            }
+        }
+    }
+
+    fn push(&self, repo: &Repository, remote: &str, refspec: &str) -> Result<(), git2::Error> {
+        let mut push_option = git2::PushOptions::new();
+        push_option.remote_callbacks(self.ssh_callbacks());
+
+        repo.find_remote(remote)?
+            .push(&[refspec], Some(&mut push_option))
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+#[path = "build_state_test.rs"]
+mod build_state_test;
+
+/// The file in a package's tmp dir holding its build state.
+pub const STATE_FILE: &str = "state.json";
+
+/// The phases of a package update.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    Cloning,
+    WaitingForBuild,
+    Pushing,
+    /// The update was checked without being built, by a dry run or
+    /// since it wasn't approved. Its tmp dir gets reused.
+    Checked,
+}
+
+/// BuildState records the progress of a package update
+/// to be able to resume it after a restart.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct BuildState {
+    pub pkg_name: String,
+    pub local_version: String,
+    pub aur_version: String,
+    pub job_id: Option<u32>,
+    pub phase: Phase,
+    /// Packages built from the pkgbase pkg_name.
+    /// Empty if pkg_name is the only one.
+    #[serde(default)]
+    pub members: Vec<String>,
+    /// Unix time the update was started at. 0 if unknown.
+    #[serde(default)]
+    pub started: u64,
+    /// Environment variables of the build job,
+    /// kept for recreating failed jobs.
+    #[serde(default)]
+    pub build_env: HashMap<String, String>,
+    /// dmanager namespace of the build job. Empty for the default one.
+    #[serde(default)]
+    pub upload_namespace: String,
+}
+
+impl BuildState {
+    /// Create the state of a freshly started update.
+    pub fn new(pkg_name: String, local_version: String, aur_version: String) -> Self {
+        BuildState {
+            pkg_name,
+            local_version,
+            aur_version,
+            job_id: None,
+            phase: Phase::Cloning,
+            members: Vec::new(),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |i| i.as_secs()),
+            build_env: HashMap::new(),
+            upload_namespace: String::new(),
+        }
+    }
+
+    /// Returns how long the update has been running.
+    pub fn age(&self) -> Option<Duration> {
+        if self.started == 0 {
+            return None;
+        }
+
+        let started = UNIX_EPOCH + Duration::from_secs(self.started);
+        SystemTime::now().duration_since(started).ok()
+    }
+
+    /// Returns the names of all packages built by the update.
+    pub fn package_names(&self) -> Vec<&str> {
+        if self.members.is_empty() {
+            vec![self.pkg_name.as_str()]
+        } else {
+            self.members.iter().map(|i| i.as_str()).collect()
+        }
+    }
+
+    /// Load the state stored in dir.
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(dir.join(STATE_FILE))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Store the state in dir. The file is replaced
+    /// atomically so it can't end up half written.
+    pub fn save(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        let part_file = dir.join(format!("{}.part", STATE_FILE));
+        fs::write(&part_file, serde_json::to_string_pretty(self)?)?;
+        fs::rename(part_file, dir.join(STATE_FILE))?;
+        Ok(())
+    }
+
+    /// Set the phase and store the state in dir.
+    pub fn set_phase(&mut self, phase: Phase, dir: &Path) -> Result<(), Box<dyn Error>> {
+        self.phase = phase;
+        self.save(dir)
+    }
+
+    /// Returns true if the build job was submitted already
+    /// and the update can be resumed.
+    pub fn is_resumable(&self) -> bool {
+        self.job_id.is_some() && self.phase != Phase::Cloning
+    }
+}
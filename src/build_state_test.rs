@@ -0,0 +1,54 @@
+use super::*;
+
+use crate::test_dir::TestDir;
+
+#[test]
+fn save_load_state() {
+    let dir = TestDir::new("state");
+
+    let mut state = BuildState::new("pkg".to_owned(), "1.0-1".to_owned(), "1.1-1".to_owned());
+    state.save(&dir).unwrap();
+    assert!(!BuildState::load(&dir).unwrap().is_resumable());
+
+    state.job_id = Some(42);
+    state.set_phase(Phase::WaitingForBuild, &dir).unwrap();
+
+    let loaded = BuildState::load(&dir).unwrap();
+    assert_eq!(loaded, state);
+    assert!(loaded.is_resumable());
+}
+
+#[test]
+fn load_missing_state() {
+    let dir = TestDir::new("missing_state");
+    assert!(BuildState::load(&dir).is_err());
+}
+
+#[test]
+fn state_age() {
+    let mut state = BuildState::new("foo".to_owned(), "1.0-1".to_owned(), "1.1-1".to_owned());
+    assert!(state.age().unwrap() < Duration::from_secs(60));
+
+    state.started -= 3600;
+    assert!(state.age().unwrap() >= Duration::from_secs(3600));
+
+    // States of older versions don't know
+    state.started = 0;
+    assert_eq!(state.age(), None);
+}
+
+#[test]
+fn split_package_names() {
+    let mut state = BuildState::new("foo".to_owned(), "1.0-1".to_owned(), "1.1-1".to_owned());
+    assert_eq!(state.package_names(), vec!["foo"]);
+
+    state.members = vec!["foo-cli".to_owned(), "foo-gui".to_owned()];
+    assert_eq!(state.package_names(), vec!["foo-cli", "foo-gui"]);
+
+    // States written before split packages were supported
+    let loaded: BuildState = serde_json::from_str(
+        r#"{"pkg_name":"foo","local_version":"1","aur_version":"2","job_id":1,"phase":"pushing"}"#,
+    )
+    .unwrap();
+    assert!(loaded.members.is_empty());
+}
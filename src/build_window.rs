@@ -0,0 +1,135 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+
+use crate::config;
+
+#[cfg(test)]
+#[path = "build_window_test.rs"]
+mod build_window_test;
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Daily time span in which build jobs may be created.
+#[derive(Debug, PartialEq)]
+pub struct BuildWindow {
+    /// Seconds since midnight the window opens at.
+    start: i64,
+    /// Seconds since midnight the window closes at.
+    /// Smaller than start for windows spanning midnight.
+    end: i64,
+    /// Offset to UTC in seconds. The local offset is used if None.
+    utc_offset: Option<i64>,
+}
+
+impl BuildWindow {
+    /// Parse the configured window. Times are HH:MM, the offset is
+    /// UTC, Z or a numeric offset like +02:00.
+    pub fn new(config: &config::BuildWindow) -> Result<Self, String> {
+        let start = parse_time(&config.start)?;
+        let end = parse_time(&config.end)?;
+        if start == end {
+            return Err(format!("start and end are both {}", config.start));
+        }
+
+        let utc_offset = match config.utc_offset {
+            Some(ref offset) => Some(parse_offset(offset)?),
+            None => None,
+        };
+
+        Ok(BuildWindow {
+            start,
+            end,
+            utc_offset,
+        })
+    }
+
+    /// Returns true if builds may be created right now.
+    pub fn is_open(&self) -> bool {
+        self.contains(self.now())
+    }
+
+    /// Returns the time until the window opens. Zero if it's open.
+    pub fn until_open(&self) -> Duration {
+        Duration::from_secs(self.secs_until_open(self.now()) as u64)
+    }
+
+    /// Returns true if the window contains the time of day secs.
+    fn contains(&self, secs: i64) -> bool {
+        if self.start < self.end {
+            self.start <= secs && secs < self.end
+        } else {
+            secs >= self.start || secs < self.end
+        }
+    }
+
+    /// Seconds from the time of day secs until the window opens.
+    fn secs_until_open(&self, secs: i64) -> i64 {
+        if self.contains(secs) {
+            0
+        } else {
+            (self.start - secs).rem_euclid(SECS_PER_DAY)
+        }
+    }
+
+    /// Current time of day in seconds since midnight.
+    fn now(&self) -> i64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        let offset = self.utc_offset.unwrap_or_else(|| local_offset(now));
+        (now + offset).rem_euclid(SECS_PER_DAY)
+    }
+}
+
+/// Returns the offset of the local timezone to UTC at the unix time now.
+fn local_offset(now: i64) -> i64 {
+    let now = now as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return 0;
+    }
+
+    tm.tm_gmtoff as i64
+}
+
+/// Parse a HH:MM time into seconds since midnight.
+fn parse_time(time: &str) -> Result<i64, String> {
+    let re = Regex::new(r"^(\d{1,2}):(\d{2})$").unwrap();
+    let invalid = || format!("{} is not a HH:MM time", time);
+
+    let caps = re.captures(time.trim()).ok_or_else(invalid)?;
+    let hours: i64 = caps[1].parse().map_err(|_| invalid())?;
+    let minutes: i64 = caps[2].parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+
+    Ok(hours * 3600 + minutes * 60)
+}
+
+/// Parse an offset like UTC, Z, +02:00, +0530 or -03 into seconds.
+fn parse_offset(offset: &str) -> Result<i64, String> {
+    let offset = offset.trim();
+    if offset.eq_ignore_ascii_case("utc") || offset == "Z" {
+        return Ok(0);
+    }
+
+    let re = Regex::new(r"^([+-])(\d{2}):?(\d{2})?$").unwrap();
+    let invalid = || format!("{} is not a UTC offset", offset);
+
+    let caps = re.captures(offset).ok_or_else(invalid)?;
+    let hours: i64 = caps[2].parse().map_err(|_| invalid())?;
+    let minutes: i64 = match caps.get(3) {
+        Some(minutes) => minutes.as_str().parse().map_err(|_| invalid())?,
+        None => 0,
+    };
+    if hours > 14 || minutes > 59 {
+        return Err(invalid());
+    }
+
+    let secs = hours * 3600 + minutes * 60;
+    Ok(if &caps[1] == "-" { -secs } else { secs })
+}
@@ -0,0 +1,67 @@
+use super::*;
+
+fn window(start: &str, end: &str) -> BuildWindow {
+    BuildWindow::new(&config::BuildWindow {
+        start: start.to_owned(),
+        end: end.to_owned(),
+        utc_offset: Some("UTC".to_owned()),
+    })
+    .unwrap()
+}
+
+#[test]
+fn check_parse_time() {
+    assert_eq!(parse_time("0:00"), Ok(0));
+    assert_eq!(parse_time("18:30"), Ok(18 * 3600 + 30 * 60));
+
+    assert!(parse_time("24:00").is_err());
+    assert!(parse_time("12:60").is_err());
+    assert!(parse_time("12").is_err());
+}
+
+#[test]
+fn check_parse_offset() {
+    assert_eq!(parse_offset("UTC"), Ok(0));
+    assert_eq!(parse_offset("Z"), Ok(0));
+    assert_eq!(parse_offset("+02:00"), Ok(7200));
+    assert_eq!(parse_offset("+0530"), Ok(5 * 3600 + 30 * 60));
+    assert_eq!(parse_offset("-03"), Ok(-3 * 3600));
+
+    assert!(parse_offset("02:00").is_err());
+    assert!(parse_offset("Europe/Berlin").is_err());
+}
+
+#[test]
+fn window_within_day() {
+    let window = window("09:00", "17:00");
+    assert!(window.contains(9 * 3600));
+    assert!(window.contains(12 * 3600));
+    assert!(!window.contains(17 * 3600));
+    assert!(!window.contains(3600));
+
+    assert_eq!(window.secs_until_open(12 * 3600), 0);
+    assert_eq!(window.secs_until_open(8 * 3600), 3600);
+    assert_eq!(window.secs_until_open(18 * 3600), 15 * 3600);
+}
+
+#[test]
+fn window_over_midnight() {
+    let window = window("22:00", "6:00");
+    assert!(window.contains(23 * 3600));
+    assert!(window.contains(3600));
+    assert!(!window.contains(6 * 3600));
+    assert!(!window.contains(12 * 3600));
+
+    assert_eq!(window.secs_until_open(21 * 3600), 3600);
+    assert_eq!(window.secs_until_open(0), 0);
+}
+
+#[test]
+fn reject_empty_window() {
+    let config = config::BuildWindow {
+        start: "10:00".to_owned(),
+        end: "10:00".to_owned(),
+        utc_offset: None,
+    };
+    assert!(BuildWindow::new(&config).is_err());
+}
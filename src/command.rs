@@ -0,0 +1,31 @@
+use std::process::Output;
+
+#[cfg(test)]
+#[path = "command_test.rs"]
+mod command_test;
+
+/// Maximum length of the stderr output included in error messages.
+const MAX_STDERR_LEN: usize = 1000;
+
+/// Returns the stderr output of a command for error messages. Long
+/// outputs are cut to their end, which usually holds the actual error.
+pub fn stderr(output: &Output) -> String {
+    truncate_start(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        MAX_STDERR_LEN,
+    )
+}
+
+/// Cut s to its last max_len bytes without splitting a character.
+fn truncate_start(s: &str, max_len: usize) -> String {
+    if s.len() <= max_len {
+        return s.to_owned();
+    }
+
+    let mut start = s.len() - max_len;
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+
+    format!("...{}", &s[start..])
+}
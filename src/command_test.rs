@@ -0,0 +1,8 @@
+use super::*;
+
+#[test]
+fn check_truncate_start() {
+    assert_eq!(truncate_start("error", 10), "error");
+    assert_eq!(truncate_start("first line\nerror", 5), "...error");
+    assert_eq!(truncate_start("aäb", 2), "...b");
+}
@@ -2,75 +2,726 @@ use lib_remotebuild_rs::{config::RequestConfig, librb};
 use serde::{Deserialize, Serialize};
 use serde_yaml::from_str;
 
+use std::collections::HashMap;
+use std::env;
 use std::error;
 use std::fs;
 use std::fs::{create_dir_all, OpenOptions};
 use std::io::{self, Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+#[cfg(test)]
+#[path = "config_test.rs"]
+mod config_test;
+
 /// The defalut config path.
 pub const CONFIG_PATH: &str = "./data/";
 pub const CONFIG_FILE: &str = "config.yaml";
 
+/// Replaced with the package name in git URLs, e.g. git@host:aur/{pkg}.git
+pub const PKG_PLACEHOLDER: &str = "{pkg}";
+
+/// Environment variable overriding CONFIG_PATH.
+/// The --config-dir argument takes precedence.
+pub const CONFIG_DIR_ENV: &str = "AURTOMATIC_CONFIG_DIR";
+
+/// Environment variable holding the passphrase of the git priv_key.
+/// Takes precedence over priv_key_passphrase in the config.
+pub const PRIV_KEY_PASSPHRASE_ENV: &str = "AURTOMATIC_PRIV_KEY_PASSPHRASE";
+
+/// Environment variables overriding values of the config file, so
+/// secrets don't have to be stored on disk. A set variable takes
+/// precedence over the file, empty variables are ignored.
+pub const ENV_TELEGRAM_BOT_TOKEN: &str = "AURTOMATIC_TELEGRAM_BOT_TOKEN";
+pub const ENV_RBUILD_TOKEN: &str = "AURTOMATIC_RBUILD_TOKEN";
+pub const ENV_DMANAGER_TOKEN: &str = "AURTOMATIC_DMANAGER_TOKEN";
+pub const ENV_GIT_PRIV_KEY: &str = "AURTOMATIC_GIT_PRIV_KEY";
+pub const ENV_API_TOKEN: &str = "AURTOMATIC_API_TOKEN";
+pub const ENV_WEBHOOK_SECRET: &str = "AURTOMATIC_WEBHOOK_SECRET";
+pub const ENV_FORGE_TOKEN: &str = "AURTOMATIC_FORGE_TOKEN";
+
+/// The lockfile preventing multiple running instances.
+pub const LOCK_FILE: &str = "aurtomatic.lock";
+
+/// The default delay between two refreshes.
+pub const DEFAULT_REFRESH_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// The default amount of packages handled at the same time.
+pub const DEFAULT_MAX_CONCURRENT: usize = 10;
+
+/// The AUR used if no other one is configured.
+pub const DEFAULT_AUR_BASE_URL: &str = "https://aur.archlinux.org";
+pub const DEFAULT_AUR_RPC_URL: &str = "https://aur.archlinux.org/rpc";
+
+/// The public telegram bot API.
+pub const DEFAULT_TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+/// The default amount of attempts for telegram requests.
+pub const DEFAULT_TELEGRAM_MAX_ATTEMPTS: u32 = 5;
+
+/// The default size limit of changed binary files in bytes.
+pub const DEFAULT_MAX_CHANGED_BINARY_SIZE: u64 = 5 * 1024 * 1024;
+
+/// The default time AUR lookups are cached for.
+pub const DEFAULT_AUR_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The default age after which tmp dirs without a running
+/// build job are removed on startup.
+pub const DEFAULT_STALE_TMP_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The default time an update can run before it's reported as stuck.
+pub const DEFAULT_STUCK_BUILD_AGE: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// The default amount of refresh cycles a package is missing from the
+/// AUR before it gets reported.
+pub const DEFAULT_ORPHAN_THRESHOLD: u32 = 3;
+
+/// The default amount of retries for failed build jobs.
+pub const DEFAULT_MAX_BUILD_RETRIES: u32 = 3;
+
+/// The delay before the first retry of a failed build job.
+/// It doubles with every further retry.
+pub const BUILD_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The default maximum delay between two build job polls.
+pub const DEFAULT_BUILD_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The delay before the first build job poll.
+pub const BUILD_POLL_START: Duration = Duration::from_secs(5);
+
+/// The branch used if HEAD of a repository isn't a branch.
+pub const DEFAULT_BRANCH: &str = "master";
+
+/// Build job variables which can't be set through build_env. They
+/// control the job itself or where makepkg writes its output to.
+pub const FORBIDDEN_BUILD_ENV: &[&str] = &[
+    "REPO",
+    "DM_Token",
+    "DM_USER",
+    "DM_HOST",
+    "DM_NAMESPACE",
+    "PKGDEST",
+    "SRCDEST",
+    "SRCPKGDEST",
+    "LOGDEST",
+    "BUILDDIR",
+];
+
+/// File created to check that tmp_dir is writable.
+const TMP_PROBE_FILE: &str = ".aurtomatic_probe";
+
+/// The remote changes get pushed to if none is configured.
+pub const DEFAULT_REMOTE: &str = "origin";
+
+/// The default log level. Can be overridden with RUST_LOG.
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// The default compression extensions of package files.
+pub const DEFAULT_PACKAGE_EXTENSIONS: &[&str] = &["zst", "xz"];
+
 /// Whole config struct
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// Directory the config was loaded from. Relative
+    /// paths like priv_key are resolved against it.
+    #[serde(skip)]
+    pub config_dir: PathBuf,
     pub repo_dir: String,
     pub tmp_dir: String,
+    pub out_dir: Option<String>,
+    /// Directory keeping persistent checkouts of the package repositories.
+    /// Updates fetch into them instead of cloning from zero.
+    pub cache_dir: Option<String>,
     pub rbuild: TokenConfig,
     pub dmanager: TokenConfig,
     pub git: Git,
+    /// Package names to ignore. Entries containing '*' or
+    /// '?' are matched as glob patterns.
     pub ignore_packages: Option<Vec<String>>,
+    /// Regex patterns of package names to ignore.
+    pub ignore_patterns: Option<Vec<String>>,
+    /// Only manage the listed packages and skip the ignore rules.
+    /// An empty list manages nothing, all packages are managed if unset.
+    pub only_packages: Option<Vec<String>>,
+    /// Base URL of the AUR git repositories. Empty for the real AUR.
+    #[serde(default)]
+    pub aur_base_url: String,
+    /// URL of the AUR RPC interface. Empty for the real AUR.
+    #[serde(default)]
+    pub aur_rpc_url: String,
+    /// How long AUR lookups are reused. Triggered refreshes
+    /// always look up the AUR. 0 disables the cache.
+    #[serde(default = "default_aur_cache_ttl", with = "human_duration")]
+    pub aur_cache_ttl: Duration,
+    /// Refresh cycles in a row a package has to be missing from
+    /// the AUR to get reported as orphaned. 0 disables the report.
+    #[serde(default = "default_orphan_threshold")]
+    pub orphan_threshold: u32,
+    /// Compression extensions of the package files in repo_dir.
+    /// Files are matched by their full .pkg.tar.<ext> suffix.
+    #[serde(default = "default_package_extensions")]
+    pub package_extensions: Vec<String>,
+    #[serde(with = "human_duration")]
     pub refresh_delay: Duration,
+    /// Amount of packages handled at the same time. A value
+    /// of 1 processes all packages sequentially.
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+    #[serde(default)]
     pub telegram: Telegram,
+    #[serde(default)]
+    pub notifications: Notifications,
     pub disable_pkgcheck: bool,
+    /// PKGBUILD variables allowed to change in addition to the defaults.
+    pub allowed_pkgbuild_keys: Option<Vec<String>>,
+    /// Use allowed_pkgbuild_keys instead of the defaults.
+    #[serde(default)]
+    pub replace_default_pkgbuild_keys: bool,
+    /// .install files allowed to change, by package name.
+    pub allowed_install_files: Option<HashMap<String, Vec<String>>>,
+    /// Newest version allowed to be built, by package name.
+    pub version_pins: Option<HashMap<String, String>>,
+    /// Directory of <pkgname>.yaml files overriding the
+    /// check settings of single packages.
+    pub package_configs: Option<String>,
+    /// Regex patterns added lines must not match. Replaces the defaults.
+    pub suspicious_patterns: Option<Vec<String>>,
+    /// Mime prefixes of files which are diffed line by line. Replaces the defaults.
+    pub utf8_mimes: Option<Vec<String>>,
+    /// Mime prefixes of binary files allowed to change. Replaces the defaults.
+    pub allowed_mimes: Option<Vec<String>>,
+    /// PKGBUILD variables which must not be removed. Replaces the
+    /// defaults, which are the checksums and validpgpkeys.
+    pub reject_removal_of: Option<Vec<String>>,
+    /// Size limit in bytes of binary files allowed to change. 0 disables the limit.
+    #[serde(default = "default_max_changed_binary_size")]
+    pub max_changed_binary_size: u64,
+    /// Lines of context of the unified diff logged and sent for
+    /// the offending file of rejected updates. Unset disables it.
+    pub rejected_diff_context: Option<usize>,
+    /// Download the sources and verify them against their checksums
+    /// before building, once an update got approved. Dry runs don't.
+    /// Sources fetched over plain HTTP without a checksum are rejected.
+    #[serde(default)]
+    pub verify_sources: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Send readiness and watchdog notifications to systemd.
+    /// Requires the systemd feature.
+    #[serde(default)]
+    pub systemd_notify: bool,
+    /// Port serving prometheus metrics on /metrics.
+    /// Requires the metrics feature.
+    pub metrics_port: Option<u16>,
+    /// Port serving the JSON status API. Requires the api feature.
+    pub api_port: Option<u16>,
+    /// Bearer token required to trigger a refresh through the API.
+    #[serde(default)]
+    pub api_token: String,
+    /// File containing the api_token, e.g. a mounted secret.
+    pub api_token_file: Option<String>,
+    /// Port of the webhook triggering refreshes. Requires the webhook feature.
+    pub webhook_port: Option<u16>,
+    /// Shared secret webhook requests have to send.
+    #[serde(default)]
+    pub webhook_secret: String,
+    /// File containing the webhook_secret, e.g. a mounted secret.
+    pub webhook_secret_file: Option<String>,
+    /// One of off, error, warn, info, debug or trace.
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// Maximum delay between two polls of a build job's status.
+    #[serde(default = "default_build_poll_interval", with = "human_duration")]
+    pub build_poll_interval: Duration,
+    /// Cancel build jobs running longer than this. 0 disables the timeout.
+    #[serde(default, with = "human_duration")]
+    pub build_timeout: Duration,
+    /// Age after which interrupted updates whose build job isn't
+    /// running anymore are given up on startup. 0 keeps them.
+    #[serde(default = "default_stale_tmp_age", with = "human_duration")]
+    pub stale_tmp_age: Duration,
+    /// Warn once about an update still running after this, as its
+    /// package doesn't get updated meanwhile. 0 disables the warning.
+    #[serde(default = "default_stuck_build_age", with = "human_duration")]
+    pub stuck_build_age: Duration,
+    /// dmanager namespace build artifacts are uploaded to. Empty
+    /// uses its default namespace. Package configs can override it.
+    #[serde(default)]
+    pub upload_namespace: String,
+    /// Environment variables of the build jobs, e.g. MAKEFLAGS or
+    /// PACKAGER. Package configs can add to and override them. The
+    /// FORBIDDEN_BUILD_ENV variables are rejected.
+    #[serde(default)]
+    pub build_env: HashMap<String, String>,
+    /// How often a failed build job gets recreated.
+    #[serde(default = "default_max_build_retries")]
+    pub max_build_retries: u32,
+    #[serde(default)]
+    pub require_approval: bool,
+    #[serde(default, with = "human_duration")]
+    pub approval_timeout: Duration,
+    pub signing: Option<Signing>,
+    /// Push updates to a branch per update and open a pull or merge
+    /// request for it. Updates are pushed to the branch directly if unset.
+    pub forge: Option<Forge>,
+    /// pacman repository database built packages get added to.
+    /// Requires out_dir, unset if the database is managed elsewhere.
+    pub repo_db: Option<RepoDb>,
+    /// Versions of a package kept in repo_dir after an upgrade. Older
+    /// ones get deleted with their signatures. Unset keeps all versions.
+    pub keep_versions: Option<usize>,
+    /// Daily time span build jobs are created in. Updates found
+    /// outside of it wait until it opens. Unset builds any time.
+    pub build_window: Option<BuildWindow>,
+    /// Packages built first, in the given order. All
+    /// other packages follow in the order they're found.
+    #[serde(default)]
+    pub build_priority: Vec<String>,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Telegram {
     pub bot_token: String,
+    /// File containing the bot_token, e.g. a mounted secret.
+    pub bot_token_file: Option<String>,
+    /// The user allowed to send commands and approve updates.
     pub user_id: u64,
+    /// Chats notifications are sent to. Empty for user_id only.
+    #[serde(default)]
+    pub chat_ids: Vec<i64>,
     pub startup_message: bool,
+    /// Base URL of a self-hosted bot API server. Empty for the public one.
+    #[serde(default)]
+    pub api_base_url: String,
+    /// How often a failed bot API request is tried.
+    #[serde(default = "default_telegram_max_attempts")]
+    pub max_attempts: u32,
+}
+
+/// Selects where notifications are sent to.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct Notifications {
+    pub backend: Backend,
+    #[serde(default)]
+    pub mode: NotificationMode,
+    pub discord: Option<Discord>,
+    pub email: Option<Email>,
+}
+
+/// Available notification backends.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Telegram,
+    Discord,
+    Email,
+}
+
+// Deriving requires #[default], which isn't available on older compilers.
+#[allow(clippy::derivable_impls)]
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Telegram
+    }
+}
+
+/// When notifications are sent.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationMode {
+    /// One message per built package or error.
+    Events,
+    /// One message per refresh cycle, only sent if
+    /// anything got updated, rejected or failed.
+    Summary,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for NotificationMode {
+    fn default() -> Self {
+        NotificationMode::Events
+    }
 }
 
-/// Git upstream for custom repository.
 #[derive(Default, Debug, Serialize, Deserialize)]
+pub struct Discord {
+    pub webhook_url: String,
+}
+
+/// SMTP configuration for email notifications.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Email {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    /// File containing the password, e.g. a mounted secret.
+    pub password_file: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+impl Email {
+    fn is_empty(&self) -> bool {
+        self.host.is_empty()
+            || self.port == 0
+            || self.username.is_empty()
+            || self.password.is_empty()
+            || self.from.is_empty()
+            || self.to.is_empty()
+    }
+}
+
+/// Git upstream for custom repository.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Git {
     pub bot_name: String,
     pub bot_email: String,
 
+    /// Base URL of the package repositories, e.g. https://host/base,
+    /// ssh://host/base or git@host:base. The package name is appended
+    /// as path segment unless the URL contains a PKG_PLACEHOLDER.
     pub url: String,
+    /// SSH key used if the ssh-agent can't authenticate.
+    /// Only the agent is used if empty.
+    #[serde(default)]
     pub priv_key: String,
+    pub priv_key_passphrase: Option<String>,
+    /// Branch to build from and push to. The default
+    /// branch of the repository is used if not set.
+    pub branch: Option<String>,
+    /// Name of the remote of the cloned repositories
+    /// changes are pushed to. Defaults to origin.
+    pub remote_name: Option<String>,
+    /// Author of the commits. bot_name and bot_email are used if not
+    /// set, they always are the committer.
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    /// GPG key signing the commits. Commits are unsigned if not set.
+    pub signing_key: Option<String>,
 }
 
-/// RemoteBuild configuration.
+/// Package signing configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct Signing {
+    pub key_id: String,
+    pub gpg_path: Option<String>,
+}
+
+/// Forge opening pull or merge requests for updates.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Forge {
+    pub kind: ForgeKind,
+    /// Base URL of the API. Empty for github.com or gitlab.com.
+    #[serde(default)]
+    pub api_url: String,
+    #[serde(default)]
+    pub token: String,
+    /// File containing the token, e.g. a mounted secret.
+    pub token_file: Option<String>,
+    /// Path of a package's repository on the forge, e.g. owner/{pkg}.
+    /// The package name replaces a PKG_PLACEHOLDER.
+    pub repository: String,
+}
+
+/// Supported forges.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+}
+
+/// pacman repository database configuration.
 #[derive(Default, Debug, Serialize, Deserialize)]
+pub struct RepoDb {
+    /// Name of the repository. The database is <out_dir>/<name>.db.tar.zst.
+    pub name: String,
+    /// Sign the database with the signing key.
+    #[serde(default)]
+    pub sign: bool,
+}
+
+/// Build window configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct BuildWindow {
+    /// HH:MM time the window opens at.
+    pub start: String,
+    /// HH:MM time the window closes at. Earlier than
+    /// start for windows spanning midnight.
+    pub end: String,
+    /// Timezone of the times as UTC offset, e.g. +02:00.
+    /// The local timezone is used if unset.
+    pub utc_offset: Option<String>,
+}
+
+/// RemoteBuild configuration.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct TokenConfig {
     pub user_name: String,
     pub token: String,
+    /// File containing the token, e.g. a mounted secret.
+    pub token_file: Option<String>,
     pub url: String,
 }
 
-impl TokenConfig {
+fn default_max_concurrent() -> usize {
+    DEFAULT_MAX_CONCURRENT
+}
+
+fn default_aur_cache_ttl() -> Duration {
+    DEFAULT_AUR_CACHE_TTL
+}
+
+fn default_stale_tmp_age() -> Duration {
+    DEFAULT_STALE_TMP_AGE
+}
+
+fn default_stuck_build_age() -> Duration {
+    DEFAULT_STUCK_BUILD_AGE
+}
+
+fn default_orphan_threshold() -> u32 {
+    DEFAULT_ORPHAN_THRESHOLD
+}
+
+fn default_build_poll_interval() -> Duration {
+    DEFAULT_BUILD_POLL_INTERVAL
+}
+
+fn default_telegram_max_attempts() -> u32 {
+    DEFAULT_TELEGRAM_MAX_ATTEMPTS
+}
+
+fn default_max_changed_binary_size() -> u64 {
+    DEFAULT_MAX_CHANGED_BINARY_SIZE
+}
+
+fn default_max_build_retries() -> u32 {
+    DEFAULT_MAX_BUILD_RETRIES
+}
+
+fn default_log_level() -> String {
+    DEFAULT_LOG_LEVEL.to_owned()
+}
+
+fn default_package_extensions() -> Vec<String> {
+    DEFAULT_PACKAGE_EXTENSIONS
+        .iter()
+        .map(|i| (*i).to_owned())
+        .collect()
+}
+
+impl Telegram {
     fn is_empty(&self) -> bool {
-        self.user_name.is_empty() || self.token.is_empty() || self.url.is_empty()
+        self.bot_token.is_empty() || self.user_id == 0
+    }
+
+    /// Returns the base URL of the bot API.
+    pub fn api_base_url(&self) -> &str {
+        if self.api_base_url.is_empty() {
+            DEFAULT_TELEGRAM_API_URL
+        } else {
+            self.api_base_url.as_str()
+        }
     }
 }
 
 impl Git {
-    fn is_empty(&self) -> bool {
-        self.url.is_empty()
-            || self.bot_email.is_empty()
-            || self.bot_name.is_empty()
-            || self.priv_key.is_empty()
+    /// Returns the name of the remote changes are pushed to.
+    pub fn remote_name(&self) -> &str {
+        self.remote_name.as_deref().unwrap_or(DEFAULT_REMOTE)
+    }
+
+    /// Returns the name and email of the commit author.
+    pub fn author(&self) -> (&str, &str) {
+        (
+            self.author_name.as_deref().unwrap_or(&self.bot_name),
+            self.author_email.as_deref().unwrap_or(&self.bot_email),
+        )
+    }
+
+    /// Passphrase of priv_key, read from PRIV_KEY_PASSPHRASE_ENV or the config.
+    pub fn passphrase(&self) -> Option<String> {
+        env::var(PRIV_KEY_PASSPHRASE_ENV)
+            .ok()
+            .or_else(|| self.priv_key_passphrase.clone())
+    }
+
+    /// Read the private key used to authenticate against the git server.
+    pub fn read_priv_key(&self, config_dir: &Path) -> Result<String, io::Error> {
+        fs::read_to_string(config_dir.join(&self.priv_key))
+    }
+
+    /// Check that priv_key can be read and holds a PEM or OpenSSH private key.
+    fn check_priv_key(&self, config_dir: &Path) -> Result<(), io::Error> {
+        if self.priv_key.is_empty() {
+            return Ok(());
+        }
+
+        let key = self.read_priv_key(config_dir).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("can't read priv_key {}: {}", self.priv_key, e),
+            )
+        })?;
+
+        if !is_private_key(&key) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("priv_key {} is not a private key", self.priv_key),
+            ));
+        }
+
+        Ok(())
     }
 }
 
+/// Returns the config directory passed with --config-dir,
+/// set in CONFIG_DIR_ENV or CONFIG_PATH otherwise.
+pub fn config_dir<I: Iterator<Item = String>>(args: I, env_dir: Option<String>) -> PathBuf {
+    let mut args = args.skip_while(|i| i != "--config-dir" && !i.starts_with("--config-dir="));
+
+    let arg = args
+        .next()
+        .and_then(|i| match i.strip_prefix("--config-dir=") {
+            Some(dir) => Some(dir.to_owned()),
+            None => args.next(),
+        });
+
+    arg.or(env_dir)
+        .filter(|i| !i.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(CONFIG_PATH))
+}
+
+/// Check that url is a URL like https://host/path, an SCP-like
+/// SSH remote like git@host:path or an absolute local path.
+fn check_git_url(url: &str) -> Result<(), String> {
+    if url.starts_with('/') {
+        return Ok(());
+    }
+
+    if url.contains("://") {
+        let parsed =
+            reqwest::Url::parse(url).map_err(|e| format!("{} is not a URL: {}", url, e))?;
+
+        return match parsed.scheme() {
+            "http" | "https" | "ssh" | "git" if parsed.host_str().is_none() => {
+                Err(format!("{} has no host", url))
+            }
+            "http" | "https" | "ssh" | "git" | "file" => Ok(()),
+            scheme => Err(format!("{} has the unsupported scheme {}", url, scheme)),
+        };
+    }
+
+    // SCP-like syntax: [user@]host:path
+    match url.find(':') {
+        Some(pos) => {
+            let host = url[..pos].rsplit('@').next().unwrap_or_default();
+            if host.is_empty() || host.contains('/') || url[pos + 1..].is_empty() {
+                Err(format!("{} is not of the form user@host:path", url))
+            } else {
+                Ok(())
+            }
+        }
+        None => Err(format!(
+            "{} is neither a URL nor of the form user@host:path",
+            url
+        )),
+    }
+}
+
+/// Append name to the path of a git URL. URLs and SCP-like remotes
+/// are treated as strings, neither Path::join nor Url::join keep
+/// the host:path syntax intact.
+fn join_git_url(base: &str, name: &str) -> String {
+    if base.contains(PKG_PLACEHOLDER) {
+        return base.replace(PKG_PLACEHOLDER, name);
+    }
+
+    let base = base.trim_end_matches('/');
+    if base.ends_with(':') {
+        // git@host: refers to the home directory of the user
+        format!("{}{}", base, name)
+    } else {
+        format!("{}/{}", base, name)
+    }
+}
+
+/// Returns the FORBIDDEN_BUILD_ENV variables set in env.
+pub fn forbidden_build_env(env: &HashMap<String, String>) -> Vec<&str> {
+    let mut forbidden: Vec<&str> = env
+        .keys()
+        .map(|i| i.as_str())
+        .filter(|i| FORBIDDEN_BUILD_ENV.contains(i))
+        .collect();
+    forbidden.sort_unstable();
+    forbidden
+}
+
+/// Empty mime prefixes would match every file.
+fn has_empty_entry(list: &Option<Vec<String>>) -> bool {
+    matches!(list, Some(ref l) if l.iter().any(|i| i.trim().is_empty()))
+}
+
+/// Canonicalize path, which may not exist yet. Missing
+/// components are appended to their existing parent.
+fn resolve_path(path: &Path) -> Result<PathBuf, io::Error> {
+    match fs::canonicalize(path) {
+        Ok(path) => Ok(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => {
+                let parent = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+                Ok(resolve_path(parent)?.join(name))
+            }
+            _ => Err(e),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Read a secret from the file at path, ignoring trailing newlines.
+fn read_secret(path: &str) -> Result<String, Box<dyn error::Error>> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("can't read secret file {}: {}", path, e))?;
+
+    let secret = content.trim_end_matches(&['\n', '\r'][..]);
+    if secret.is_empty() {
+        return Err(format!("secret file {} is empty", path).into());
+    }
+
+    Ok(secret.to_owned())
+}
+
+/// Look up an executable in PATH, like `which` does.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Check if key looks like a PEM encoded or OpenSSH private key.
+fn is_private_key(key: &str) -> bool {
+    let mut lines = key.trim().lines();
+    let first = lines.next().unwrap_or_default();
+    let last = lines.last().unwrap_or_default();
+
+    first.starts_with("-----BEGIN ")
+        && first.ends_with("PRIVATE KEY-----")
+        && last == first.replacen("BEGIN", "END", 1)
+}
+
 impl Config {
-    /// Create and return a new config.
-    pub fn new() -> Result<(Self, bool), Box<dyn error::Error>> {
-        let path = Path::new(&CONFIG_PATH).join(&CONFIG_FILE);
+    /// Create and return a new config from config_dir.
+    pub fn new(config_dir: &Path) -> Result<(Self, bool), Box<dyn error::Error>> {
+        let path = config_dir.join(CONFIG_FILE);
 
         if path.parent().is_some() && !path.parent().unwrap().exists() {
             create_dir_all(path.parent().unwrap())?;
@@ -84,7 +735,24 @@ impl Config {
         if path.exists() {
             oo.read(true).open(path)?.read_to_string(&mut config_str)?;
         } else {
-            config_str = serde_yaml::to_string(&Config::default())?;
+            config_str = serde_yaml::to_string(&Config {
+                max_concurrent: DEFAULT_MAX_CONCURRENT,
+                package_extensions: default_package_extensions(),
+                log_level: default_log_level(),
+                max_build_retries: DEFAULT_MAX_BUILD_RETRIES,
+                max_changed_binary_size: DEFAULT_MAX_CHANGED_BINARY_SIZE,
+                build_poll_interval: DEFAULT_BUILD_POLL_INTERVAL,
+                aur_cache_ttl: DEFAULT_AUR_CACHE_TTL,
+                orphan_threshold: DEFAULT_ORPHAN_THRESHOLD,
+                stale_tmp_age: DEFAULT_STALE_TMP_AGE,
+                stuck_build_age: DEFAULT_STUCK_BUILD_AGE,
+                refresh_delay: DEFAULT_REFRESH_DELAY,
+                telegram: Telegram {
+                    max_attempts: DEFAULT_TELEGRAM_MAX_ATTEMPTS,
+                    ..Telegram::default()
+                },
+                ..Config::default()
+            })?;
             oo.create(true)
                 .write(true)
                 .open(path)?
@@ -92,18 +760,316 @@ impl Config {
             just_created = true;
         }
 
-        Ok((from_str(&config_str)?, just_created))
+        let mut config: Config = from_str(&config_str)?;
+        config.config_dir = config_dir.to_owned();
+        config.read_secret_files()?;
+        config.apply_env(|name| env::var(name).ok());
+
+        Ok((config, just_created))
+    }
+
+    /// Replace secrets with the content of their *_file fields.
+    /// Environment variables still take precedence.
+    fn read_secret_files(&mut self) -> Result<(), Box<dyn error::Error>> {
+        let mut secrets = vec![
+            (&self.telegram.bot_token_file, &mut self.telegram.bot_token),
+            (&self.rbuild.token_file, &mut self.rbuild.token),
+            (&self.dmanager.token_file, &mut self.dmanager.token),
+            (&self.api_token_file, &mut self.api_token),
+            (&self.webhook_secret_file, &mut self.webhook_secret),
+        ];
+        if let Some(ref mut forge) = self.forge {
+            secrets.push((&forge.token_file, &mut forge.token));
+        }
+        if let Some(ref mut email) = self.notifications.email {
+            secrets.push((&email.password_file, &mut email.password));
+        }
+
+        for (path, value) in secrets.iter_mut() {
+            if let Some(path) = path {
+                **value = read_secret(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Overlay the values of the ENV_* variables looked up by var.
+    fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) {
+        let mut overrides = [
+            (ENV_TELEGRAM_BOT_TOKEN, &mut self.telegram.bot_token),
+            (ENV_RBUILD_TOKEN, &mut self.rbuild.token),
+            (ENV_DMANAGER_TOKEN, &mut self.dmanager.token),
+            (ENV_GIT_PRIV_KEY, &mut self.git.priv_key),
+            (ENV_API_TOKEN, &mut self.api_token),
+            (ENV_WEBHOOK_SECRET, &mut self.webhook_secret),
+        ];
+
+        for (name, value) in overrides.iter_mut() {
+            if let Some(v) = var(name).filter(|i| !i.is_empty()) {
+                **value = v;
+            }
+        }
+
+        if let Some(ref mut forge) = self.forge {
+            if let Some(v) = var(ENV_FORGE_TOKEN).filter(|i| !i.is_empty()) {
+                forge.token = v;
+            }
+        }
     }
 
     /// Check if config is set up completely.
-    pub fn need_adjustment(&self) -> bool {
-        self.repo_dir.is_empty()
-            || self.tmp_dir.is_empty()
-            || self.rbuild.is_empty()
-            || self.dmanager.is_empty()
-            || self.git.is_empty()
-            || self.telegram.bot_token.is_empty()
-            || self.telegram.user_id == 0
+    pub fn is_ok(&self) -> bool {
+        self.need_adjustment().is_empty()
+    }
+
+    /// Returns the names of the options which are unset or invalid.
+    pub fn need_adjustment(&self) -> Vec<&'static str> {
+        let checks = [
+            ("repo_dir", self.repo_dir.is_empty()),
+            ("tmp_dir", self.tmp_dir.is_empty()),
+            ("rbuild.user_name", self.rbuild.user_name.is_empty()),
+            ("rbuild.token", self.rbuild.token.is_empty()),
+            ("rbuild.url", self.rbuild.url.is_empty()),
+            ("dmanager.user_name", self.dmanager.user_name.is_empty()),
+            ("dmanager.token", self.dmanager.token.is_empty()),
+            ("dmanager.url", self.dmanager.url.is_empty()),
+            ("git.bot_name", self.git.bot_name.is_empty()),
+            ("git.bot_email", self.git.bot_email.is_empty()),
+            ("git.url", self.git.url.is_empty()),
+            (
+                "telegram",
+                self.needs_telegram() && self.telegram.is_empty(),
+            ),
+            (
+                "telegram.api_base_url",
+                reqwest::Url::parse(self.telegram.api_base_url()).is_err(),
+            ),
+            (
+                "notifications.discord",
+                self.notifications.backend == Backend::Discord
+                    && !matches!(self.notifications.discord, Some(ref d) if !d.webhook_url.is_empty()),
+            ),
+            (
+                "notifications.email",
+                self.notifications.backend == Backend::Email
+                    && !matches!(self.notifications.email, Some(ref e) if !e.is_empty()),
+            ),
+            ("max_concurrent", self.max_concurrent < 1),
+            ("package_extensions", self.package_extensions.is_empty()),
+            (
+                "log_level",
+                self.log_level.parse::<log::LevelFilter>().is_err(),
+            ),
+            (
+                "approval_timeout",
+                self.require_approval && self.approval_timeout.as_secs() == 0,
+            ),
+            (
+                "build_poll_interval",
+                self.build_poll_interval.as_secs() == 0,
+            ),
+            (
+                "signing",
+                matches!(self.signing, Some(ref s) if s.key_id.is_empty()),
+            ),
+            (
+                "git.signing_key",
+                matches!(self.git.signing_key, Some(ref key) if key.is_empty()),
+            ),
+            (
+                "git.remote_name",
+                matches!(self.git.remote_name, Some(ref name) if name.is_empty()),
+            ),
+            (
+                "forge",
+                matches!(self.forge, Some(ref f) if f.token.is_empty() || f.repository.is_empty()),
+            ),
+            (
+                "repo_db",
+                matches!(self.repo_db, Some(ref db) if db.name.is_empty()
+                    || self.out_dir.is_none()
+                    || (db.sign && self.signing.is_none())),
+            ),
+            ("keep_versions", self.keep_versions == Some(0)),
+            (
+                "build_env",
+                !forbidden_build_env(&self.build_env).is_empty(),
+            ),
+            (
+                "api_token",
+                self.api_port.is_some() && self.api_token.is_empty(),
+            ),
+            (
+                "webhook_secret",
+                self.webhook_port.is_some() && self.webhook_secret.is_empty(),
+            ),
+            ("utf8_mimes", has_empty_entry(&self.utf8_mimes)),
+            ("allowed_mimes", has_empty_entry(&self.allowed_mimes)),
+        ];
+
+        checks
+            .iter()
+            .filter(|(_, invalid)| *invalid)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// Check the files and tools the config refers to without
+    /// creating anything. Returns a description of each problem.
+    pub fn check_paths(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !Path::new(&self.repo_dir).is_dir() {
+            problems.push(format!("repo_dir {} is not a directory", self.repo_dir));
+        }
+
+        if let Some(ref dir) = self.package_configs {
+            if !Path::new(dir).is_dir() {
+                problems.push(format!("package_configs {} is not a directory", dir));
+            }
+        }
+
+        if let Err(e) = self.git.check_priv_key(&self.config_dir) {
+            problems.push(e.to_string());
+        }
+
+        let mut tools = vec!["makepkg"];
+        if self.repo_db.is_some() {
+            tools.push("repo-add");
+        }
+        for tool in tools {
+            if find_in_path(tool).is_none() {
+                problems.push(format!("{} not found in PATH", tool));
+            }
+        }
+
+        problems
+    }
+
+    /// Check that the git and AUR URLs are well-formed.
+    pub fn check_urls(&self) -> Result<(), String> {
+        check_git_url(&self.custom_git_url("pkg")).map_err(|e| format!("git.url: {}", e))?;
+
+        if !self.aur_base_url.is_empty() {
+            check_git_url(&self.aur_base_url).map_err(|e| format!("aur_base_url: {}", e))?;
+        }
+
+        reqwest::Url::parse(self.aur_rpc_url())
+            .map_err(|e| format!("aur_rpc_url: {} is not a URL: {}", self.aur_rpc_url(), e))?;
+
+        Ok(())
+    }
+
+    /// Returns the git URL of a package in the custom repository.
+    pub fn custom_git_url(&self, pkg_name: &str) -> String {
+        join_git_url(&self.git.url, pkg_name)
+    }
+
+    /// Returns the git URL of an AUR package.
+    pub fn aur_git_url(&self, pkg_name: &str) -> String {
+        let base = if self.aur_base_url.is_empty() {
+            DEFAULT_AUR_BASE_URL
+        } else {
+            self.aur_base_url.as_str()
+        };
+
+        format!("{}/{}.git", base.trim_end_matches('/'), pkg_name)
+    }
+
+    /// Returns the path of the repository database if repo-add is enabled.
+    pub fn repo_db_path(&self) -> Option<PathBuf> {
+        match (&self.repo_db, &self.out_dir) {
+            (Some(db), Some(out_dir)) => {
+                Some(Path::new(out_dir).join(format!("{}.db.tar.zst", db.name)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the build job variables of a package with
+    /// overrides from its package config.
+    pub fn build_env(&self, overrides: &HashMap<String, String>) -> HashMap<String, String> {
+        let mut env = self.build_env.clone();
+        env.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+        env
+    }
+
+    /// Returns the sort key of a package in build order.
+    /// Packages without priority share the lowest.
+    pub fn build_priority(&self, pkg_name: &str) -> usize {
+        self.build_priority
+            .iter()
+            .position(|i| i == pkg_name)
+            .unwrap_or(self.build_priority.len())
+    }
+
+    /// Returns the URL of the AUR RPC interface.
+    pub fn aur_rpc_url(&self) -> &str {
+        if self.aur_rpc_url.is_empty() {
+            DEFAULT_AUR_RPC_URL
+        } else {
+            self.aur_rpc_url.as_str()
+        }
+    }
+
+    /// Check if a feature depending on telegram is enabled.
+    fn needs_telegram(&self) -> bool {
+        self.notifications.backend == Backend::Telegram || self.require_approval
+    }
+
+    /// Check that repo_dir can be read and tmp_dir, which has to be a
+    /// different directory, can be written to. Both have to exist.
+    fn check_dirs(&self) -> Result<(), io::Error> {
+        let with_context = |what: &str, dir: &str| {
+            let message = format!("{} {}", what, dir);
+            move |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", message, e))
+        };
+
+        fs::read_dir(&self.repo_dir)
+            .map_err(with_context("can't read repo_dir", &self.repo_dir))?;
+
+        // Packages would be built inside of the scanned directory
+        let repo_dir = fs::canonicalize(&self.repo_dir)?;
+        let tmp_dir = fs::canonicalize(&self.tmp_dir)?;
+        let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        if repo_dir == tmp_dir {
+            return invalid(format!(
+                "repo_dir and tmp_dir have to be different directories: {}",
+                repo_dir.display()
+            ));
+        }
+        if tmp_dir.starts_with(&repo_dir) {
+            return invalid(format!(
+                "tmp_dir must not be inside of repo_dir: {}",
+                tmp_dir.display()
+            ));
+        }
+
+        // Entries of the tmp dir are removed at startup
+        let nested = [
+            ("repo_dir", Some(&self.repo_dir)),
+            ("out_dir", self.out_dir.as_ref()),
+            ("cache_dir", self.cache_dir.as_ref()),
+        ];
+        for (name, dir) in nested.iter() {
+            let dir = match dir {
+                Some(dir) => resolve_path(Path::new(dir))?,
+                None => continue,
+            };
+            if dir.starts_with(&tmp_dir) {
+                return invalid(format!(
+                    "{} must not be inside of tmp_dir: {}",
+                    name,
+                    dir.display()
+                ));
+            }
+        }
+
+        let probe = tmp_dir.join(TMP_PROBE_FILE);
+        fs::write(&probe, b"")
+            .and_then(|_| fs::remove_file(&probe))
+            .map_err(with_context("can't write to tmp_dir", &self.tmp_dir))
     }
 
     /// Create all files needed for a working environment.
@@ -112,6 +1078,31 @@ impl Config {
         if !tmp_path.exists() {
             fs::create_dir(tmp_path)?;
         }
+        self.check_dirs()?;
+
+        if let Some(ref out_dir) = self.out_dir {
+            create_dir_all(out_dir)?;
+        }
+
+        if let Some(ref cache_dir) = self.cache_dir {
+            create_dir_all(cache_dir)?;
+        }
+
+        self.git.check_priv_key(&self.config_dir)?;
+
+        if find_in_path("makepkg").is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "makepkg not found in PATH, install pacman to regenerate .SRCINFO files",
+            ));
+        }
+
+        if self.repo_db.is_some() && find_in_path("repo-add").is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "repo-add not found in PATH, install pacman or unset repo_db",
+            ));
+        }
 
         Ok(())
     }
@@ -126,3 +1117,29 @@ impl Config {
         })
     }
 }
+
+/// (De)serialize durations as human readable strings like "30m"
+/// or "6h". The old secs/nanos form is still accepted.
+mod human_duration {
+    use serde::de::{Deserializer, Error};
+    use serde::{Deserialize, Serializer};
+    use std::time::Duration;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Human(String),
+        Struct(Duration),
+    }
+
+    pub fn serialize<S: Serializer>(d: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&humantime::format_duration(*d).to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        match Repr::deserialize(deserializer)? {
+            Repr::Human(s) => humantime::parse_duration(&s).map_err(D::Error::custom),
+            Repr::Struct(d) => Ok(d),
+        }
+    }
+}
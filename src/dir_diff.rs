@@ -1,23 +1,54 @@
 extern crate walkdir;
 
 use std::cmp::Ordering;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
 
 use walkdir::{DirEntry, WalkDir};
 
+#[cfg(test)]
+#[path = "dir_diff_test.rs"]
+mod dir_diff_test;
+
 /// The various errors that can happen when diffing two directories
 #[derive(Debug)]
+#[allow(dead_code)]
 pub enum Error {
     Io(std::io::Error),
     StripPrefix(std::path::StripPrefixError),
     WalkDir(walkdir::Error),
 }
 
+/// Where a difference between two directories was found.
 #[derive(Debug, PartialEq)]
 pub enum Site {
+    /// The entry only exists in the left directory.
     Left,
+    /// The entry only exists in the right directory.
     Right,
-    Unknown,
+    /// The entry exists in both directories but with a different type.
+    Mismatch,
+    /// The entry is a symlink on at least one site and
+    /// the link targets differ.
+    SymlinkMismatch,
+}
+
+/// A single difference between two directories.
+#[derive(Debug, PartialEq)]
+pub struct DirDiffEntry {
+    /// Path relative to the base directories.
+    pub path: PathBuf,
+    pub site: Site,
+}
+
+/// An entry found in the left, the right or both directories.
+#[derive(Debug)]
+pub struct Pair {
+    /// Path relative to the base directories.
+    pub path: PathBuf,
+    pub left: Option<DirEntry>,
+    pub right: Option<DirEntry>,
 }
 
 pub fn git_filter_entries(f: &DirEntry) -> bool {
@@ -26,35 +57,101 @@ pub fn git_filter_entries(f: &DirEntry) -> bool {
         || f.file_name() == ".SRCINFO")
 }
 
-/// Check if directories are different. On difference detected,
-/// return the site which caused the difference. This
-/// only applies to additional files.
+/// Check if directories are different. Returns an entry for
+/// each path which only exists on one site or whose file type
+/// differs. Equal directories result in an empty list.
 pub fn is_different<A: AsRef<Path>, B: AsRef<Path>>(
     a_base: A,
     b_base: B,
-) -> Result<Option<Site>, Error> {
-    let mut a_walker = walk_dir(a_base)?.filter_entry(git_filter_entries);
-    let mut b_walker = walk_dir(b_base)?.filter_entry(git_filter_entries);
-
-    for (a, b) in (&mut a_walker).zip(&mut b_walker) {
-        let a = a?;
-        let b = b?;
-
-        if a.depth() != b.depth()
-            || a.file_type() != b.file_type()
-            || a.file_name() != b.file_name()
-        {
-            return Ok(Some(Site::Unknown));
-        }
+) -> Result<Vec<DirDiffEntry>, Error> {
+    let differences = pair_entries(a_base, b_base)?
+        .into_iter()
+        .filter_map(|pair| {
+            let site = match (&pair.left, &pair.right) {
+                (Some(_), None) => Site::Left,
+                (None, Some(_)) => Site::Right,
+                (Some(a), Some(b)) if a.path_is_symlink() || b.path_is_symlink() => {
+                    if link_target(a) == link_target(b) {
+                        return None;
+                    }
+                    Site::SymlinkMismatch
+                }
+                (Some(a), Some(b)) if a.file_type() != b.file_type() => Site::Mismatch,
+                _ => return None,
+            };
+
+            Some(DirDiffEntry {
+                path: pair.path,
+                site,
+            })
+        })
+        .collect();
+
+    Ok(differences)
+}
+
+/// Returns the target of entry if it is a symlink.
+pub fn link_target(entry: &DirEntry) -> Option<PathBuf> {
+    if !entry.path_is_symlink() {
+        return None;
     }
+    fs::read_link(entry.path()).ok()
+}
 
-    if a_walker.next().is_some() {
-        return Ok(Some(Site::Left));
+/// Check if a symlink at the relative path link points outside
+/// of the directory it is contained in. Absolute targets are
+/// always considered to be outside.
+pub fn is_link_outside(link: &Path, target: &Path) -> bool {
+    let mut depth = link.components().count().saturating_sub(1);
+
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return true,
+        }
     }
-    if b_walker.next().is_some() {
-        return Ok(Some(Site::Right));
+
+    false
+}
+
+/// Pair up the entries of both directories by their path relative
+/// to the base directories. The pairs are sorted by path.
+pub fn pair_entries<A: AsRef<Path>, B: AsRef<Path>>(
+    a_base: A,
+    b_base: B,
+) -> Result<Vec<Pair>, std::io::Error> {
+    let mut left = relative_entries(a_base)?;
+    let mut right = relative_entries(b_base)?;
+
+    let mut paths: Vec<PathBuf> = left.keys().chain(right.keys()).cloned().collect();
+    paths.sort();
+    paths.dedup();
+
+    Ok(paths
+        .into_iter()
+        .map(|path| Pair {
+            left: left.remove(&path),
+            right: right.remove(&path),
+            path,
+        })
+        .collect())
+}
+
+/// Returns all entries of base by their relative path.
+fn relative_entries<P: AsRef<Path>>(
+    base: P,
+) -> Result<BTreeMap<PathBuf, DirEntry>, std::io::Error> {
+    let mut entries = BTreeMap::new();
+
+    for entry in walk_dir(&base)?.filter_entry(git_filter_entries) {
+        let entry = entry?;
+        let path = entry.path().strip_prefix(&base).unwrap().to_path_buf();
+        entries.insert(path, entry);
     }
-    Ok(None)
+
+    Ok(entries)
 }
 
 pub fn walk_dir<P: AsRef<Path>>(path: P) -> Result<walkdir::IntoIter, std::io::Error> {
@@ -70,6 +167,30 @@ pub fn compare_by_file_name(a: &DirEntry, b: &DirEntry) -> Ordering {
     a.file_name().cmp(b.file_name())
 }
 
+impl std::fmt::Display for DirDiffEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.site {
+            Site::Left => "removed",
+            Site::Right => "added",
+            Site::Mismatch => "type changed",
+            Site::SymlinkMismatch => "symlink changed",
+        };
+        write!(f, "{} ({})", self.path.display(), kind)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::StripPrefix(e) => write!(f, "{}", e),
+            Error::WalkDir(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Error {
         Error::Io(e)
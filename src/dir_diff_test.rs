@@ -0,0 +1,67 @@
+use super::*;
+
+#[test]
+fn check_pair_entries() {
+    let pairs = pair_entries("./tests/dir_diff/left", "./tests/dir_diff/right").unwrap();
+
+    let paths: Vec<&Path> = pairs.iter().map(|i| i.path.as_path()).collect();
+    assert_eq!(paths, vec![Path::new("a"), Path::new("b"), Path::new("c")]);
+
+    assert!(pairs[0].left.is_some() && pairs[0].right.is_some());
+    assert!(pairs[1].left.is_none() && pairs[1].right.is_some());
+    assert!(pairs[2].left.is_some() && pairs[2].right.is_some());
+}
+
+#[test]
+fn check_is_different() {
+    let differences = is_different("./tests/dir_diff/left", "./tests/dir_diff/right").unwrap();
+    assert_eq!(
+        differences,
+        vec![DirDiffEntry {
+            path: PathBuf::from("b"),
+            site: Site::Right,
+        }]
+    );
+    assert_eq!(differences[0].to_string(), "b (added)");
+
+    let differences = is_different("./tests/dir_diff/right", "./tests/dir_diff/left").unwrap();
+    assert_eq!(differences[0].site, Site::Left);
+
+    let differences = is_different("./tests/dir_diff/left", "./tests/dir_diff/left").unwrap();
+    assert!(differences.is_empty());
+}
+
+#[test]
+fn check_is_different_symlinks() {
+    let differences = is_different(
+        "./tests/dir_diff_links/left",
+        "./tests/dir_diff_links/right",
+    )
+    .unwrap();
+
+    let paths: Vec<(&Path, &Site)> = differences
+        .iter()
+        .map(|i| (i.path.as_path(), &i.site))
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            (Path::new("changed"), &Site::SymlinkMismatch),
+            (Path::new("file"), &Site::SymlinkMismatch),
+        ]
+    );
+}
+
+#[test]
+fn check_is_link_outside() {
+    assert!(!is_link_outside(Path::new("a"), Path::new("b")));
+    assert!(!is_link_outside(Path::new("a"), Path::new("./b")));
+    assert!(!is_link_outside(Path::new("dir/a"), Path::new("../b")));
+    assert!(!is_link_outside(
+        Path::new("dir/a"),
+        Path::new("sub/../../b")
+    ));
+    assert!(is_link_outside(Path::new("a"), Path::new("../b")));
+    assert!(is_link_outside(Path::new("dir/a"), Path::new("../../b")));
+    assert!(is_link_outside(Path::new("a"), Path::new("/etc/shadow")));
+}
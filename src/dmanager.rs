@@ -0,0 +1,118 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::error::Error;
+
+#[cfg(test)]
+#[path = "dmanager_test.rs"]
+mod dmanager_test;
+
+/// DManager downloads build artifacts uploaded
+/// by the remote build jobs.
+pub struct DManager<'a> {
+    config: &'a config::TokenConfig,
+}
+
+/// Artifact lookup response.
+#[derive(Debug, Deserialize)]
+struct Artifact {
+    url: String,
+}
+
+impl<'a> DManager<'a> {
+    /// Create a new dmanager client
+    pub fn new(config: &'a config::TokenConfig) -> Self {
+        DManager { config }
+    }
+
+    /// Returns the URL of the artifact uploaded by the given job.
+    pub async fn artifact_url(&self, job_id: u32) -> Result<Url, Error> {
+        let url = self.url(format!("job/{}/artifact", job_id).as_str())?;
+
+        let artifact: Artifact = self
+            .request(url.clone())
+            .await
+            .map_err(|e| download_error(&url, e))?
+            .json()
+            .await
+            .map_err(|e| download_error(&url, e))?;
+
+        self.url(&artifact.url)
+    }
+
+    /// Download a file into dir. The file is written to a .part
+    /// file first, which gets renamed once the download is complete
+    /// and removed if it fails. Returns the path of the downloaded file.
+    pub async fn download(&self, url: &Url, dir: &Path) -> Result<PathBuf, Error> {
+        let file_name = match url.path_segments().and_then(|mut i| i.next_back()) {
+            Some(name) if !name.is_empty() => name.to_owned(),
+            _ => return Err(download_error(url, "no file name in URL")),
+        };
+
+        let path = dir.join(&file_name);
+        let part_path = dir.join(format!("{}.part", file_name));
+
+        let mut res = self
+            .request(url.clone())
+            .await
+            .map_err(|e| download_error(url, e))?;
+
+        let mut file = File::create(&part_path).map_err(|e| download_error(url, e))?;
+        let mut hasher = Sha256::new();
+
+        let written: Result<(), Error> = async {
+            while let Some(chunk) = res.chunk().await.map_err(|e| download_error(url, e))? {
+                hasher.update(&chunk);
+                file.write_all(&chunk).map_err(|e| download_error(url, e))?;
+            }
+            fs::rename(&part_path, &path).map_err(|e| download_error(url, e))
+        }
+        .await;
+
+        if let Err(e) = written {
+            let _ = fs::remove_file(&part_path);
+            return Err(e);
+        }
+        info!("Downloaded {} sha256: {:x}", file_name, hasher.finalize());
+
+        Ok(path)
+    }
+
+    /// Do a GET request. The token is only sent to the dmanager
+    /// itself, artifacts may be served from somewhere else.
+    async fn request(&self, url: Url) -> reqwest::Result<reqwest::Response> {
+        let mut req = Client::new().get(url.clone());
+        if self.is_own(&url) {
+            req = req.bearer_auth(&self.config.token);
+        }
+
+        req.send().await?.error_for_status()
+    }
+
+    /// Returns true if url has the origin of the dmanager.
+    fn is_own(&self, url: &Url) -> bool {
+        match Url::parse(&self.config.url) {
+            Ok(base) => base.origin() == url.origin(),
+            Err(_) => false,
+        }
+    }
+
+    /// Join path with the dmanager base URL. Absolute URLs
+    /// are returned as they are.
+    fn url(&self, path: &str) -> Result<Url, Error> {
+        Url::parse(&self.config.url)
+            .and_then(|base| base.join(path))
+            .map_err(|e| Error::DownloadFailed(path.to_owned(), Box::new(e)))
+    }
+}
+
+fn download_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(url: &Url, e: E) -> Error {
+    Error::DownloadFailed(url.to_string(), e.into())
+}
@@ -0,0 +1,20 @@
+use super::*;
+
+#[test]
+fn token_only_for_own_origin() {
+    let config = config::TokenConfig {
+        url: "https://dmanager.example.com/api/".to_owned(),
+        ..config::TokenConfig::default()
+    };
+    let dmanager = DManager::new(&config);
+    let url = |url: &str| Url::parse(url).unwrap();
+
+    assert!(dmanager.is_own(&url("https://dmanager.example.com/files/foo.pkg.tar.zst")));
+    assert!(dmanager.is_own(&url("https://dmanager.example.com:443/job/1/artifact")));
+    assert!(dmanager.is_own(&dmanager.url("job/1/artifact").unwrap()));
+
+    assert!(!dmanager.is_own(&url("http://dmanager.example.com/files/foo")));
+    assert!(!dmanager.is_own(&url("https://dmanager.example.com:8443/files/foo")));
+    assert!(!dmanager.is_own(&url("https://cdn.example.com/files/foo")));
+    assert!(!dmanager.is_own(&dmanager.url("https://evil.example.com/foo").unwrap()));
+}
@@ -1,20 +1,86 @@
 use std::error::Error as stdErr;
 use std::fmt::{Display, Formatter, Result};
+use std::io;
 
+use lib_remotebuild_rs::request_error::Error as RequestError;
+
+#[cfg(test)]
+#[path = "error_test.rs"]
+mod error_test;
+
+/// Errors of the build service. Variants wrapping another
+/// error expose it as source and append it to their message.
 #[derive(Debug)]
-#[allow(dead_code)]
+#[allow(dead_code, clippy::enum_variant_names)]
 pub enum Error {
     DifferentDirs(String),
     ChecksFailed(String),
-    AurJobError(String),
-    JobInfoError(String),
+    /// Creating a build job for the package failed.
+    AurJobError(String, RequestError),
+    /// Looking up a build job failed.
+    JobInfoError(String, Option<RequestError>),
     JobFailed(String),
+    JobCancelled(String),
+    JobTimeout(u32),
+    TelegramError(String),
+    NotApproved(String),
+    SigningFailed(String, Option<io::Error>),
+    DownloadFailed(String, Box<dyn stdErr + Send + Sync>),
+    GitError(String, Option<git2::Error>),
+    SrcinfoFailed(String),
+    /// The AUR commit isn't signed by a trusted key.
+    UntrustedCommit(String),
+    /// Adding a package to the repository database failed.
+    RepoAddFailed(String, Option<io::Error>),
+}
+
+impl Error {
+    /// Returns true if the failed build job is worth to be retried.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::AurJobError(..) | Error::JobInfoError(..) | Error::JobFailed(_)
+        )
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::DifferentDirs(s) => write!(f, "Files were added or removed: {}", s),
+            Error::ChecksFailed(s) => write!(f, "Package checks failed: {}", s),
+            Error::AurJobError(pkg, _) => write!(f, "Can't create build job for {}", pkg),
+            Error::JobInfoError(s, _) => write!(f, "Can't get build job info: {}", s),
+            Error::JobFailed(s) => write!(f, "Build job failed: {}", s),
+            Error::JobCancelled(s) => write!(f, "Build job was cancelled: {}", s),
+            Error::JobTimeout(id) => write!(f, "Build job {} timed out", id),
+            Error::TelegramError(s) => write!(f, "Telegram request failed: {}", s),
+            Error::NotApproved(s) => write!(f, "Update of {} was not approved", s),
+            Error::SigningFailed(s, _) => write!(f, "Signing failed: {}", s),
+            Error::DownloadFailed(s, _) => write!(f, "Download failed: {}", s),
+            Error::GitError(s, _) => write!(f, "Git error: {}", s),
+            Error::SrcinfoFailed(s) => write!(f, "Can't update .SRCINFO: {}", s),
+            Error::UntrustedCommit(s) => write!(f, "Untrusted AUR commit: {}", s),
+            Error::RepoAddFailed(s, _) => write!(f, "repo-add failed: {}", s),
+        }?;
+
+        match self.source() {
+            Some(source) => write!(f, ": {}", source),
+            None => Ok(()),
+        }
     }
 }
 
-impl stdErr for Error {}
+impl stdErr for Error {
+    fn source(&self) -> Option<&(dyn stdErr + 'static)> {
+        match self {
+            Error::AurJobError(_, e) => Some(e),
+            Error::JobInfoError(_, e) => e.as_ref().map(|e| e as _),
+            Error::SigningFailed(_, e) => e.as_ref().map(|e| e as _),
+            Error::DownloadFailed(_, e) => Some(e.as_ref()),
+            Error::GitError(_, e) => e.as_ref().map(|e| e as _),
+            Error::RepoAddFailed(_, e) => e.as_ref().map(|e| e as _),
+            _ => None,
+        }
+    }
+}
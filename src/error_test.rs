@@ -0,0 +1,20 @@
+use super::*;
+
+#[test]
+fn display_with_source() {
+    let err = Error::GitError(
+        "Can't read HEAD".to_owned(),
+        Some(git2::Error::from_str("reference not found")),
+    );
+    assert_eq!(
+        err.to_string(),
+        "Git error: Can't read HEAD: reference not found"
+    );
+    assert!(err.source().is_some());
+
+    let err = Error::GitError("Branch 'main' doesn't exist".to_owned(), None);
+    assert_eq!(err.to_string(), "Git error: Branch 'main' doesn't exist");
+    assert!(err.source().is_none());
+
+    assert_eq!(Error::JobTimeout(3).to_string(), "Build job 3 timed out");
+}
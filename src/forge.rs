@@ -0,0 +1,106 @@
+use std::error::Error;
+
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+
+use crate::config::{self, ForgeKind};
+
+#[cfg(test)]
+#[path = "forge_test.rs"]
+mod forge_test;
+
+/// API base URL of github.com.
+const GITHUB_API_URL: &str = "https://api.github.com";
+/// API base URL of gitlab.com.
+const GITLAB_API_URL: &str = "https://gitlab.com/api/v4";
+
+/// Returns the branch the update of a package gets pushed to.
+/// Characters not allowed in refs, like the epoch separator, become '_'.
+pub fn branch_name(pkg_base: &str, version: &str) -> String {
+    let version: String = version
+        .chars()
+        .map(|i| if ":~^?*[\\ ".contains(i) { '_' } else { i })
+        .collect();
+    format!("aurtomatic/update-{}-{}", pkg_base, version)
+}
+
+/// Open a pull or merge request of branch head into base in the
+/// repository of pkg_base. Returns the web URL of the request.
+pub async fn open_request(
+    forge: &config::Forge,
+    pkg_base: &str,
+    (head, base): (&str, &str),
+    title: &str,
+) -> Result<String, Box<dyn Error>> {
+    let (url, body) = request(forge, pkg_base, (head, base), title)?;
+
+    let client = Client::new().post(url).json(&body);
+    let client = match forge.kind {
+        ForgeKind::GitHub => client
+            .header("Authorization", format!("token {}", forge.token))
+            .header("Accept", "application/vnd.github.v3+json")
+            .header("User-Agent", "AURtomatic"),
+        ForgeKind::GitLab => client.header("PRIVATE-TOKEN", forge.token.as_str()),
+    };
+
+    let res: Value = client.send().await?.error_for_status()?.json().await?;
+    web_url(forge.kind, &res).ok_or_else(|| "response without URL".into())
+}
+
+/// Returns the API URL and body creating a pull or merge request.
+fn request(
+    forge: &config::Forge,
+    pkg_base: &str,
+    (head, base): (&str, &str),
+    title: &str,
+) -> Result<(Url, Value), Box<dyn Error>> {
+    let repository = forge.repository.replace(config::PKG_PLACEHOLDER, pkg_base);
+    let api_url = match (forge.api_url.as_str(), forge.kind) {
+        ("", ForgeKind::GitHub) => GITHUB_API_URL,
+        ("", ForgeKind::GitLab) => GITLAB_API_URL,
+        (url, _) => url,
+    };
+
+    let mut url = Url::parse(api_url)?;
+    {
+        let mut segments = url
+            .path_segments_mut()
+            .map_err(|_| format!("{} can't be a base URL", api_url))?;
+        segments.pop_if_empty();
+
+        match forge.kind {
+            ForgeKind::GitHub => {
+                segments.push("repos");
+                segments.extend(repository.split('/'));
+                segments.push("pulls");
+            }
+            // The project path is a single, encoded segment
+            ForgeKind::GitLab => {
+                segments.push("projects");
+                segments.push(&repository);
+                segments.push("merge_requests");
+            }
+        }
+    }
+
+    let body = match forge.kind {
+        ForgeKind::GitHub => json!({ "title": title, "head": head, "base": base }),
+        ForgeKind::GitLab => json!({
+            "title": title,
+            "source_branch": head,
+            "target_branch": base,
+            "remove_source_branch": true,
+        }),
+    };
+
+    Ok((url, body))
+}
+
+/// Returns the web URL of a created pull or merge request.
+fn web_url(kind: ForgeKind, res: &Value) -> Option<String> {
+    let key = match kind {
+        ForgeKind::GitHub => "html_url",
+        ForgeKind::GitLab => "web_url",
+    };
+    res.get(key).and_then(|i| i.as_str()).map(|i| i.to_owned())
+}
@@ -0,0 +1,52 @@
+use super::*;
+
+fn forge(kind: ForgeKind, api_url: &str) -> config::Forge {
+    config::Forge {
+        kind,
+        api_url: api_url.to_owned(),
+        token: "token".to_owned(),
+        token_file: None,
+        repository: "group/aur/{pkg}".to_owned(),
+    }
+}
+
+#[test]
+fn github_request() {
+    let forge = forge(ForgeKind::GitHub, "");
+    let (url, body) = request(&forge, "foo", ("aurtomatic/update-foo-1.2", "master"), "t").unwrap();
+
+    assert_eq!(
+        url.as_str(),
+        "https://api.github.com/repos/group/aur/foo/pulls"
+    );
+    assert_eq!(body["head"], "aurtomatic/update-foo-1.2");
+    assert_eq!(body["base"], "master");
+}
+
+#[test]
+fn gitlab_request() {
+    let forge = forge(ForgeKind::GitLab, "https://git.example.com/api/v4/");
+    let (url, body) = request(&forge, "foo", ("update", "main"), "t").unwrap();
+
+    assert_eq!(
+        url.as_str(),
+        "https://git.example.com/api/v4/projects/group%2Faur%2Ffoo/merge_requests"
+    );
+    assert_eq!(body["source_branch"], "update");
+    assert_eq!(body["target_branch"], "main");
+}
+
+#[test]
+fn request_web_url() {
+    let res = json!({ "html_url": "https://github.com/a/b/pull/1", "web_url": "x" });
+    assert_eq!(
+        web_url(ForgeKind::GitHub, &res).as_deref(),
+        Some("https://github.com/a/b/pull/1")
+    );
+    assert_eq!(web_url(ForgeKind::GitLab, &json!({})), None);
+    assert_eq!(branch_name("foo", "1.2-1"), "aurtomatic/update-foo-1.2-1");
+    assert_eq!(
+        branch_name("foo", "1:1.2-1"),
+        "aurtomatic/update-foo-1_1.2-1"
+    );
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::Path;
+
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{FetchOptions, Oid, Repository, ResetType};
+use log::{debug, warn};
+
+/// Fetch refspec keeping all remote branches of a cache up to date.
+const FETCH_REFSPEC: &str = "+refs/heads/*:refs/remotes/origin/*";
+
+/// Clone url into dest using a persistent checkout at cache. The cache
+/// gets a fetch and a hard reset to the remote branch instead of being
+/// cloned from zero. Missing or corrupt caches are cloned again.
+/// The origin of the returned repository points to url.
+pub fn clone<'cb, F>(
+    url: &str,
+    cache: &Path,
+    dest: &Path,
+    branch: Option<&str>,
+    fetch_options: F,
+) -> Result<Repository, git2::Error>
+where
+    F: Fn() -> FetchOptions<'cb>,
+{
+    let cache_repo = match update(url, cache, branch, &fetch_options) {
+        Ok(repo) => repo,
+        Err(e) => {
+            if cache.exists() {
+                warn!("Recreating git cache {}: {}", cache.display(), e);
+                fs::remove_dir_all(cache).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+            }
+
+            clone_remote(url, cache, branch, fetch_options())?
+        }
+    };
+
+    let cache_url = cache
+        .to_str()
+        .ok_or_else(|| git2::Error::from_str("invalid cache path"))?;
+
+    let repo = RepoBuilder::new()
+        .branch(&head_branch(&cache_repo)?)
+        .clone(cache_url, dest)?;
+    repo.remote_set_url("origin", url)?;
+
+    Ok(repo)
+}
+
+/// Check out revision, a commit hash or tag, with a detached HEAD.
+/// Returns the id of the checked out commit.
+pub fn checkout(repo: &Repository, revision: &str) -> Result<Oid, git2::Error> {
+    let commit = repo.revparse_single(revision)?.peel_to_commit()?;
+    repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(commit.id())?;
+    Ok(commit.id())
+}
+
+/// Fetch the cache and hard reset it to the remote branch.
+fn update<'cb, F>(
+    url: &str,
+    cache: &Path,
+    branch: Option<&str>,
+    fetch_options: &F,
+) -> Result<Repository, git2::Error>
+where
+    F: Fn() -> FetchOptions<'cb>,
+{
+    let repo = Repository::open(cache)?;
+    repo.remote_set_url("origin", url)?;
+    repo.find_remote("origin")?
+        .fetch(&[FETCH_REFSPEC], Some(&mut fetch_options()), None)?;
+
+    let branch = match branch {
+        Some(branch) => branch.to_owned(),
+        None => head_branch(&repo)?,
+    };
+
+    let commit = repo
+        .revparse_single(&format!("refs/remotes/origin/{}", branch))?
+        .peel_to_commit()?;
+
+    if head_branch(&repo).ok().as_deref() != Some(branch.as_str()) {
+        repo.branch(&branch, &commit, true)?;
+        repo.set_head(&format!("refs/heads/{}", branch))?;
+    }
+
+    repo.reset(commit.as_object(), ResetType::Hard, None)?;
+    debug!("Updated git cache {} to {}", cache.display(), commit.id());
+
+    drop(commit);
+    Ok(repo)
+}
+
+/// Clone a fresh cache from url.
+fn clone_remote(
+    url: &str,
+    cache: &Path,
+    branch: Option<&str>,
+    fetch_options: FetchOptions<'_>,
+) -> Result<Repository, git2::Error> {
+    if let Some(parent) = cache.parent() {
+        fs::create_dir_all(parent).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+    }
+
+    let mut builder = RepoBuilder::new();
+    builder.fetch_options(fetch_options);
+    if let Some(branch) = branch {
+        builder.branch(branch);
+    }
+
+    builder.clone(url, cache)
+}
+
+/// Name of the branch HEAD points to.
+fn head_branch(repo: &Repository) -> Result<String, git2::Error> {
+    repo.head()?
+        .shorthand()
+        .map(|i| i.to_owned())
+        .ok_or_else(|| git2::Error::from_str("HEAD is not a branch"))
+}
+
+#[cfg(test)]
+#[path = "git_cache_test.rs"]
+mod git_cache_test;
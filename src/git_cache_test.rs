@@ -0,0 +1,99 @@
+use super::*;
+
+use crate::test_dir::TestDir;
+
+/// Commit a file named PKGBUILD with content to the repo at path.
+fn commit(path: &Path, content: &str) {
+    let repo = Repository::open(path).unwrap();
+    fs::write(path.join("PKGBUILD"), content).unwrap();
+
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("PKGBUILD")).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+    let sig = git2::Signature::now("test", "test@localhost").unwrap();
+    let parent = repo.head().ok().and_then(|i| i.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &sig, &sig, content, &tree, &parents)
+        .unwrap();
+}
+
+fn head(path: &Path) -> Oid {
+    let repo = Repository::open(path).unwrap();
+    let id = repo.head().unwrap().peel_to_commit().unwrap().id();
+    id
+}
+
+fn pkgbuild(path: &Path) -> String {
+    fs::read_to_string(path.join("PKGBUILD")).unwrap()
+}
+
+#[test]
+fn clone_and_update_cache() {
+    let dir = TestDir::new("git_cache");
+    let origin = dir.join("origin");
+    let cache = dir.join("cache").join("pkg");
+    Repository::init(&origin).unwrap();
+    commit(&origin, "v1");
+    let url = origin.to_str().unwrap();
+
+    let repo = clone(url, &cache, &dir.join("a"), None, FetchOptions::new).unwrap();
+    assert_eq!(pkgbuild(&dir.join("a")), "v1");
+    assert_eq!(repo.find_remote("origin").unwrap().url(), Some(url));
+    assert!(cache.exists());
+
+    // Local changes in the cache get reset
+    commit(&origin, "v2");
+    fs::write(cache.join("PKGBUILD"), "dirty").unwrap();
+
+    clone(url, &cache, &dir.join("b"), None, FetchOptions::new).unwrap();
+    assert_eq!(pkgbuild(&dir.join("b")), "v2");
+    assert_eq!(pkgbuild(&cache), "v2");
+}
+
+#[test]
+fn recreate_corrupt_cache() {
+    let dir = TestDir::new("git_cache_corrupt");
+    let origin = dir.join("origin");
+    let cache = dir.join("cache");
+    Repository::init(&origin).unwrap();
+    commit(&origin, "v1");
+
+    fs::create_dir(&cache).unwrap();
+    fs::write(cache.join("garbage"), "").unwrap();
+
+    let url = origin.to_str().unwrap();
+    clone(url, &cache, &dir.join("a"), None, FetchOptions::new).unwrap();
+    assert_eq!(pkgbuild(&dir.join("a")), "v1");
+    assert!(!cache.join("garbage").exists());
+}
+
+#[test]
+fn checkout_revision() {
+    let dir = TestDir::new("git_cache_checkout");
+    let origin = dir.join("origin");
+    Repository::init(&origin).unwrap();
+    commit(&origin, "v1");
+    let first = head(&origin);
+    {
+        let repo = Repository::open(&origin).unwrap();
+        let target = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v1", &target, false).unwrap();
+    }
+    commit(&origin, "v2");
+    let second = head(&origin);
+
+    let url = origin.to_str().unwrap();
+    let cache = dir.join("cache");
+    let repo = clone(url, &cache, &dir.join("a"), None, FetchOptions::new).unwrap();
+    assert_eq!(pkgbuild(&dir.join("a")), "v2");
+
+    assert_eq!(checkout(&repo, "v1").unwrap(), first);
+    assert_eq!(pkgbuild(&dir.join("a")), "v1");
+    assert!(repo.head_detached().unwrap());
+
+    assert_eq!(checkout(&repo, &second.to_string()).unwrap(), second);
+    assert_eq!(pkgbuild(&dir.join("a")), "v2");
+    assert!(checkout(&repo, "missing").is_err());
+}
@@ -0,0 +1,57 @@
+use regex::Regex;
+
+#[cfg(test)]
+#[path = "ignore_test.rs"]
+mod ignore_test;
+
+/// Compile the ignore_patterns of the config.
+pub fn patterns(custom: &Option<Vec<String>>) -> Result<Vec<Regex>, regex::Error> {
+    custom.iter().flatten().map(|i| Regex::new(i)).collect()
+}
+
+/// Returns the rule which causes pkg_name to be ignored. Names
+/// match exactly or as glob pattern using '*' and '?'.
+pub fn matching_rule(pkg_name: &str, names: &[String], patterns: &[Regex]) -> Option<String> {
+    if let Some(name) = names.iter().find(|i| i.as_str() == pkg_name) {
+        return Some(format!("ignore_packages entry '{}'", name));
+    }
+
+    if let Some(glob) = names.iter().find(|i| glob_match(i, pkg_name)) {
+        return Some(format!("ignore_packages glob '{}'", glob));
+    }
+
+    patterns
+        .iter()
+        .find(|i| i.is_match(pkg_name))
+        .map(|i| format!("ignore_patterns regex '{}'", i))
+}
+
+/// Match name against a glob pattern. '*' matches any
+/// amount of characters and '?' exactly one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position of the last '*' and the name position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Let the last '*' match one more character
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, n));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|i| *i == '*')
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+#[test]
+fn check_glob_match() {
+    assert!(glob_match("*-git", "foo-git"));
+    assert!(glob_match("*-git", "-git"));
+    assert!(glob_match("lib?", "liba"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("a*b*c", "aXbYbZc"));
+
+    assert!(!glob_match("*-git", "foo-git-bin"));
+    assert!(!glob_match("lib?", "lib"));
+    assert!(!glob_match("foo", "foobar"));
+}
+
+#[test]
+fn check_matching_rule() {
+    let names = vec!["foo".to_owned(), "*-git".to_owned()];
+    let patterns = patterns(&Some(vec![r"^lib.*-debug$".to_owned()])).unwrap();
+
+    assert_eq!(
+        matching_rule("foo", &names, &patterns).as_deref(),
+        Some("ignore_packages entry 'foo'")
+    );
+    assert_eq!(
+        matching_rule("bar-git", &names, &patterns).as_deref(),
+        Some("ignore_packages glob '*-git'")
+    );
+    assert_eq!(
+        matching_rule("libbar-debug", &names, &patterns).as_deref(),
+        Some("ignore_patterns regex '^lib.*-debug$'")
+    );
+    assert_eq!(matching_rule("bar", &names, &patterns), None);
+}
@@ -0,0 +1,74 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+#[cfg(test)]
+#[path = "lockfile_test.rs"]
+mod lockfile_test;
+
+/// Lockfile holds an advisory lock on a file containing the
+/// PID of the running process. The lock gets released once the
+/// Lockfile is dropped, the file is kept.
+#[derive(Debug)]
+pub struct Lockfile {
+    /// The lock is held as long as the file is open.
+    _file: File,
+}
+
+impl Lockfile {
+    /// Acquire the lock at path. Fails if it is held by another
+    /// process. The lock of a dead process is released by the kernel,
+    /// so files left behind by it are reused.
+    pub fn acquire<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let path = path.as_ref();
+
+        // The PID is only informative, whoever holds the lock is running
+        let mut file = try_lock(path)?.ok_or_else(|| {
+            let holder = match read_pid(path) {
+                Some(pid) => format!(" (pid {})", pid),
+                None => String::new(),
+            };
+            io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("another instance is running{}", holder),
+            )
+        })?;
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+
+        Ok(Lockfile { _file: file })
+    }
+}
+
+/// Open path and try to lock it. Returns None if
+/// the file is locked already.
+fn try_lock(path: &Path) -> Result<Option<File>, io::Error> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(Some(file));
+    }
+
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Ok(None)
+    } else {
+        Err(err)
+    }
+}
+
+/// Read the PID recorded in the lockfile.
+fn read_pid(path: &Path) -> Option<i32> {
+    let mut content = String::new();
+    File::open(path).ok()?.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
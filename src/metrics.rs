@@ -0,0 +1,136 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+#[cfg(test)]
+#[path = "metrics_test.rs"]
+mod metrics_test;
+
+/// Metrics collects counters about the work done
+/// by the build service.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    packages_checked: AtomicU64,
+    updates_built: AtomicU64,
+    build_failures: AtomicU64,
+    checks_rejected: AtomicU64,
+    building: AtomicI64,
+}
+
+/// Counts a package as building as long as it is alive.
+pub struct Building<'a> {
+    metrics: &'a Metrics,
+}
+
+impl Metrics {
+    pub fn package_checked(&self) {
+        inc(&self.packages_checked);
+    }
+
+    pub fn update_built(&self) {
+        inc(&self.updates_built);
+    }
+
+    pub fn build_failed(&self) {
+        inc(&self.build_failures);
+    }
+
+    pub fn check_rejected(&self) {
+        inc(&self.checks_rejected);
+    }
+
+    /// Count a package as building until the
+    /// returned guard gets dropped.
+    pub fn building(&self) -> Building<'_> {
+        self.building.fetch_add(1, Ordering::Relaxed);
+        Building { metrics: self }
+    }
+
+    /// Render all metrics in the prometheus text format.
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let counters = [
+            (
+                "aurtomatic_packages_checked_total",
+                "Packages checked for updates.",
+                &self.packages_checked,
+            ),
+            (
+                "aurtomatic_updates_built_total",
+                "Updates built and pushed.",
+                &self.updates_built,
+            ),
+            (
+                "aurtomatic_build_failures_total",
+                "Failed or cancelled build jobs.",
+                &self.build_failures,
+            ),
+            (
+                "aurtomatic_checks_rejected_total",
+                "Updates rejected by the package checks.",
+                &self.checks_rejected,
+            ),
+        ];
+
+        for (name, help, counter) in counters.iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP aurtomatic_packages_building Packages currently building."
+        );
+        let _ = writeln!(out, "# TYPE aurtomatic_packages_building gauge");
+        let _ = writeln!(
+            out,
+            "aurtomatic_packages_building {}",
+            self.building.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+impl Drop for Building<'_> {
+    fn drop(&mut self) {
+        self.metrics.building.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn inc(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Serve the metrics on /metrics at the given port.
+#[cfg(feature = "metrics")]
+pub async fn serve(metrics: std::sync::Arc<Metrics>, port: u16) -> Result<(), hyper::Error> {
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server, StatusCode};
+    use std::convert::Infallible;
+
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let metrics = metrics.clone();
+                async move {
+                    let res = if req.uri().path() == "/metrics" {
+                        Response::new(Body::from(metrics.render()))
+                    } else {
+                        let mut res = Response::new(Body::empty());
+                        *res.status_mut() = StatusCode::NOT_FOUND;
+                        res
+                    };
+                    Ok::<_, Infallible>(res)
+                }
+            }))
+        }
+    });
+
+    Server::bind(&([0, 0, 0, 0], port).into())
+        .serve(make_service)
+        .await
+}
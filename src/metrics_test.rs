@@ -0,0 +1,22 @@
+use super::*;
+
+#[test]
+fn render_metrics() {
+    let metrics = Metrics::default();
+    metrics.package_checked();
+    metrics.package_checked();
+    metrics.check_rejected();
+
+    let building = metrics.building();
+    let out = metrics.render();
+    assert!(out.contains("# TYPE aurtomatic_packages_checked_total counter\n"));
+    assert!(out.contains("aurtomatic_packages_checked_total 2\n"));
+    assert!(out.contains("aurtomatic_checks_rejected_total 1\n"));
+    assert!(out.contains("aurtomatic_updates_built_total 0\n"));
+    assert!(out.contains("aurtomatic_packages_building 1\n"));
+
+    drop(building);
+    assert!(metrics
+        .render()
+        .contains("aurtomatic_packages_building 0\n"));
+}
@@ -0,0 +1,139 @@
+use std::error::Error as stdErr;
+
+use async_trait::async_trait;
+use lettre::smtp::authentication::Credentials;
+use lettre::{ClientSecurity, ClientTlsParameters, SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+use reqwest::Client;
+use serde_json::json;
+
+use crate::config::{self, Backend, Config};
+use crate::tg_bot_wrapper::TgBot;
+
+/// Notifier sends messages to the user.
+#[async_trait(?Send)]
+pub trait Notifier {
+    async fn notify(&self, message: &str) -> Result<(), Box<dyn stdErr>>;
+}
+
+/// Create the notifier selected in the config.
+pub fn new(config: &Config) -> Box<dyn Notifier> {
+    match config.notifications.backend {
+        Backend::Telegram => Box::new(
+            TgBot::new(config.telegram.bot_token.clone(), config.telegram.user_id)
+                .with_recipients(config.telegram.chat_ids.clone())
+                .with_api_base_url(config.telegram.api_base_url())
+                .with_max_attempts(config.telegram.max_attempts),
+        ),
+        Backend::Discord => Box::new(DiscordWebhook::new(
+            config
+                .notifications
+                .discord
+                .as_ref()
+                .map(|i| i.webhook_url.clone())
+                .unwrap_or_default(),
+        )),
+        Backend::Email => Box::new(EmailNotifier::new(
+            config.notifications.email.clone().unwrap_or_default(),
+        )),
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for TgBot {
+    async fn notify(&self, message: &str) -> Result<(), Box<dyn stdErr>> {
+        // An unreachable chat must not keep the others from being notified
+        let mut failed = Vec::new();
+        for &chat_id in self.recipients() {
+            let res = match self.send_message(chat_id, message).await {
+                Ok(res) => res.error_for_status().map(|_| ()).map_err(|e| e.into()),
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = res {
+                failed.push(format!("chat {}: {}", chat_id, e));
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed.join(", ").into())
+        }
+    }
+}
+
+/// Sends notifications to a discord channel.
+pub struct DiscordWebhook {
+    url: String,
+}
+
+impl DiscordWebhook {
+    pub fn new(url: String) -> Self {
+        DiscordWebhook { url }
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for DiscordWebhook {
+    async fn notify(&self, message: &str) -> Result<(), Box<dyn stdErr>> {
+        Client::new()
+            .post(&self.url)
+            .json(&json!({ "content": message }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Sends notifications as emails over SMTP using STARTTLS.
+pub struct EmailNotifier {
+    config: config::Email,
+}
+
+impl EmailNotifier {
+    pub fn new(config: config::Email) -> Self {
+        EmailNotifier { config }
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for EmailNotifier {
+    async fn notify(&self, message: &str) -> Result<(), Box<dyn stdErr>> {
+        let config = self.config.clone();
+        let message = message.to_owned();
+
+        // lettre is blocking
+        tokio::task::spawn_blocking(move || send_mail(&config, &message))
+            .await?
+            .map_err(|e| e as Box<dyn stdErr>)
+    }
+}
+
+fn send_mail(config: &config::Email, message: &str) -> Result<(), Box<dyn stdErr + Send + Sync>> {
+    let email = EmailBuilder::new()
+        .from(config.from.as_str())
+        .to(config.to.as_str())
+        .subject(format!(
+            "AURtomatic: {}",
+            message.lines().next().unwrap_or_default()
+        ))
+        .text(message)
+        .build()?;
+
+    let tls = ClientTlsParameters::new(config.host.clone(), TlsConnector::new()?);
+    let mut transport = SmtpClient::new(
+        (config.host.as_str(), config.port),
+        ClientSecurity::Required(tls),
+    )?
+    .credentials(Credentials::new(
+        config.username.clone(),
+        config.password.clone(),
+    ))
+    .transport();
+
+    transport.send(email.into())?;
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[cfg(test)]
+#[path = "orphans_test.rs"]
+mod orphans_test;
+
+/// Orphans counts the refresh cycles in a row a package wasn't
+/// found in the AUR, e.g. because it got renamed or removed.
+#[derive(Debug, Default)]
+pub struct Orphans {
+    /// Misses after which a package is reported. 0 disables reports.
+    threshold: u32,
+    misses: Mutex<HashMap<String, u32>>,
+}
+
+impl Orphans {
+    pub fn new(threshold: u32) -> Self {
+        Orphans {
+            threshold,
+            misses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reset the misses of a package found in the AUR.
+    pub fn found(&self, pkg_name: &str) {
+        self.misses.lock().unwrap().remove(pkg_name);
+    }
+
+    /// Count a lookup which didn't find the package. Returns
+    /// true only once, when the threshold is reached.
+    pub fn missing(&self, pkg_name: &str) -> bool {
+        let mut misses = self.misses.lock().unwrap();
+        let count = misses.entry(pkg_name.to_owned()).or_insert(0);
+        *count = count.saturating_add(1);
+        self.threshold > 0 && *count == self.threshold
+    }
+}
@@ -0,0 +1,22 @@
+use super::*;
+
+#[test]
+fn report_once() {
+    let orphans = Orphans::new(2);
+    assert!(!orphans.missing("foo"));
+    assert!(orphans.missing("foo"));
+    assert!(!orphans.missing("foo"));
+
+    // Finding it again starts over
+    orphans.found("foo");
+    assert!(!orphans.missing("foo"));
+    assert!(orphans.missing("foo"));
+}
+
+#[test]
+fn disabled_reports() {
+    let orphans = Orphans::new(0);
+    for _ in 0..5 {
+        assert!(!orphans.missing("foo"));
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::config;
+
+#[cfg(test)]
+#[path = "package_config_test.rs"]
+mod package_config_test;
+
+/// Placeholder in aur_revision for the AUR version of the update.
+pub const VERSION_PLACEHOLDER: &str = "{version}";
+
+/// Per package overrides read from <package_configs>/<pkgname>.yaml.
+/// They only apply to the package they're named after.
+#[derive(Default, Debug, Deserialize)]
+#[serde(default)]
+pub struct PackageConfig {
+    /// PKGBUILD variables allowed to change in addition to the global ones.
+    pub allowed_pkgbuild_keys: Vec<String>,
+    /// Files allowed to change even if they are checked otherwise,
+    /// relative to the package root.
+    pub allowed_files: Vec<String>,
+    /// Trust the package and skip the diff check.
+    pub disable_pkgcheck: bool,
+    /// Newest version which is allowed to be built.
+    pub max_version: Option<String>,
+    /// GPG fingerprints of which one has to sign the HEAD commit
+    /// of the AUR repository. Commits aren't verified if empty.
+    pub trusted_keys: Vec<String>,
+    /// AUR commit hash or tag to build instead of HEAD. VERSION_PLACEHOLDER
+    /// gets replaced by the AUR version, e.g. v{version} for a tag per version.
+    pub aur_revision: Option<String>,
+    /// Environment variables of the build job, added to
+    /// and overriding the global build_env.
+    pub build_env: HashMap<String, String>,
+    /// dmanager namespace the package is uploaded to
+    /// instead of the global upload_namespace.
+    pub upload_namespace: Option<String>,
+}
+
+impl PackageConfig {
+    /// Load the override of pkg_name from dir. Packages
+    /// without an override file get the defaults.
+    pub fn load(dir: &Path, pkg_name: &str) -> Result<Self, Box<dyn Error>> {
+        let path = dir.join(format!("{}.yaml", pkg_name));
+        if !path.exists() {
+            return Ok(PackageConfig::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let package_config: PackageConfig =
+            serde_yaml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let forbidden = config::forbidden_build_env(&package_config.build_env);
+        if !forbidden.is_empty() {
+            return Err(format!(
+                "{}: build_env can't set {}",
+                path.display(),
+                forbidden.join(", ")
+            )
+            .into());
+        }
+
+        Ok(package_config)
+    }
+
+    /// Returns the AUR revision to build for version, if the package is pinned.
+    pub fn aur_revision(&self, version: &str) -> Option<String> {
+        self.aur_revision
+            .as_ref()
+            .map(|i| i.replace(VERSION_PLACEHOLDER, version))
+    }
+}
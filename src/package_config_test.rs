@@ -0,0 +1,55 @@
+use super::*;
+
+use crate::test_dir::TestDir;
+
+#[test]
+fn load_package_config() {
+    let dir = TestDir::new("package_config");
+    fs::write(
+        dir.join("foo.yaml"),
+        "allowed_pkgbuild_keys: [makedepends]\ndisable_pkgcheck: true\nmax_version: '1.2.0-1'\n",
+    )
+    .unwrap();
+    fs::write(dir.join("broken.yaml"), "allowed_files: true\n").unwrap();
+
+    let config = PackageConfig::load(&dir, "foo").unwrap();
+    assert_eq!(config.allowed_pkgbuild_keys, vec!["makedepends".to_owned()]);
+    assert!(config.allowed_files.is_empty());
+    assert!(config.disable_pkgcheck);
+    assert_eq!(config.max_version.as_deref(), Some("1.2.0-1"));
+
+    let config = PackageConfig::load(&dir, "bar").unwrap();
+    assert!(config.allowed_pkgbuild_keys.is_empty());
+    assert!(!config.disable_pkgcheck);
+    assert!(config.max_version.is_none());
+
+    assert!(PackageConfig::load(&dir, "broken").is_err());
+}
+
+#[test]
+fn pinned_aur_revision() {
+    let config = PackageConfig::default();
+    assert_eq!(config.aur_revision("1.0-1"), None);
+
+    let config = PackageConfig {
+        aur_revision: Some("v{version}".to_owned()),
+        ..PackageConfig::default()
+    };
+    assert_eq!(config.aur_revision("1.0-1").as_deref(), Some("v1.0-1"));
+}
+
+#[test]
+fn load_build_job_settings() {
+    let dir = TestDir::new("package_build_env");
+    fs::write(
+        dir.join("foo.yaml"),
+        "build_env:\n  MAKEFLAGS: -j4\nupload_namespace: games\n",
+    )
+    .unwrap();
+    fs::write(dir.join("bar.yaml"), "build_env:\n  PKGDEST: /tmp\n").unwrap();
+
+    let config = PackageConfig::load(&dir, "foo").unwrap();
+    assert_eq!(config.build_env["MAKEFLAGS"], "-j4");
+    assert_eq!(config.upload_namespace.as_deref(), Some("games"));
+    assert!(PackageConfig::load(&dir, "bar").is_err());
+}
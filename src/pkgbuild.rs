@@ -0,0 +1,420 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+#[cfg(test)]
+#[path = "pkgbuild_test.rs"]
+mod pkgbuild_test;
+
+/// A top level statement of a PKGBUILD.
+#[derive(Debug, PartialEq)]
+pub enum Item {
+    /// name=value or name+=value. Arrays are joined into a single line.
+    Assignment { name: String, value: String },
+    /// A function with its normalized body, starting after the opening brace.
+    Function { name: String, body: String },
+    /// Any other statement, e.g. a conditional or an export.
+    Command(String),
+}
+
+/// A semantic difference between two PKGBUILDs.
+#[derive(Debug, PartialEq)]
+pub enum Change<'a> {
+    /// A variable got added, changed or removed (new is None).
+    Variable {
+        name: &'a str,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// A function got added or its body changed.
+    Function(&'a str),
+    /// A command got added.
+    Command(&'a str),
+}
+
+/// Result of scanning a line for unquoted syntax.
+#[derive(Debug, Default, PartialEq)]
+pub struct Scan {
+    /// Change of the parenthesis depth.
+    pub parens: i32,
+    /// Change of the brace depth.
+    pub braces: i32,
+    /// Position of a trailing comment.
+    pub comment: Option<usize>,
+    /// Delimiter of a here-doc started in the line.
+    pub heredoc: Option<String>,
+}
+
+/// Split a PKGBUILD into its top level assignments, functions and commands.
+/// Comments are ignored, here-docs are kept as part of their statement.
+pub fn parse(src: &str) -> Vec<Item> {
+    let src = unwrap_multi_line(src);
+    let mut lines = src.lines();
+    let mut items = Vec::new();
+
+    while let Some(line) = lines.next() {
+        let scan = scan_line(line);
+        let line = match scan.comment {
+            Some(pos) => &line[..pos],
+            None => line,
+        }
+        .trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = function_name(line) {
+            let mut body = vec![line.to_owned()];
+            let mut opened = line.contains('{');
+            let mut depth = scan.braces;
+            let mut heredoc = scan.heredoc;
+
+            // Collect lines until the braces of the function are balanced
+            while !opened || depth > 0 {
+                let line = match lines.next() {
+                    Some(line) => line,
+                    None => break,
+                };
+
+                if let Some(ref delimiter) = heredoc {
+                    if line.trim() == delimiter {
+                        heredoc = None;
+                    }
+                    body.push(line.to_owned());
+                    continue;
+                }
+
+                let scan = scan_line(line);
+                let line = match scan.comment {
+                    Some(pos) => &line[..pos],
+                    None => line,
+                }
+                .trim();
+
+                if line.is_empty() {
+                    continue;
+                }
+
+                opened |= line.contains('{');
+                depth += scan.braces;
+                heredoc = scan.heredoc;
+                body.push(line.to_owned());
+            }
+
+            // Whether the brace is on the header line doesn't matter
+            let body = body.join("\n");
+            let body = match body.find('{') {
+                Some(pos) => body[pos + 1..].trim().to_owned(),
+                None => body,
+            };
+            items.push(Item::Function { name, body });
+            continue;
+        }
+
+        let mut statement = line.to_owned();
+        if let Some(delimiter) = scan.heredoc {
+            for line in &mut lines {
+                statement.push('\n');
+                statement.push_str(line);
+                if line.trim() == delimiter {
+                    break;
+                }
+            }
+            items.push(Item::Command(statement));
+            continue;
+        }
+
+        items.extend(
+            split_statements(&statement)
+                .into_iter()
+                .map(parse_statement),
+        );
+    }
+
+    items
+}
+
+/// Compare two parsed PKGBUILDs. Removed functions and commands
+/// aren't reported, everything else which changed is.
+pub fn diff<'a>(old: &'a [Item], new: &'a [Item]) -> Vec<Change<'a>> {
+    let (old_vars, old_funcs, old_cmds) = split_items(old);
+    let (new_vars, new_funcs, new_cmds) = split_items(new);
+    let mut changes = Vec::new();
+
+    for (name, value) in &new_vars {
+        let old_value = old_vars.get(name);
+        if old_value != Some(value) {
+            changes.push(Change::Variable {
+                name,
+                old: old_value.map(|i| i.join("; ")),
+                new: Some(value.join("; ")),
+            });
+        }
+    }
+
+    for (name, value) in &old_vars {
+        if !new_vars.contains_key(name) {
+            changes.push(Change::Variable {
+                name,
+                old: Some(value.join("; ")),
+                new: None,
+            });
+        }
+    }
+
+    for (name, body) in &new_funcs {
+        if old_funcs.get(name) != Some(body) {
+            changes.push(Change::Function(name));
+        }
+    }
+
+    for cmd in new_cmds {
+        if !old_cmds.contains(&cmd) {
+            changes.push(Change::Command(cmd));
+        }
+    }
+
+    changes
+}
+
+type Items<'a> = (
+    BTreeMap<&'a str, Vec<&'a str>>,
+    BTreeMap<&'a str, &'a str>,
+    Vec<&'a str>,
+);
+
+/// Group items by their kind. Multiple assignments
+/// of the same variable are kept in order.
+fn split_items(items: &[Item]) -> Items<'_> {
+    let mut vars: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut funcs = BTreeMap::new();
+    let mut cmds = Vec::new();
+
+    for item in items {
+        match item {
+            Item::Assignment { name, value } => vars.entry(name).or_default().push(value),
+            Item::Function { name, body } => {
+                funcs.insert(name.as_str(), body.as_str());
+            }
+            Item::Command(cmd) => cmds.push(cmd.as_str()),
+        }
+    }
+
+    (vars, funcs, cmds)
+}
+
+/// Returns the name of the function declared in line.
+fn function_name(line: &str) -> Option<String> {
+    let re =
+        Regex::new(r"^(?:function\s+)?([A-Za-z_][A-Za-z0-9_-]*)\s*\(\s*\)\s*(?:\{.*)?$").unwrap();
+    let function = Regex::new(r"^function\s+([A-Za-z_][A-Za-z0-9_-]*)\s*(?:\{.*)?$").unwrap();
+
+    re.captures(line)
+        .or_else(|| function.captures(line))
+        .map(|i| i[1].to_owned())
+}
+
+/// Parse a single statement as assignment or command.
+fn parse_statement(statement: &str) -> Item {
+    let re = Regex::new(r"^([A-Za-z_][A-Za-z0-9_]*)\+?=(.*)$").unwrap();
+
+    match re.captures(statement) {
+        Some(caps) => {
+            let value = caps[2].trim();
+
+            // Spaces at the borders of arrays are left from joined lines
+            let value = if value.starts_with('(') && value.ends_with(')') && value.len() > 1 {
+                format!("({})", value[1..value.len() - 1].trim())
+            } else {
+                value.to_owned()
+            };
+
+            Item::Assignment {
+                name: caps[1].to_owned(),
+                value,
+            }
+        }
+        None => Item::Command(statement.to_owned()),
+    }
+}
+
+/// Split line at unquoted semicolons outside of parentheses.
+fn split_statements(line: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut quoting = Quoting::default();
+
+    for (i, c) in line.char_indices() {
+        if !quoting.next(c) {
+            continue;
+        }
+
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ';' if depth == 0 && !line[i..].starts_with(";;") => {
+                statements.push(line[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    statements.push(line[start..].trim());
+    statements.retain(|i| !i.is_empty());
+    statements
+}
+
+/// Join arrays spanning multiple lines and lines continued with a
+/// backslash into single lines and collapse repeated spaces. This
+/// keeps the normalized form of an assignment independent of how
+/// it's wrapped. Comments inside of arrays are removed, here-docs
+/// are kept as they are.
+pub fn unwrap_multi_line(src: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut joining = false;
+    let mut heredoc: Option<String> = None;
+
+    for line in src.trim().lines() {
+        if let Some(ref delimiter) = heredoc {
+            if line.trim() == delimiter {
+                heredoc = None;
+            }
+            lines.push(line.to_owned());
+            continue;
+        }
+
+        let scan = scan_line(line);
+        let line = match scan.comment {
+            Some(pos) if depth > 0 || depth + scan.parens > 0 => &line[..pos],
+            _ => line,
+        };
+
+        if joining {
+            current.push(' ');
+            current.push_str(line.trim());
+        } else {
+            current.push_str(line);
+        }
+        depth += scan.parens;
+
+        if current.trim_end().ends_with('\\') {
+            current.truncate(current.trim_end().len() - 1);
+            joining = true;
+        } else if depth > 0 && scan.heredoc.is_none() {
+            joining = true;
+        } else {
+            lines.push(std::mem::take(&mut current));
+            depth = 0;
+            joining = false;
+            heredoc = scan.heredoc;
+        }
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    Regex::new("[ ]+")
+        .unwrap()
+        .replace_all(lines.join("\n").as_str(), " ")
+        .to_string()
+}
+
+/// Scan line for unquoted parentheses, braces, comments and here-docs.
+pub fn scan_line(line: &str) -> Scan {
+    let mut scan = Scan::default();
+    let mut quoting = Quoting::default();
+    let mut prev = ' ';
+
+    for (i, c) in line.char_indices() {
+        let word_start = quoting.word_start;
+        if quoting.next(c) {
+            match c {
+                '(' => scan.parens += 1,
+                ')' => scan.parens -= 1,
+                '{' => scan.braces += 1,
+                '}' => scan.braces -= 1,
+                '#' if word_start => {
+                    scan.comment = Some(i);
+                    break;
+                }
+                // <<< is a here-string
+                '<' if scan.heredoc.is_none() && prev != '<' => {
+                    scan.heredoc = heredoc_delimiter(&line[i..])
+                }
+                _ => {}
+            }
+        }
+        prev = c;
+    }
+
+    scan
+}
+
+/// Quoting tracks the quotes and escapes of a line char by char.
+struct Quoting {
+    /// The open quote, $ for ANSI-C quotes like $'a\'b'.
+    quote: Option<char>,
+    escaped: bool,
+    /// The next char starts a word. Only then # starts a comment.
+    word_start: bool,
+    /// The previous char was an unquoted $.
+    dollar: bool,
+}
+
+impl Default for Quoting {
+    fn default() -> Self {
+        Quoting {
+            quote: None,
+            escaped: false,
+            word_start: true,
+            dollar: false,
+        }
+    }
+}
+
+impl Quoting {
+    /// Advance by c. Returns true if c is neither quoted nor escaped.
+    fn next(&mut self, c: char) -> bool {
+        let dollar = self.dollar;
+        self.word_start = false;
+        self.dollar = false;
+
+        if self.escaped {
+            self.escaped = false;
+            return false;
+        }
+
+        match (self.quote, c) {
+            (Some('\''), '\'') => self.quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => self.escaped = true,
+            (Some('$'), '\'') => self.quote = None,
+            (Some('"'), '"') => self.quote = None,
+            (Some(_), _) => {}
+            (None, '\'') if dollar => self.quote = Some('$'),
+            (None, '\'') | (None, '"') => self.quote = Some(c),
+            (None, _) => {
+                self.word_start = c.is_whitespace();
+                self.dollar = c == '$';
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Returns the delimiter if s starts a here-doc like <<EOF or <<-'EOF'.
+fn heredoc_delimiter(s: &str) -> Option<String> {
+    let re = Regex::new(r#"^<<-?\s*(?:'(\w+)'|"(\w+)"|([A-Za-z_]\w*))"#).unwrap();
+
+    re.captures(s)
+        .and_then(|i| i.iter().skip(1).flatten().next())
+        .map(|i| i.as_str().to_owned())
+}
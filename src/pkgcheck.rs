@@ -1,16 +1,20 @@
 #![allow(dead_code)]
 
+use std::cmp::Ordering;
 use std::error::Error;
 use std::fs::{self, File};
 use std::io::{self, prelude::*};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicUsize};
 
-use md5;
+use log::{debug, info, warn};
+use rayon::prelude::*;
 use regex::Regex;
-use tokio::process::Command;
-use tree_magic;
+use sha2::{Digest, Sha256};
 
 use crate::dir_diff;
+use crate::pkgbuild::{self, unwrap_multi_line, Change};
+use crate::version;
 
 #[cfg(test)]
 #[path = "pkgcheck_test.rs"]
@@ -24,11 +28,48 @@ mod pkgcheck_test;
 pub struct Check<'a> {
     folder_left: &'a Path,
     folder_right: &'a Path,
+    name: &'a str,
+    allowed_changes: Vec<String>,
+    allowed_install_files: Vec<String>,
+    allowed_files: Vec<String>,
+    suspicious_patterns: &'a [Regex],
+    utf8_mimes: Vec<String>,
+    allowed_mimes: Vec<String>,
+    max_binary_size: Option<u64>,
+    reject_removal_of: Vec<String>,
+}
+
+/// Result of checking the files of an update.
+#[derive(Debug, PartialEq)]
+pub enum FileCheck {
+    /// All changes are allowed.
+    Passed,
+    /// The update doesn't change any file.
+    Unchanged,
+    /// The update contains an illegal change of file,
+    /// the path relative to the package directories.
+    Illegal { file: PathBuf, reason: String },
+}
+
+/// Result of checking a single pair of files.
+#[derive(Debug)]
+enum PairCheck {
+    Unchanged,
+    Changed,
+    Illegal(String),
+}
+
+fn changed_if(changed: bool) -> PairCheck {
+    if changed {
+        PairCheck::Changed
+    } else {
+        PairCheck::Unchanged
+    }
 }
 
 /// All PKGBUILD changes's prefixes which are allowed
 /// to be changed with updates
-const ALLOWED_CHANGES: &'static [&'static str] = &[
+const ALLOWED_CHANGES: &[&str] = &[
     "license",
     "pkgver",
     "pkgrel",
@@ -47,11 +88,36 @@ const ALLOWED_CHANGES: &'static [&'static str] = &[
     "_pkgname",
 ];
 
+/// PKGBUILD variables which must not be removed with updates, as
+/// that disables integrity or signature checks. Architecture
+/// specific variants like sha256sums_x86_64 are included.
+const REJECT_REMOVAL_OF: &[&str] = &[
+    "md5sums",
+    "sha1sums",
+    "sha224sums",
+    "sha256sums",
+    "sha384sums",
+    "sha512sums",
+    "b2sums",
+    "validpgpkeys",
+];
+
+/// Patterns of commands which are not allowed to be
+/// added with updates
+const SUSPICIOUS_PATTERNS: &[&str] = &[
+    r"(curl|wget)[^|]*\|\s*(sudo\s+)?(ba|z)?sh",
+    r"\beval\b",
+    r"base64\s+(-d|--decode)",
+    r">\s*/etc/",
+    r"(>|tee\s+(-a\s+)?)\s*(\$HOME|\$\{HOME\}|~)/",
+    r"\.bashrc",
+];
+
 /// All MIMES which are allowed to be changed in updates.
-const ALLOWED_MIMES: &'static [&'static str] = &["image/"];
+const ALLOWED_MIMES: &[&str] = &["image/"];
 
 /// All MIMES which will be go through diff checks
-const UTF8_MIMES: &'static [&'static str] = &[
+const UTF8_MIMES: &[&str] = &[
     "text/",
     "application/x-shellscript",
     "application/x-desktop",
@@ -60,95 +126,520 @@ const UTF8_MIMES: &'static [&'static str] = &[
     "application/json",
 ];
 
+/// Name of the build script, which is compared semantically.
+const PKGBUILD: &str = "PKGBUILD";
+
+/// Size of the chunks files are hashed in.
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Amount of bytes the mime of a file is detected from.
+const MIME_PREFIX_SIZE: u64 = 8 * 1024;
+
 impl<'a> Check<'a> {
     /// Create a new check
-    pub fn new(folder_left: &'a Path, folder_right: &'a Path) -> Self {
+    pub fn new(
+        folder_left: &'a Path,
+        folder_right: &'a Path,
+        allowed_changes: Vec<String>,
+    ) -> Self {
         Check {
             folder_left,  // folder_left is the local git version
             folder_right, // folder_right is the remote version
+            name: "",
+            allowed_changes,
+            allowed_install_files: Vec::new(),
+            allowed_files: Vec::new(),
+            suspicious_patterns: &[],
+            utf8_mimes: utf8_mimes(&None),
+            allowed_mimes: allowed_mimes(&None),
+            max_binary_size: None,
+            reject_removal_of: reject_removal_of(&None),
         }
     }
 
+    /// Set the package name log messages are prefixed with.
+    pub fn with_name(mut self, name: &'a str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Reject added lines matching any of the given patterns.
+    pub fn with_suspicious_patterns(mut self, patterns: &'a [Regex]) -> Self {
+        self.suspicious_patterns = patterns;
+        self
+    }
+
+    /// Allow changes to the given .install files.
+    pub fn with_allowed_install_files(mut self, files: Vec<String>) -> Self {
+        self.allowed_install_files = files;
+        self
+    }
+
+    /// Diff files matching utf8_mimes and allow binary files
+    /// matching allowed_mimes to change. Both match by prefix.
+    pub fn with_mimes(mut self, utf8_mimes: Vec<String>, allowed_mimes: Vec<String>) -> Self {
+        self.utf8_mimes = utf8_mimes;
+        self.allowed_mimes = allowed_mimes;
+        self
+    }
+
+    /// Reject changed binary files bigger than size bytes. 0 disables the limit.
+    pub fn with_max_binary_size(mut self, size: u64) -> Self {
+        self.max_binary_size = Some(size).filter(|i| *i > 0);
+        self
+    }
+
+    /// Reject updates removing any of the given PKGBUILD variables.
+    pub fn with_reject_removal_of(mut self, keys: Vec<String>) -> Self {
+        self.reject_removal_of = keys;
+        self
+    }
+
+    /// Allow any changes to the given files, relative to the package root.
+    pub fn with_allowed_files(mut self, files: Vec<String>) -> Self {
+        self.allowed_files = files;
+        self
+    }
+
     /// Check if there are new files in the AUR version
     pub fn are_dirs_different(&self) -> bool {
-        if let Some(site) = dir_diff::is_different(self.folder_left, self.folder_right).unwrap() {
-            return site == dir_diff::Site::Right;
-        }
-        false
+        self.dir_differences()
+            .unwrap()
+            .iter()
+            .any(|i| i.site == dir_diff::Site::Right)
+    }
+
+    /// Returns all differences between the file trees of the
+    /// git version and the AUR version.
+    pub fn dir_differences(&self) -> Result<Vec<dir_diff::DirDiffEntry>, dir_diff::Error> {
+        dir_diff::is_different(self.folder_left, self.folder_right)
     }
 
     /// Check all files by comparing the differences of the git version and the
-    /// new AUR package version.
-    pub fn check_files(&self, check_diff: bool) -> Result<bool, Box<dyn Error>> {
+    /// new AUR package version. The files are compared in parallel, the result
+    /// is the one of the first illegal change in path order nonetheless.
+    pub fn check_files(&self, check_diff: bool) -> Result<FileCheck, Box<dyn Error>> {
+        // Pair up all git files and the corresponding updated files
+        let pairs = dir_diff::pair_entries(self.folder_left, self.folder_right)?;
+
+        // Index of the first rejected pair found so far. Pairs behind it
+        // are skipped, pairs in front of it still have to be checked.
+        let first_rejected = AtomicUsize::new(usize::MAX);
+
+        let results: Vec<Option<Result<PairCheck, io::Error>>> = pairs
+            .par_iter()
+            .enumerate()
+            .map(|(i, pair)| {
+                if i > first_rejected.load(atomic::Ordering::Relaxed) {
+                    return None;
+                }
+
+                let res = self.check_pair(pair, check_diff);
+                if matches!(res, Ok(PairCheck::Illegal(_)) | Err(_)) {
+                    first_rejected.fetch_min(i, atomic::Ordering::Relaxed);
+                }
+                Some(res)
+            })
+            .collect();
+
         let mut had_diff = false;
+        for (pair, res) in pairs.iter().zip(results) {
+            match res.transpose()? {
+                None | Some(PairCheck::Unchanged) => {}
+                Some(PairCheck::Changed) => had_diff = true,
+                Some(PairCheck::Illegal(reason)) => {
+                    return Ok(FileCheck::Illegal {
+                        file: pair.path.clone(),
+                        reason,
+                    })
+                }
+            }
+        }
 
-        // Zip up all git files and the corresponding updated files
-        for (a, b) in dir_diff::walk_dir(self.folder_left)?
-            .filter_entry(dir_diff::git_filter_entries)
-            .zip(dir_diff::walk_dir(self.folder_right)?.filter_entry(dir_diff::git_filter_entries))
-        {
-            let a = a?; // local file
-            let b = b?; // remote file
+        if !had_diff {
+            info!("{}: No change detected!", self.name);
+            return Ok(FileCheck::Unchanged);
+        }
 
-            if a.file_type().is_dir() || b.file_type().is_dir() {
-                continue;
+        Ok(FileCheck::Passed)
+    }
+
+    /// Compare a single git file with the corresponding updated file.
+    fn check_pair(&self, pair: &dir_diff::Pair, check_diff: bool) -> Result<PairCheck, io::Error> {
+        let (a, b) = match (&pair.left, &pair.right) {
+            (Some(a), Some(b)) => (a, b), // local file, remote file
+            (None, Some(_)) => {
+                // New files are forbidden
+                if check_diff {
+                    return Ok(self.illegal(format!("Added '{}'", pair.path.display())));
+                }
+                return Ok(PairCheck::Changed);
+            }
+            // Files only available in the git version are kept
+            _ => return Ok(PairCheck::Unchanged),
+        };
+
+        if a.file_type() != b.file_type() {
+            if check_diff {
+                return Ok(self.illegal(format!("Changed type of '{}'", pair.path.display())));
+            }
+            return Ok(PairCheck::Changed);
+        }
+
+        // Symlinks are compared by their targets instead of their contents
+        if b.path_is_symlink() {
+            let target = dir_diff::link_target(b);
+            if dir_diff::link_target(a) == target {
+                return Ok(PairCheck::Unchanged);
+            }
+
+            let outside = match target {
+                Some(target) => dir_diff::is_link_outside(&pair.path, &target),
+                None => true,
             };
+            if check_diff && outside {
+                return Ok(self.illegal(format!(
+                    "Symlink '{}' points outside of the package",
+                    pair.path.display()
+                )));
+            }
+            return Ok(PairCheck::Changed);
+        }
 
-            let mime = get_mime(b.path())?;
-            if partial_contains(UTF8_MIMES, mime) {
-                println!("utf8-mime: {}", mime);
-                let a_content = parse_src_file(fs::read_to_string(a.path())?);
-                let b_content = parse_src_file(fs::read_to_string(b.path())?);
+        if a.file_type().is_dir() {
+            return Ok(PairCheck::Unchanged);
+        };
 
-                //  Build diff from both file contents
-                let diff = diff::lines(a_content.as_str(), b_content.as_str());
-                if !is_diff_empty(&diff) {
-                    had_diff = true;
+        // Explicitly allowed files may change freely
+        if self.is_file_allowed(&pair.path) {
+            return Ok(changed_if(!hash_file_diff(a.path(), b.path())?));
+        }
+
+        // The mimes are detected from the start of the files,
+        // only text files get read entirely
+        let (a_prefix, b_prefix) = (read_prefix(a.path())?, read_prefix(b.path())?);
+        let mime = get_mime(&b_prefix);
+
+        // Replacing a script with a binary would bypass the diff checks.
+        // Empty files have no mime worth comparing.
+        let old_mime = get_mime(&a_prefix);
+        if partial_contains(&self.utf8_mimes, old_mime) != partial_contains(&self.utf8_mimes, mime)
+            && !a_prefix.is_empty()
+            && !b_prefix.is_empty()
+        {
+            if check_diff {
+                return Ok(self.illegal(format!(
+                    "Changed '{}' from {} to {}",
+                    pair.path.display(),
+                    old_mime,
+                    mime
+                )));
+            }
+            return Ok(PairCheck::Changed);
+        }
+
+        if partial_contains(&self.utf8_mimes, mime) {
+            debug!("{}: utf8-mime: {}", self.name, mime);
+            let (a_src, b_src) = (
+                into_string(fs::read(a.path())?)?,
+                into_string(fs::read(b.path())?)?,
+            );
+            let (a_src, b_src) = (normalize_whitespace(&a_src), normalize_whitespace(&b_src));
+            let a_content = parse_src_file(a_src.clone());
+            let b_content = parse_src_file(b_src.clone());
+
+            //  Build diff from both file contents
+            let diff = diff::lines(a_content.as_str(), b_content.as_str());
+            let changed = changed_if(!is_diff_empty(&diff));
+
+            // .install files can run arbitrary code and must not change
+            // unless explicitly allowed. They are compared as they are,
+            // code must not hide behind anything taken for a comment.
+            let file_name = a.file_name().to_str().unwrap();
+            if is_install_file(file_name) {
+                let has_diff = has_changes(&diff_lines(&a_src, &b_src));
+                if check_diff && has_diff && !self.is_install_file_allowed(file_name) {
+                    return Ok(self.illegal(format!("Changed install file '{}'", file_name)));
                 }
+                return Ok(changed_if(has_diff));
+            }
 
-                // Check and validate the upgraded package
-                if check_diff && !Self::check_diff(diff, a.file_name().to_str().unwrap()) {
-                    return Ok(false);
+            // PKGBUILDs are compared by their variables and functions
+            if check_diff && file_name == PKGBUILD {
+                if let Err(reason) = self.check_pkgbuild(&a_src, &b_src) {
+                    return Ok(PairCheck::Illegal(format!(
+                        "Illegal change in '{}': {}",
+                        pair.path.display(),
+                        reason
+                    )));
                 }
-            } else {
-                println!("Non utf8-mime: {}", mime);
-                let has_diff = hash_file_diff(&a.path(), &b.path())?;
+                return Ok(changed);
+            }
+
+            // Check and validate the upgraded package
+            if check_diff && !self.check_diff(diff, file_name) {
+                return Ok(PairCheck::Illegal(format!(
+                    "Illegal change in '{}'",
+                    pair.path.display()
+                )));
+            }
+
+            Ok(changed)
+        } else {
+            debug!("{}: Non utf8-mime: {}", self.name, mime);
+
+            // Allowed mimes must not be used to smuggle in big blobs. The
+            // size is taken from the metadata, blobs which got too big are
+            // rejected before anything of them is read.
+            let (a_size, b_size) = (fs::metadata(a.path())?.len(), fs::metadata(b.path())?.len());
+            let too_big = matches!(self.max_binary_size, Some(max) if check_diff && b_size > max);
+            if too_big && a_size != b_size {
+                return Ok(self.binary_too_big(&pair.path, b_size));
+            }
+
+            let has_diff = !hash_file_diff(a.path(), b.path())?;
+            if check_diff && !partial_contains(&self.allowed_mimes, mime) && has_diff {
+                // Throw error if mime doesn't allow changing
+                return Ok(self.illegal(format!("Hashsum check failed: {}", pair.path.display())));
+            }
+
+            if too_big && has_diff {
+                return Ok(self.binary_too_big(&pair.path, b_size));
+            }
+
+            Ok(changed_if(has_diff))
+        }
+    }
+
+    /// Reject a changed binary file exceeding max_binary_size.
+    fn binary_too_big(&self, path: &Path, size: u64) -> PairCheck {
+        self.illegal(format!(
+            "Changed binary file '{}' has {} bytes, the limit is {}",
+            path.display(),
+            size,
+            self.max_binary_size.unwrap_or_default()
+        ))
+    }
+
+    /// Log an illegal change and return it as check result.
+    fn illegal(&self, reason: String) -> PairCheck {
+        warn!("{}: {} -> Illegal change", self.name, reason);
+        PairCheck::Illegal(reason)
+    }
+
+    /// Create a human readable summary of all changes between
+    /// the git version and the new AUR package version.
+    pub fn diff_summary(&self) -> Result<String, Box<dyn Error>> {
+        let mut summary = String::new();
 
-                if check_diff && !partial_contains(ALLOWED_MIMES, mime) && has_diff {
-                    // Throw error if mime doesn't allow changing
-                    println!("Hashsum check failed: {}", b.path().display());
-                    return Ok(false);
+        for pair in dir_diff::pair_entries(self.folder_left, self.folder_right)? {
+            let file_name = pair.path.display();
+
+            let (a, b) = match (pair.left, pair.right) {
+                (Some(a), Some(b)) => (a, b), // local file, remote file
+                (None, Some(_)) => {
+                    summary.push_str(format!("{}: new file\n", file_name).as_str());
+                    continue;
                 }
+                _ => continue,
+            };
+
+            if a.file_type() != b.file_type() {
+                summary.push_str(format!("{}: file type changed\n", file_name).as_str());
+                continue;
+            }
 
-                if has_diff {
-                    had_diff = true;
+            if b.path_is_symlink() {
+                let a_target = dir_diff::link_target(&a).unwrap_or_default();
+                let b_target = dir_diff::link_target(&b).unwrap_or_default();
+                if a_target != b_target {
+                    summary.push_str(
+                        format!(
+                            "{}: symlink changed {} -> {}\n",
+                            file_name,
+                            a_target.display(),
+                            b_target.display()
+                        )
+                        .as_str(),
+                    );
                 }
+                continue;
+            }
+
+            if a.file_type().is_dir() {
+                continue;
+            };
+
+            if partial_contains(&self.utf8_mimes, get_mime(&read_prefix(b.path())?)) {
+                let a_content = parse_src_file(into_string(fs::read(a.path())?)?);
+                let b_content = parse_src_file(into_string(fs::read(b.path())?)?);
+
+                let diff = diff::lines(a_content.as_str(), b_content.as_str());
+                if is_diff_empty(&diff) {
+                    continue;
+                }
+
+                summary.push_str(format!("{}:\n", file_name).as_str());
+                for d in diff {
+                    match d {
+                        diff::Result::Left(l) => summary.push_str(format!("-{}\n", l).as_str()),
+                        diff::Result::Right(r) => summary.push_str(format!("+{}\n", r).as_str()),
+                        diff::Result::Both(_, _) => {}
+                    }
+                }
+            } else if !hash_file_diff(a.path(), b.path())? {
+                summary.push_str(format!("{}: binary file changed\n", file_name).as_str());
             }
         }
 
-        if !had_diff {
-            println!("No change detected!");
-            return Ok(false);
+        Ok(summary)
+    }
+
+    /// Render the changes of a single file as unified diff. Returns
+    /// None for unchanged files and files which aren't UTF-8.
+    pub fn file_diff(
+        &self,
+        file: &Path,
+        context: usize,
+        color: bool,
+    ) -> Result<Option<String>, io::Error> {
+        // Added files are diffed against an empty one. Only
+        // files with a utf8 mime get read entirely.
+        let is_text = |prefix: &[u8]| {
+            prefix.is_empty() || partial_contains(&self.utf8_mimes, get_mime(prefix))
+        };
+        let read = |path: PathBuf| match read_prefix(&path) {
+            Ok(prefix) if !is_text(&prefix) => Ok(None),
+            Ok(_) => Ok(String::from_utf8(fs::read(path)?).ok()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Some(String::new())),
+            Err(e) => Err(e),
+        };
+
+        let (a, b) = match (
+            read(self.folder_left.join(file))?,
+            read(self.folder_right.join(file))?,
+        ) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Ok(None),
+        };
+
+        let diff = diff_lines(&a, &b);
+        if !has_changes(&diff) {
+            return Ok(None);
+        }
+
+        Ok(Some(unified_diff(&diff, file, context, color)))
+    }
+
+    fn is_removal_rejected(&self, name: &str) -> bool {
+        self.reject_removal_of
+            .iter()
+            .any(|i| name == i || name.starts_with(&format!("{}_", i)))
+    }
+
+    fn is_install_file_allowed(&self, file: &str) -> bool {
+        self.allowed_install_files.iter().any(|i| i == file)
+    }
+
+    fn is_file_allowed(&self, path: &Path) -> bool {
+        self.allowed_files.iter().any(|i| Path::new(i) == path)
+    }
+
+    /// Compare the assignments, functions and commands of two PKGBUILDs.
+    /// Returns the first illegal change.
+    fn check_pkgbuild(&self, old: &str, new: &str) -> Result<(), String> {
+        let (old, new) = (pkgbuild::parse(old), pkgbuild::parse(new));
+
+        for change in pkgbuild::diff(&old, &new) {
+            let reason = match change {
+                Change::Variable {
+                    name, new: None, ..
+                } if self.is_removal_rejected(name) => {
+                    format!("removed {}", name)
+                }
+                Change::Variable { new: None, .. } => continue,
+                Change::Variable {
+                    name,
+                    old,
+                    new: Some(value),
+                } => {
+                    let line = format!("{}={}", name, value);
+                    if let Some(p) = self.suspicious_patterns.iter().find(|p| p.is_match(&line)) {
+                        format!("{} matches suspicious pattern '{}'", name, p)
+                    } else if has_command_substitution(&value) {
+                        format!("changed {} to command substitution {}", name, value)
+                    } else if self.allowed_changes.iter().any(|i| i == name)
+                        || name.starts_with('_')
+                    {
+                        debug!(
+                            "{}: {} changed from {:?} to {}",
+                            self.name, name, old, value
+                        );
+                        continue;
+                    } else {
+                        format!("changed {} to {}", name, value)
+                    }
+                }
+                Change::Function(name) => format!("changed function {}()", name),
+                Change::Command(cmd) => format!("added command '{}'", cmd),
+            };
+
+            warn!("{}: PKGBUILD {} -> Illegal change", self.name, reason);
+            return Err(reason);
         }
 
-        Ok(true)
+        Ok(())
     }
 
     /// Returns false if the AUR file contains illegal changes
-    fn check_diff(res: Vec<diff::Result<&str>>, file: &str) -> bool {
+    fn check_diff(&self, res: Vec<diff::Result<&str>>, file: &str) -> bool {
+        // Changed variables show up as removed and added line
+        let added: Vec<&str> = res
+            .iter()
+            .filter_map(|i| match i {
+                diff::Result::Right(r) => Some(variable_name(r)),
+                _ => None,
+            })
+            .collect();
+
+        for diff in res.iter() {
+            if let diff::Result::Left(l) = diff {
+                let name = variable_name(l);
+                if !added.contains(&name) && self.is_removal_rejected(name) {
+                    warn!(
+                        "{}: Removed '{}' -> Illegal change in {}",
+                        self.name, name, file
+                    );
+                    return false;
+                }
+            }
+        }
+
         // Go through every created diff
         for diff in res {
             if let diff::Result::Right(r) = diff {
+                // Dangerous commands are forbidden, even in allowed variables
+                if let Some(p) = self.suspicious_patterns.iter().find(|p| p.is_match(r)) {
+                    warn!(
+                        "{}: Changed '{}' matches suspicious pattern '{}' -> Illegal change in {}",
+                        self.name, r, p, file
+                    );
+                    return false;
+                }
+
                 // All non-variable changes are forbidden
                 if !r.contains("=") {
-                    eprintln!("Changed '{}' Which has no '=' -> Illegal change", r);
+                    warn!(
+                        "{}: Changed '{}' Which has no '=' -> Illegal change",
+                        self.name, r
+                    );
                     return false;
                 }
 
                 let s = r.split("=").nth(0).unwrap();
                 // Check if the variable update is allowed. Custom variables are allowed
-                if !ALLOWED_CHANGES.contains(&s) && !s.starts_with("_") {
-                    eprintln!("Found '{}' -> Illegal change in {}", s, file);
+                if !self.allowed_changes.iter().any(|i| i == s) && !s.starts_with("_") {
+                    warn!("{}: Found '{}' -> Illegal change in {}", self.name, s, file);
                     return false;
                 }
             }
@@ -159,47 +650,166 @@ impl<'a> Check<'a> {
 
     /// Apply changes from aur to own repo
     pub fn apply_changes(&self) -> Result<(), io::Error> {
-        for (a, b) in dir_diff::walk_dir(self.folder_left)?
-            .filter_entry(dir_diff::git_filter_entries)
-            .zip(dir_diff::walk_dir(self.folder_right)?.filter_entry(dir_diff::git_filter_entries))
-        {
-            let a = a?; // local file
-            let b = b?; // remote file
+        for pair in dir_diff::pair_entries(self.folder_left, self.folder_right)? {
+            let b = match pair.right {
+                Some(b) => b, // remote file
+                None => continue,
+            };
+            let a_path = self.folder_left.join(&pair.path); // local file
 
-            // Copy filecontents to own git
-            fs::copy(b.path(), a.path())?;
+            if b.path_is_symlink() {
+                // Recreate the link instead of copying its target
+                remove_file_or_link(&a_path)?;
+                std::os::unix::fs::symlink(fs::read_link(b.path())?, a_path)?;
+            } else if b.file_type().is_dir() {
+                fs::create_dir_all(a_path)?;
+            } else {
+                // Don't write through an existing link
+                if is_symlink(&a_path) {
+                    fs::remove_file(&a_path)?;
+                }
+
+                // Copy filecontents to own git
+                fs::copy(b.path(), a_path)?;
+            }
         }
 
         Ok(())
     }
 
-    pub async fn update_custom_srcinfo(&self) -> Result<(), Box<dyn Error>> {
-        let status = Command::new("sh")
-            .arg("-c")
-            .arg(format!(
-                "pushd \"{}\" > /dev/null; makepkg --printsrcinfo > .SRCINFO",
-                self.folder_left.to_str().unwrap()
-            ))
-            .status()
-            .await?;
+    /// Write the regenerated .SRCINFO of the git version and make sure
+    /// it describes the expected [epoch:]pkgver-pkgrel version.
+    pub fn write_custom_srcinfo(
+        &self,
+        srcinfo: &str,
+        expected_version: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        fs::write(self.folder_left.join(".SRCINFO"), srcinfo)?;
+        match srcinfo_version(srcinfo) {
+            Some(ref version) if version::compare(version, expected_version) == Ordering::Equal => {
+                Ok(())
+            }
+            version => Err(Box::new(crate::error::Error::SrcinfoFailed(format!(
+                "{}: .SRCINFO has version {}, expected {}",
+                self.name,
+                version.as_deref().unwrap_or("none"),
+                expected_version
+            )))),
+        }
+    }
+}
+
+/// Compile the patterns added lines are checked against.
+/// The defaults are used if no custom patterns are set.
+pub fn suspicious_patterns(custom: &Option<Vec<String>>) -> Result<Vec<Regex>, regex::Error> {
+    match custom {
+        Some(patterns) => patterns.iter().map(|i| Regex::new(i)).collect(),
+        None => SUSPICIOUS_PATTERNS.iter().map(|i| Regex::new(i)).collect(),
+    }
+}
 
-        Ok(())
+/// Returns the mimes diffed line by line, custom ones replace the defaults.
+pub fn utf8_mimes(custom: &Option<Vec<String>>) -> Vec<String> {
+    match custom {
+        Some(mimes) => mimes.clone(),
+        None => UTF8_MIMES.iter().map(|i| i.to_string()).collect(),
     }
 }
 
-/// Read file and remove empty lines
+/// Returns the binary mimes allowed to change, custom ones replace the defaults.
+pub fn allowed_mimes(custom: &Option<Vec<String>>) -> Vec<String> {
+    match custom {
+        Some(mimes) => mimes.clone(),
+        None => ALLOWED_MIMES.iter().map(|i| i.to_string()).collect(),
+    }
+}
+
+/// Returns the PKGBUILD variables which must not be removed,
+/// custom ones replace the defaults.
+pub fn reject_removal_of(custom: &Option<Vec<String>>) -> Vec<String> {
+    match custom {
+        Some(keys) => keys.clone(),
+        None => REJECT_REMOVAL_OF.iter().map(|i| i.to_string()).collect(),
+    }
+}
+
+/// Returns the PKGBUILD variables which are allowed to be changed.
+/// Custom variables are added to the defaults or replace them.
+pub fn allowed_changes(custom: &Option<Vec<String>>, replace_defaults: bool) -> Vec<String> {
+    let mut allowed: Vec<String> = Vec::new();
+
+    if !replace_defaults {
+        allowed.extend(ALLOWED_CHANGES.iter().map(|i| i.to_string()));
+    }
+
+    if let Some(custom) = custom {
+        allowed.extend(custom.iter().cloned());
+    }
+
+    allowed
+}
+
+/// Returns the [epoch:]pkgver-pkgrel version described by a .SRCINFO.
+fn srcinfo_version(srcinfo: &str) -> Option<String> {
+    let mut epoch = None;
+    let mut pkgver = None;
+    let mut pkgrel = None;
+
+    for line in srcinfo.lines() {
+        let mut kv = line.splitn(2, '=').map(|i| i.trim());
+        match (kv.next(), kv.next()) {
+            (Some("epoch"), Some(v)) => epoch = Some(v),
+            (Some("pkgver"), Some(v)) => pkgver = Some(v),
+            (Some("pkgrel"), Some(v)) => pkgrel = Some(v),
+            _ => {}
+        }
+    }
+
+    let version = format!("{}-{}", pkgver?, pkgrel?);
+    Some(match epoch {
+        Some(epoch) => format!("{}:{}", epoch, version),
+        None => version,
+    })
+}
+
+/// Returns true if value runs commands when it gets evaluated,
+/// even allowed variables must not do that.
+fn has_command_substitution(value: &str) -> bool {
+    value.contains("$(") || value.contains('`')
+}
+
+/// Read file and remove empty lines and comments
 fn parse_src_file(src: String) -> String {
     let mut s = String::new();
 
-    let src = unwrap_multi_line(&src, "'\n");
+    let src = unwrap_multi_line(&normalize_whitespace(&src));
+    let mut heredoc: Option<String> = None;
 
     for i in src.lines() {
+        // Strip trailing comments, here-doc contents are kept as they are
+        let line = match heredoc {
+            Some(ref delimiter) => {
+                if i.trim() == delimiter {
+                    heredoc = None;
+                }
+                i
+            }
+            None => {
+                let scan = pkgbuild::scan_line(i);
+                heredoc = scan.heredoc;
+                match scan.comment {
+                    Some(pos) => &i[..pos],
+                    None => i,
+                }
+            }
+        };
+
         // Ignore empty lines and comments
-        if i.trim().is_empty() || i.trim().starts_with("#") {
+        if line.trim().is_empty() || line.trim().starts_with("#") {
             continue;
         }
 
-        let m = i.trim().replace(";", ";\n");
+        let m = line.trim().replace(";", ";\n");
         s.push_str(m.as_str());
         s.push('\n');
     }
@@ -207,15 +817,122 @@ fn parse_src_file(src: String) -> String {
     s
 }
 
-/// Handy function to debug the changes.
-fn debug_diff_result<'a>(res: &Vec<diff::Result<&'a str>>) {
-    for diff in res {
-        match diff {
-            diff::Result::Left(l) => println!("-{}", l),
-            diff::Result::Both(l, _) => println!(" {}", l),
-            diff::Result::Right(r) => println!("+{}", r),
+/// Convert CRLF line endings to LF and strip trailing whitespace,
+/// so cosmetic changes of upstream don't show up in diffs.
+fn normalize_whitespace(src: &str) -> String {
+    src.split('\n')
+        .map(|i| i.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render a diff as unified diff of file. Changes closer than twice
+/// context lines are put into the same hunk. The markers and headers
+/// get colored with ANSI escape codes if color is set.
+pub fn unified_diff(
+    res: &[diff::Result<&str>],
+    file: &Path,
+    context: usize,
+    color: bool,
+) -> String {
+    let paint = |code: &str, line: String| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m\n", code, line)
+        } else {
+            format!("{}\n", line)
+        }
+    };
+
+    let changes: Vec<usize> = res
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| !matches!(i, diff::Result::Both(_, _)))
+        .map(|(pos, _)| pos)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Old and new line number in front of each entry
+    let mut positions = Vec::with_capacity(res.len());
+    let (mut old, mut new) = (0, 0);
+    for i in res {
+        positions.push((old, new));
+        match i {
+            diff::Result::Left(_) => old += 1,
+            diff::Result::Right(_) => new += 1,
+            diff::Result::Both(_, _) => {
+                old += 1;
+                new += 1;
+            }
+        }
+    }
+
+    let mut out = paint("1", format!("--- a/{}", file.display()));
+    out.push_str(&paint("1", format!("+++ b/{}", file.display())));
+
+    let mut i = 0;
+    while i < changes.len() {
+        let first = changes[i];
+        while i + 1 < changes.len() && changes[i + 1] - changes[i] <= 2 * context + 1 {
+            i += 1;
+        }
+        let last = changes[i];
+        i += 1;
+
+        let hunk = &res[first.saturating_sub(context)..(last + context + 1).min(res.len())];
+        let (old_start, new_start) = positions[first.saturating_sub(context)];
+        let old_len = hunk
+            .iter()
+            .filter(|i| !matches!(i, diff::Result::Right(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|i| !matches!(i, diff::Result::Left(_)))
+            .count();
+
+        out.push_str(&paint(
+            "36",
+            format!(
+                "@@ -{} +{} @@",
+                hunk_range(old_start, old_len),
+                hunk_range(new_start, new_len)
+            ),
+        ));
+        for d in hunk {
+            out.push_str(&match d {
+                diff::Result::Left(l) => paint("31", format!("-{}", l)),
+                diff::Result::Right(r) => paint("32", format!("+{}", r)),
+                diff::Result::Both(l, _) => format!(" {}\n", l),
+            });
         }
     }
+
+    out
+}
+
+/// Diff the lines of two texts. Unlike diff::lines, a trailing
+/// newline doesn't end in an additional empty line.
+fn diff_lines<'a>(a: &'a str, b: &'a str) -> Vec<diff::Result<&'a str>> {
+    let (a, b): (Vec<&str>, Vec<&str>) = (a.lines().collect(), b.lines().collect());
+    diff::slice(&a, &b)
+        .into_iter()
+        .map(|i| match i {
+            diff::Result::Left(l) => diff::Result::Left(*l),
+            diff::Result::Both(l, r) => diff::Result::Both(*l, *r),
+            diff::Result::Right(r) => diff::Result::Right(*r),
+        })
+        .collect()
+}
+
+/// Returns the range of a hunk header. Empty ranges
+/// start at the line in front of them.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        len => format!("{},{}", start + 1, len),
+    }
 }
 
 fn is_diff_empty(d: &Vec<diff::Result<&str>>) -> bool {
@@ -228,43 +945,97 @@ fn is_diff_empty(d: &Vec<diff::Result<&str>>) -> bool {
     true
 }
 
-fn unwrap_multi_line(a: &str, sub: &str) -> String {
-    Regex::new("[ ]+")
-        .unwrap()
-        .replace_all(String::from(a).trim().replace(sub, "' ").as_str(), " ")
-        .to_string()
+/// Returns true if the diff contains any added or removed line.
+fn has_changes(d: &[diff::Result<&str>]) -> bool {
+    d.iter().any(|i| !matches!(i, diff::Result::Both(_, _)))
+}
+
+/// Returns the name of the variable assigned in line,
+/// or an empty string if it isn't an assignment.
+fn variable_name(line: &str) -> &str {
+    match line.find('=') {
+        Some(pos) => line[..pos].trim(),
+        None => "",
+    }
+}
+
+/// Install files contain scripts being executed on
+/// package installation, removal or upgrade.
+fn is_install_file(file: &str) -> bool {
+    file.ends_with(".install")
 }
 
-fn partial_contains<'b, R>(v: R, has: &str) -> bool
+fn partial_contains<R, S>(v: R, has: &str) -> bool
 where
-    R: IntoIterator<Item = &'b &'b str>,
+    R: IntoIterator<Item = S>,
+    S: AsRef<str>,
 {
     for i in v.into_iter() {
-        if *i == has || has.starts_with(i) {
+        if i.as_ref() == has || has.starts_with(i.as_ref()) {
             return true;
         }
     }
     false
 }
 
-fn get_mime<'b>(path: &'b Path) -> Result<&'b str, io::Error> {
-    let mut buffer = Vec::new();
-    get_file_contents(&mut buffer, path)?;
-    Ok(tree_magic::from_u8(&buffer))
+fn get_mime(data: &[u8]) -> &str {
+    tree_magic::from_u8(data)
 }
 
-fn hash_file_diff(a: &Path, b: &Path) -> Result<bool, io::Error> {
-    Ok(get_file_md5(a)? == get_file_md5(b)?)
+/// Read the first MIME_PREFIX_SIZE bytes of a file.
+fn read_prefix(path: &Path) -> Result<Vec<u8>, io::Error> {
+    let mut data = Vec::new();
+    File::open(path)?
+        .take(MIME_PREFIX_SIZE)
+        .read_to_end(&mut data)?;
+    Ok(data)
 }
 
-fn get_file_md5(path: &Path) -> Result<String, io::Error> {
-    let mut buffer: Vec<u8> = Vec::new();
-    get_file_contents(&mut buffer, path)?;
-    Ok(format!("{:x}", md5::compute(buffer)))
+/// Convert the contents of a file with a utf8 mime into a string.
+fn into_string(data: Vec<u8>) -> Result<String, io::Error> {
+    String::from_utf8(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
-fn get_file_contents(buffer: &mut Vec<u8>, path: &Path) -> Result<(), io::Error> {
+fn hash_file_diff(a: &Path, b: &Path) -> Result<bool, io::Error> {
+    let a_hash = get_file_sha256(a)?;
+    let b_hash = get_file_sha256(b)?;
+
+    debug!("sha256 {}: {}", a.display(), a_hash);
+    debug!("sha256 {}: {}", b.display(), b_hash);
+
+    Ok(a_hash == b_hash)
+}
+
+/// Hash a file in chunks of HASH_BUFFER_SIZE bytes to
+/// avoid loading big files into memory entirely.
+fn get_file_sha256(path: &Path) -> Result<String, io::Error> {
     let mut f = File::open(path)?;
-    f.read_to_end(buffer)?;
-    Ok(())
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+
+    loop {
+        let n = f.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns true if path is a symlink, without following it.
+fn is_symlink(path: &Path) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(meta) => meta.file_type().is_symlink(),
+        Err(_) => false,
+    }
+}
+
+/// Remove path if it is a file or a symlink.
+fn remove_file_or_link(path: &Path) -> Result<(), io::Error> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if !meta.is_dir() => fs::remove_file(path),
+        _ => Ok(()),
+    }
 }
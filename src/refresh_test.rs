@@ -0,0 +1,43 @@
+use super::*;
+
+fn packages(names: &[&str]) -> Scope {
+    Scope::Packages(names.iter().map(|i| i.to_string()).collect())
+}
+
+#[test]
+fn merge_requests() {
+    let trigger = Trigger::default();
+    trigger.package("foo");
+    trigger.package("bar");
+    trigger.package("foo");
+    assert_eq!(trigger.take(false), packages(&["foo", "bar"]));
+
+    // Full refreshes include all packages
+    trigger.package("foo");
+    trigger.all();
+    trigger.package("bar");
+    assert_eq!(trigger.take(false), Scope::All);
+
+    trigger.package("foo");
+    assert_eq!(trigger.take(true), Scope::All);
+    assert_eq!(trigger.take(false), Scope::All);
+}
+
+#[test]
+fn forced_rebuilds() {
+    let trigger = Trigger::default();
+    trigger.rebuild("foo");
+    assert_eq!(trigger.take(false), packages(&["foo"]));
+
+    // Rebuilds are only taken by refreshes including them
+    assert!(trigger.take_forced(&packages(&["bar"])).is_empty());
+    assert!(trigger.take_forced(&packages(&["foo"])).contains("foo"));
+    assert!(trigger.take_forced(&Scope::All).is_empty());
+}
+
+#[test]
+fn scope_contains() {
+    assert!(Scope::All.contains("foo"));
+    assert!(packages(&["foo"]).contains("foo"));
+    assert!(!packages(&["foo"]).contains("bar"));
+}
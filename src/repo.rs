@@ -0,0 +1,120 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use tokio::process::Command;
+
+use crate::command;
+use crate::error::Error;
+use crate::version;
+
+#[cfg(test)]
+#[path = "repo_test.rs"]
+mod repo_test;
+
+/// Returns true if file_name is a package file compressed
+/// with one of the given extensions.
+pub fn is_package_file(file_name: &str, extensions: &[String]) -> bool {
+    extensions
+        .iter()
+        .any(|ext| file_name.ends_with(&format!(".pkg.tar.{}", ext)))
+}
+
+/// Returns true if file_name is a signature or a repo database
+/// file like foo.db, foo.db.tar.gz or foo.files.tar.zst.
+pub fn is_repo_metadata(file_name: &str) -> bool {
+    file_name.ends_with(".sig")
+        || file_name
+            .split('.')
+            .skip(1)
+            .any(|i| i == "db" || i == "files")
+}
+
+/// Delete the package files of pkg_name in dir except for the keep newest
+/// versions, together with their signatures. Files are identified by their
+/// package info, so packages sharing a name prefix aren't touched.
+/// Returns the deleted package files.
+pub fn remove_old_versions(
+    dir: &Path,
+    pkg_name: &str,
+    extensions: &[String],
+    keep: usize,
+) -> io::Result<Vec<PathBuf>> {
+    let mut versions = Vec::new();
+
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        let file_name = match path.file_name().and_then(|i| i.to_str()) {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+
+        if !file_name.starts_with(&format!("{}-", pkg_name))
+            || is_repo_metadata(file_name)
+            || !is_package_file(file_name, extensions)
+        {
+            continue;
+        }
+
+        match path.to_str().map(pkginfo::new) {
+            Some(Ok(info)) if info.pkg_name == pkg_name => versions.push((path, info.pkg_ver)),
+            Some(Ok(_)) | None => {}
+            Some(Err(e)) => warn!("Can't read package info of {}: {:?}", file_name, e),
+        }
+    }
+
+    let outdated = outdated_versions(versions, keep);
+    for file in &outdated {
+        fs::remove_file(file)?;
+
+        let mut sig_file = file.as_os_str().to_owned();
+        sig_file.push(".sig");
+        let sig_file = PathBuf::from(sig_file);
+        if sig_file.exists() {
+            fs::remove_file(sig_file)?;
+        }
+    }
+
+    Ok(outdated)
+}
+
+/// Returns the files of all but the keep newest versions.
+fn outdated_versions(mut versions: Vec<(PathBuf, String)>, keep: usize) -> Vec<PathBuf> {
+    versions.sort_by(|a, b| version::compare(&b.1, &a.1));
+    versions.into_iter().skip(keep).map(|i| i.0).collect()
+}
+
+/// Add pkg_file to the repository database db using repo-add. The
+/// database gets signed with sign_key if set.
+pub async fn add_package(db: &Path, pkg_file: &Path, sign_key: Option<&str>) -> Result<(), Error> {
+    let output = Command::new("repo-add")
+        .args(repo_add_args(db, pkg_file, sign_key))
+        .output()
+        .await
+        .map_err(|e| Error::RepoAddFailed(db.display().to_string(), Some(e)))?;
+
+    if !output.status.success() {
+        return Err(Error::RepoAddFailed(
+            format!("{}: {}", pkg_file.display(), command::stderr(&output)),
+            None,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Arguments of repo-add adding pkg_file to db.
+fn repo_add_args(db: &Path, pkg_file: &Path, sign_key: Option<&str>) -> Vec<OsString> {
+    let mut args = Vec::new();
+    if let Some(key) = sign_key {
+        args.push("--sign".into());
+        args.push("--key".into());
+        args.push(key.into());
+    }
+
+    args.push(db.into());
+    args.push(pkg_file.into());
+    args
+}
@@ -0,0 +1,79 @@
+use super::*;
+
+fn extensions() -> Vec<String> {
+    vec!["zst".to_owned(), "xz".to_owned()]
+}
+
+#[test]
+fn check_is_package_file() {
+    assert!(is_package_file(
+        "foo-1.0-1-x86_64.pkg.tar.zst",
+        &extensions()
+    ));
+    assert!(is_package_file("foo-1.0-1-any.pkg.tar.xz", &extensions()));
+
+    assert!(!is_package_file("foo-1.0-1-any.pkg.tar.gz", &extensions()));
+    assert!(!is_package_file("foo-1.0.tar.xz", &extensions()));
+    assert!(!is_package_file(
+        "foo-1.0-1-any.pkg.tar.zst.sig",
+        &extensions()
+    ));
+
+    let gz = vec!["gz".to_owned()];
+    assert!(is_package_file("foo-1.0-1-any.pkg.tar.gz", &gz));
+}
+
+#[test]
+fn check_is_repo_metadata() {
+    assert!(is_repo_metadata("foo-1.0-1-any.pkg.tar.zst.sig"));
+    assert!(is_repo_metadata("foo.db"));
+    assert!(is_repo_metadata("foo.db.tar.zst"));
+    assert!(is_repo_metadata("foo.db.tar.gz.old"));
+    assert!(is_repo_metadata("foo.files"));
+    assert!(is_repo_metadata("foo.files.tar.zst"));
+
+    assert!(!is_repo_metadata("foo-1.0-1-any.pkg.tar.zst"));
+    assert!(!is_repo_metadata("db-tools-1.0-1-any.pkg.tar.zst"));
+    assert!(!is_repo_metadata("files-1.0-1-any.pkg.tar.xz"));
+}
+
+#[test]
+fn check_repo_add_args() {
+    let db = Path::new("/srv/repo/custom.db.tar.zst");
+    let pkg = Path::new("/srv/repo/foo-1.0-1-any.pkg.tar.zst");
+
+    assert_eq!(
+        repo_add_args(db, pkg, None),
+        vec![db.as_os_str(), pkg.as_os_str()]
+    );
+    assert_eq!(
+        repo_add_args(db, pkg, Some("ABCD")),
+        vec![
+            "--sign",
+            "--key",
+            "ABCD",
+            "/srv/repo/custom.db.tar.zst",
+            "/srv/repo/foo-1.0-1-any.pkg.tar.zst"
+        ]
+    );
+}
+
+#[test]
+fn check_outdated_versions() {
+    let versions = vec![
+        (PathBuf::from("foo-1.9-1"), "1.9-1".to_owned()),
+        (PathBuf::from("foo-1:0.1-1"), "1:0.1-1".to_owned()),
+        (PathBuf::from("foo-1.10-1"), "1.10-1".to_owned()),
+        (PathBuf::from("foo-1.10-2"), "1.10-2".to_owned()),
+    ];
+
+    assert_eq!(
+        outdated_versions(versions.clone(), 2),
+        vec![PathBuf::from("foo-1.10-1"), PathBuf::from("foo-1.9-1")]
+    );
+    assert_eq!(
+        outdated_versions(versions.clone(), 4),
+        Vec::<PathBuf>::new()
+    );
+    assert_eq!(outdated_versions(versions, 1).len(), 3);
+}
@@ -0,0 +1,216 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Output, Stdio};
+
+use git2::Repository;
+use tokio::process::Command;
+
+use crate::command;
+use crate::config;
+use crate::error::Error;
+
+/// The gpg binary used if none is configured.
+const DEFAULT_GPG: &str = "gpg";
+
+/// Signer creates detached signatures for built
+/// packages using gpg.
+pub struct Signer<'a> {
+    config: &'a config::Signing,
+}
+
+impl<'a> Signer<'a> {
+    /// Create a new signer
+    pub fn new(config: &'a config::Signing) -> Self {
+        Signer { config }
+    }
+
+    /// Sign a file and verify the created signature.
+    /// Returns the path of the signature file.
+    pub async fn sign(&self, file: &Path) -> Result<PathBuf, Error> {
+        let sig_file = sig_path(file);
+
+        let output = self
+            .gpg()
+            .arg("--batch")
+            .arg("--yes")
+            .arg("--detach-sign")
+            .arg("--use-agent")
+            .arg("-u")
+            .arg(&self.config.key_id)
+            .arg("--output")
+            .arg(&sig_file)
+            .arg(file)
+            .output()
+            .await;
+        check_output(output, file)?;
+
+        self.verify(file, &sig_file).await?;
+        Ok(sig_file)
+    }
+
+    /// Verify the signature of a file.
+    pub async fn verify(&self, file: &Path, sig_file: &Path) -> Result<(), Error> {
+        let output = self
+            .gpg()
+            .arg("--batch")
+            .arg("--verify")
+            .arg(sig_file)
+            .arg(file)
+            .output()
+            .await;
+        check_output(output, file)
+    }
+
+    fn gpg(&self) -> Command {
+        Command::new(self.config.gpg_path.as_deref().unwrap_or(DEFAULT_GPG))
+    }
+}
+
+/// Returns the signature and the signed data of the HEAD commit of repo.
+pub fn commit_signature(repo: &Repository) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let commit = repo
+        .head()
+        .and_then(|i| i.peel_to_commit())
+        .map_err(|e| Error::GitError("Can't read HEAD".to_owned(), Some(e)))?;
+
+    let (signature, data) = repo
+        .extract_signature(&commit.id(), None)
+        .map_err(|_| Error::UntrustedCommit(format!("commit {} isn't signed", commit.id())))?;
+
+    Ok((signature.to_vec(), data.to_vec()))
+}
+
+/// Verify a commit signature with gpg. The signing key has to match one of
+/// trusted_keys. Temporary files are written to dir. Returns the fingerprint
+/// of the signing key.
+pub async fn verify_commit(
+    gpg_path: Option<&str>,
+    dir: &Path,
+    (signature, data): (&[u8], &[u8]),
+    trusted_keys: &[String],
+) -> Result<String, Error> {
+    let sig_file = dir.join("commit.sig");
+    let data_file = dir.join("commit");
+    fs::write(&sig_file, signature)
+        .and_then(|_| fs::write(&data_file, data))
+        .map_err(|e| Error::SigningFailed(dir.display().to_string(), Some(e)))?;
+
+    let output = Command::new(gpg_path.unwrap_or(DEFAULT_GPG))
+        .arg("--batch")
+        .arg("--status-fd")
+        .arg("1")
+        .arg("--verify")
+        .arg(&sig_file)
+        .arg(&data_file)
+        .output()
+        .await
+        .map_err(|e| Error::SigningFailed("gpg".to_owned(), Some(e)))?;
+
+    let status = String::from_utf8_lossy(&output.stdout);
+    match trusted_fingerprint(&status, trusted_keys) {
+        Some(fingerprint) if output.status.success() => Ok(fingerprint),
+        _ => Err(Error::UntrustedCommit(format!(
+            "no valid signature of a trusted key: {}",
+            command::stderr(&output)
+        ))),
+    }
+}
+
+/// Returns the fingerprint of a valid signature in gpg's status output
+/// if it belongs to a trusted key. Subkeys match by their primary key.
+/// Trusted keys may be fingerprints or long key IDs.
+fn trusted_fingerprint(status: &str, trusted_keys: &[String]) -> Option<String> {
+    let trusted: Vec<String> = trusted_keys
+        .iter()
+        .map(|i| i.replace(' ', "").trim_start_matches("0x").to_uppercase())
+        .filter(|i| i.len() >= 16)
+        .collect();
+
+    status
+        .lines()
+        .filter_map(|i| i.strip_prefix("[GNUPG:] VALIDSIG "))
+        .flat_map(|i| {
+            let fields: Vec<&str> = i.split_whitespace().collect();
+            vec![fields.first().copied(), fields.last().copied()]
+        })
+        .flatten()
+        .find(|fpr| trusted.iter().any(|key| fpr.ends_with(key.as_str())))
+        .map(|i| i.to_owned())
+}
+
+/// Create an armored signature of a commit's data with gpg, as
+/// expected by git2::Repository::commit_signed.
+pub async fn sign_commit(
+    gpg_path: Option<&str>,
+    key_id: &str,
+    data: &str,
+) -> Result<String, Error> {
+    let gpg = gpg_path.unwrap_or(DEFAULT_GPG).to_owned();
+    let key_id = key_id.to_owned();
+    let data = data.to_owned();
+
+    // Writing to gpg's stdin is blocking
+    tokio::task::spawn_blocking(move || gpg_sign(&gpg, &key_id, &data))
+        .await
+        .map_err(|e| Error::SigningFailed(format!("commit: {}", e), None))?
+}
+
+fn gpg_sign(gpg: &str, key_id: &str, data: &str) -> Result<String, Error> {
+    let gpg_err = |e| Error::SigningFailed("commit".to_owned(), Some(e));
+
+    let mut child = std::process::Command::new(gpg)
+        .arg("--batch")
+        .arg("--armor")
+        .arg("--detach-sign")
+        .arg("--use-agent")
+        .arg("-u")
+        .arg(key_id)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(gpg_err)?;
+
+    // Dropping stdin closes it, so gpg stops reading
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data.as_bytes()).map_err(gpg_err)?;
+    }
+
+    let output = child.wait_with_output().map_err(gpg_err)?;
+    if !output.status.success() {
+        return Err(Error::SigningFailed(
+            format!("commit: {}", command::stderr(&output)),
+            None,
+        ));
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| {
+        Error::SigningFailed("commit: gpg returned an invalid signature".to_owned(), None)
+    })
+}
+
+/// Returns the path of the detached signature for file.
+fn sig_path(file: &Path) -> PathBuf {
+    let mut sig_file = file.as_os_str().to_owned();
+    sig_file.push(".sig");
+    PathBuf::from(sig_file)
+}
+
+/// Map a failed or unsuccessful gpg run to an error.
+fn check_output(output: std::io::Result<Output>, file: &Path) -> Result<(), Error> {
+    let output = output.map_err(|e| Error::SigningFailed(file.display().to_string(), Some(e)))?;
+
+    if !output.status.success() {
+        return Err(Error::SigningFailed(
+            format!("{}: {}", file.display(), command::stderr(&output)),
+            None,
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[path = "signing_test.rs"]
+mod signing_test;
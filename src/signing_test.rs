@@ -0,0 +1,40 @@
+use super::*;
+
+const STATUS: &str = "[GNUPG:] NEWSIG
+[GNUPG:] GOODSIG 89ABCDEF01234567 Foo <foo@example.com>
+[GNUPG:] VALIDSIG 0123456789ABCDEF0123456789ABCDEF01234567 2020-10-01 1601510400 0 4 0 1 10 00 FEDCBA9876543210FEDCBA9876543210FEDCBA98
+[GNUPG:] TRUST_UNDEFINED 0 pgp
+";
+
+#[test]
+fn check_trusted_fingerprint() {
+    let fpr = "0123456789ABCDEF0123456789ABCDEF01234567";
+    assert_eq!(
+        trusted_fingerprint(STATUS, &[fpr.to_owned()]).as_deref(),
+        Some(fpr)
+    );
+
+    // Long key IDs, lower case and the primary key of a subkey match
+    assert!(trusted_fingerprint(STATUS, &["0x89abcdef01234567".to_owned()]).is_some());
+    assert!(trusted_fingerprint(
+        STATUS,
+        &["FEDC BA98 7654 3210 FEDC BA98 7654 3210 FEDC BA98".to_owned()]
+    )
+    .is_some());
+
+    assert!(trusted_fingerprint(STATUS, &["1111111111111111".to_owned()]).is_none());
+    // Short key IDs are too easy to collide
+    assert!(trusted_fingerprint(STATUS, &["01234567".to_owned()]).is_none());
+    assert!(
+        trusted_fingerprint("[GNUPG:] BADSIG 89ABCDEF01234567 Foo", &[fpr.to_owned()]).is_none()
+    );
+}
+
+#[tokio::test]
+async fn sign_commit_failed() {
+    let res = sign_commit(Some("false"), "0123456789ABCDEF", "tree 0").await;
+    assert!(matches!(res, Err(Error::SigningFailed(_, _))));
+
+    let res = sign_commit(Some("/nonexistent/gpg"), "0123456789ABCDEF", "tree 0").await;
+    assert!(matches!(res, Err(Error::SigningFailed(_, Some(_)))));
+}
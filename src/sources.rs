@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use regex::Regex;
+use tokio::process::Command;
+
+use crate::command;
+
+#[cfg(test)]
+#[path = "sources_test.rs"]
+mod sources_test;
+
+/// Check the sources declared in srcinfo, generated from the PKGBUILD
+/// in dir, then download them into srcdest and verify their checksums
+/// and signatures with makepkg --verifysource. Returns the reason if
+/// the sources got rejected.
+pub async fn verify(
+    dir: &Path,
+    srcinfo: &str,
+    srcdest: &Path,
+) -> Result<Option<String>, io::Error> {
+    let insecure = insecure_sources(srcinfo);
+    if !insecure.is_empty() {
+        return Ok(Some(format!(
+            "sources fetched over plain HTTP without checksum: {}",
+            insecure.join(", ")
+        )));
+    }
+
+    // Downloads go to srcdest to keep the checked files untouched
+    fs::create_dir_all(srcdest)?;
+    let output = Command::new("makepkg")
+        .arg("--verifysource")
+        .arg("--noconfirm")
+        .env("SRCDEST", srcdest)
+        .current_dir(dir)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Ok(Some(format!(
+            "makepkg --verifysource {}: {}",
+            output.status,
+            command::stderr(&output)
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Returns the sources which are fetched over plain HTTP and don't
+/// have a checksum. VCS sources pinned to a commit are fine.
+fn insecure_sources(srcinfo: &str) -> Vec<String> {
+    let source_re = Regex::new(r"^source(_\w+)?$").unwrap();
+    let sums_re = Regex::new(r"^(?:md5|sha1|sha224|sha256|sha384|sha512|b2)sums(_\w+)?$").unwrap();
+
+    // Sources and their checksums by architecture suffix
+    let mut sources: HashMap<String, Vec<&str>> = HashMap::new();
+    let mut sums: HashMap<(String, &str), Vec<&str>> = HashMap::new();
+
+    for line in srcinfo.lines() {
+        let mut parts = line.trim().splitn(2, " = ");
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => continue,
+        };
+
+        if let Some(caps) = source_re.captures(key) {
+            let arch = caps.get(1).map_or("", |i| i.as_str()).to_owned();
+            sources.entry(arch).or_default().push(value);
+        } else if let Some(caps) = sums_re.captures(key) {
+            let arch = caps.get(1).map_or("", |i| i.as_str()).to_owned();
+            sums.entry((arch, key)).or_default().push(value);
+        }
+    }
+
+    let mut insecure = Vec::new();
+    for (arch, sources) in &sources {
+        for (i, source) in sources.iter().enumerate() {
+            let has_sum = sums
+                .iter()
+                .filter(|((sum_arch, _), _)| sum_arch == arch)
+                .any(|(_, values)| matches!(values.get(i), Some(sum) if *sum != "SKIP"));
+
+            if !has_sum && is_plain_http(source) {
+                insecure.push(source.to_string());
+            }
+        }
+    }
+
+    insecure.sort();
+    insecure
+}
+
+/// Returns true if source gets fetched over plain HTTP. Sources look
+/// like [name::][vcs+]url[#fragment].
+fn is_plain_http(source: &str) -> bool {
+    let url = match source.find("::") {
+        Some(pos) => &source[pos + 2..],
+        None => source,
+    }
+    .to_lowercase();
+
+    let scheme = url.split("://").next().unwrap_or_default();
+    let is_vcs = scheme.contains('+');
+    let pinned = url.contains("#commit=");
+
+    scheme.rsplit('+').next() == Some("http") && !(is_vcs && pinned)
+}
@@ -0,0 +1,42 @@
+use super::*;
+
+#[test]
+fn check_is_plain_http() {
+    assert!(is_plain_http("http://example.com/foo-1.0.tar.gz"));
+    assert!(is_plain_http("foo.tar.gz::HTTP://example.com/latest"));
+    assert!(is_plain_http("git+http://example.com/foo.git"));
+    assert!(is_plain_http("git+http://example.com/foo.git#tag=v1"));
+
+    assert!(!is_plain_http("https://example.com/foo-1.0.tar.gz"));
+    assert!(!is_plain_http("git+https://example.com/foo.git"));
+    assert!(!is_plain_http("git+http://example.com/foo.git#commit=abc"));
+    assert!(!is_plain_http("foo.patch"));
+}
+
+#[test]
+fn find_insecure_sources() {
+    let srcinfo = "pkgbase = foo\n\
+                   \tpkgver = 1.0\n\
+                   \tsource = http://example.com/foo-1.0.tar.gz\n\
+                   \tsource = http://example.com/latest.tar.gz\n\
+                   \tsource = foo.patch\n\
+                   \tsource_x86_64 = http://example.com/foo-x86_64.bin\n\
+                   \tmd5sums = SKIP\n\
+                   \tmd5sums = SKIP\n\
+                   \tmd5sums = SKIP\n\
+                   \tsha256sums = 0123abcd\n\
+                   \tsha256sums = SKIP\n\
+                   \tsha256sums = 4567abcd\n\
+                   \tsha256sums_x86_64 = SKIP\n\
+                   \n\
+                   pkgname = foo\n";
+
+    assert_eq!(
+        insecure_sources(srcinfo),
+        vec![
+            "http://example.com/foo-x86_64.bin".to_owned(),
+            "http://example.com/latest.tar.gz".to_owned(),
+        ]
+    );
+    assert!(insecure_sources("pkgbase = foo\n\tsource = https://example.com/x\n").is_empty());
+}
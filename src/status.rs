@@ -0,0 +1,136 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::build_state::BuildState;
+
+#[cfg(test)]
+#[path = "status_test.rs"]
+mod status_test;
+
+/// Amount of finished builds kept for the status API.
+const MAX_RECENT_BUILDS: usize = 50;
+
+/// Status keeps the state of the managed packages
+/// and builds shown by the status API.
+#[derive(Debug, Default)]
+pub struct Status {
+    packages: Mutex<BTreeMap<String, PackageStatus>>,
+    builds: Mutex<VecDeque<BuildStatus>>,
+}
+
+/// A managed package as of its last check.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackageStatus {
+    pub name: String,
+    pub local_version: String,
+    pub aur_version: String,
+    /// Unix time of the last check.
+    pub last_check: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildResult {
+    Building,
+    Built,
+    Failed,
+}
+
+/// An in-progress or recently finished build.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildStatus {
+    pub pkg_name: String,
+    pub local_version: String,
+    pub aur_version: String,
+    pub job_id: Option<u32>,
+    pub status: BuildResult,
+    /// Unix time the build job was submitted or resumed at.
+    pub started: u64,
+    pub finished: Option<u64>,
+}
+
+impl Status {
+    /// Record the versions of a checked package.
+    pub fn package_checked(&self, name: &str, local_version: &str, aur_version: &str) {
+        self.packages.lock().unwrap().insert(
+            name.to_owned(),
+            PackageStatus {
+                name: name.to_owned(),
+                local_version: local_version.to_owned(),
+                aur_version: aur_version.to_owned(),
+                last_check: now(),
+            },
+        );
+    }
+
+    /// Forget all packages rejected by keep.
+    pub fn retain_packages<F: Fn(&str) -> bool>(&self, keep: F) {
+        self.packages.lock().unwrap().retain(|name, _| keep(name));
+    }
+
+    /// Record a build which is waiting for its job.
+    pub fn build_started(&self, state: &BuildState) {
+        let mut builds = self.builds.lock().unwrap();
+        builds.push_back(BuildStatus {
+            pkg_name: state.pkg_name.clone(),
+            local_version: state.local_version.clone(),
+            aur_version: state.aur_version.clone(),
+            job_id: state.job_id,
+            status: BuildResult::Building,
+            started: now(),
+            finished: None,
+        });
+
+        // Running builds are never dropped
+        while builds.len() > MAX_RECENT_BUILDS {
+            match builds
+                .iter()
+                .position(|i| i.status != BuildResult::Building)
+            {
+                Some(pos) => builds.remove(pos),
+                None => break,
+            };
+        }
+    }
+
+    /// Mark the running build of state as finished. The job
+    /// id is updated since failed jobs get recreated.
+    pub fn build_finished(&self, state: &BuildState, success: bool) {
+        let mut builds = self.builds.lock().unwrap();
+        let build = builds
+            .iter_mut()
+            .rev()
+            .find(|i| i.pkg_name == state.pkg_name && i.status == BuildResult::Building);
+
+        if let Some(build) = build {
+            build.job_id = state.job_id;
+            build.status = if success {
+                BuildResult::Built
+            } else {
+                BuildResult::Failed
+            };
+            build.finished = Some(now());
+        }
+    }
+
+    /// Returns all known packages ordered by name.
+    pub fn packages(&self) -> Vec<PackageStatus> {
+        self.packages.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Returns the running and recent builds, newest first.
+    pub fn builds(&self) -> Vec<BuildStatus> {
+        self.builds.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+/// Current unix time in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
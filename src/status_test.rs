@@ -0,0 +1,41 @@
+use super::*;
+
+fn state(pkg_name: &str, job_id: u32) -> BuildState {
+    let mut state = BuildState::new(pkg_name.to_owned(), "1.0-1".to_owned(), "1.1-1".to_owned());
+    state.job_id = Some(job_id);
+    state
+}
+
+#[test]
+fn track_builds() {
+    let status = Status::default();
+    status.build_started(&state("foo", 1));
+    status.build_started(&state("bar", 2));
+
+    // Retried jobs get a new id
+    status.build_finished(&state("foo", 3), true);
+
+    let builds = status.builds();
+    assert_eq!(builds[0].pkg_name, "bar");
+    assert_eq!(builds[0].status, BuildResult::Building);
+    assert_eq!(builds[1].job_id, Some(3));
+    assert_eq!(builds[1].status, BuildResult::Built);
+    assert!(builds[1].finished.is_some());
+}
+
+#[test]
+fn keep_running_builds() {
+    let status = Status::default();
+    status.build_started(&state("running", 1));
+
+    for i in 0..MAX_RECENT_BUILDS as u32 {
+        let state = state(&format!("pkg{}", i), i);
+        status.build_started(&state);
+        status.build_finished(&state, false);
+    }
+
+    let builds = status.builds();
+    assert_eq!(builds.len(), MAX_RECENT_BUILDS);
+    assert_eq!(builds.last().unwrap().pkg_name, "running");
+    assert_eq!(builds[0].status, BuildResult::Failed);
+}
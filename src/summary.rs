@@ -0,0 +1,65 @@
+use std::mem;
+
+#[cfg(test)]
+#[path = "summary_test.rs"]
+mod summary_test;
+
+/// Summary collects the results of a refresh cycle,
+/// which are sent as a single notification.
+#[derive(Debug, Default)]
+pub struct Summary {
+    checked: usize,
+    updated: Vec<String>,
+    rejected: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl Summary {
+    pub fn checked(&mut self) {
+        self.checked += 1;
+    }
+
+    pub fn updated(&mut self, update: String) {
+        self.updated.push(update);
+    }
+
+    pub fn rejected(&mut self, reason: String) {
+        self.rejected.push(reason);
+    }
+
+    pub fn error(&mut self, error: String) {
+        self.errors.push(error);
+    }
+
+    /// Returns the collected results and starts over.
+    pub fn take(&mut self) -> Summary {
+        mem::take(self)
+    }
+
+    /// Returns true if nothing worth reporting happened.
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.rejected.is_empty() && self.errors.is_empty()
+    }
+
+    /// Render the summary as notification message.
+    pub fn render(&self) -> String {
+        let mut out = format!("Refresh summary: {} package(s) checked", self.checked);
+
+        for (title, lines) in &[
+            ("Updated", &self.updated),
+            ("Rejected", &self.rejected),
+            ("Errors", &self.errors),
+        ] {
+            if lines.is_empty() {
+                continue;
+            }
+
+            out.push_str(&format!("\n\n{}:", title));
+            for line in lines.iter() {
+                out.push_str(&format!("\n- {}", line));
+            }
+        }
+
+        out
+    }
+}
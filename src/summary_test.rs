@@ -0,0 +1,23 @@
+use super::*;
+
+#[test]
+fn render_summary() {
+    let mut summary = Summary::default();
+    summary.checked();
+    summary.checked();
+    assert!(summary.is_empty());
+
+    summary.updated("foo 1.0-1 -> 1.1-1".to_owned());
+    summary.error("bar: job 3 failed".to_owned());
+    assert!(!summary.is_empty());
+
+    assert_eq!(
+        summary.render(),
+        "Refresh summary: 2 package(s) checked\n\nUpdated:\n- foo 1.0-1 -> 1.1-1\n\nErrors:\n- bar: job 3 failed"
+    );
+
+    let taken = summary.take();
+    assert!(!taken.is_empty());
+    assert!(summary.is_empty());
+    assert_eq!(summary.render(), "Refresh summary: 0 package(s) checked");
+}
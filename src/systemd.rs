@@ -0,0 +1,19 @@
+/// Tell systemd the service finished starting up.
+/// Does nothing without the systemd feature.
+pub fn ready() {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Ready]);
+}
+
+/// Reset the systemd watchdog timer.
+pub fn watchdog() {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Watchdog]);
+}
+
+#[cfg(feature = "systemd")]
+fn notify(state: &[sd_notify::NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        log::warn!("Can't notify systemd: {}", e);
+    }
+}
@@ -1,51 +1,636 @@
 extern crate reqwest;
 
-use reqwest::{Client, Url};
-use serde::Serialize;
+use std::collections::HashSet;
+use std::error::Error as stdErr;
+use std::time::{Duration, Instant};
+
+use async_std::task;
+use futures::lock::Mutex;
+use log::{info, warn};
+use reqwest::header::RETRY_AFTER;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, RequestBuilder, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config;
+use crate::error::Error;
+
+#[cfg(test)]
+#[path = "tg_bot_wrapper_test.rs"]
+mod tg_bot_wrapper_test;
+
+/// Seconds the bot API may hold a getUpdates request
+/// open until new updates arrive.
+const POLL_TIMEOUT: u64 = 10;
+
+/// Longest text telegram accepts in a single message.
+const MAX_MESSAGE_LEN: usize = 4096;
+
+/// Opens and closes code blocks in MarkdownV2 messages.
+const CODE_FENCE: &str = "```";
+
+/// Characters which have to be escaped in MarkdownV2 text.
+const MARKDOWN_V2_SPECIAL: &str = "_*[]()~`>#+-=|{}.!\\";
+
+/// The delay before the first retry of a failed request.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
 
 pub struct TgBot {
     token: String,
+    chat_id: u64,
+    recipients: Vec<i64>,
+    api_base_url: String,
+    max_attempts: u32,
+    updates: Mutex<UpdateState>,
+}
+
+/// Updates received from the bot API which
+/// haven't been consumed yet.
+#[derive(Default)]
+struct UpdateState {
+    offset: i64,
+    callback_queries: Vec<CallbackQuery>,
+    messages: Vec<Message>,
+    /// Messages whose buttons are waited for, as (chat_id, message_id).
+    awaited: HashSet<(i64, i64)>,
+}
+
+impl UpdateState {
+    /// Remove the callback queries of messages nobody waits for.
+    /// They would pile up otherwise.
+    fn take_unawaited(&mut self) -> Vec<CallbackQuery> {
+        let awaited = &self.awaited;
+        let (kept, unawaited) = std::mem::take(&mut self.callback_queries)
+            .into_iter()
+            .partition(|i| match &i.message {
+                Some(m) => awaited.contains(&(m.chat.id, m.message_id)),
+                None => false,
+            });
+        self.callback_queries = kept;
+        unawaited
+    }
+}
+
+/// Formatting styles of message texts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(dead_code)]
+pub enum ParseMode {
+    MarkdownV2,
+    Html,
+}
+
+impl ParseMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            ParseMode::MarkdownV2 => "MarkdownV2",
+            ParseMode::Html => "HTML",
+        }
+    }
+}
+
+/// Response wrapper of the telegram bot API.
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub id: i64,
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Update {
+    pub update_id: i64,
+    pub message: Option<Message>,
+    pub callback_query: Option<CallbackQuery>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    pub message_id: i64,
+    pub chat: Chat,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chat {
+    pub id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    pub id: String,
+    pub message: Option<Message>,
+    pub data: Option<String>,
 }
 
 impl TgBot {
-    pub fn new(token: String) -> Self {
-        TgBot { token }
+    pub fn new(token: String, chat_id: u64) -> Self {
+        TgBot {
+            token,
+            chat_id,
+            recipients: vec![chat_id as i64],
+            api_base_url: config::DEFAULT_TELEGRAM_API_URL.to_owned(),
+            max_attempts: 1,
+            updates: Mutex::new(UpdateState::default()),
+        }
+    }
+
+    /// Use a self-hosted bot API server.
+    pub fn with_api_base_url(mut self, api_base_url: &str) -> Self {
+        self.api_base_url = api_base_url.to_owned();
+        self
+    }
+
+    /// Retry failed requests until max_attempts were made.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Send notifications to recipients instead of the chat
+    /// passed to new. An empty list keeps that chat.
+    pub fn with_recipients(mut self, recipients: Vec<i64>) -> Self {
+        if !recipients.is_empty() {
+            self.recipients = recipients;
+        }
+        self
+    }
+
+    /// Returns the chats notifications are sent to.
+    pub fn recipients(&self) -> &[i64] {
+        &self.recipients
     }
 
     fn get_client(&self) -> Client {
         Client::new()
     }
 
+    /// Do an API request with params as query.
     async fn api_request<S: AsRef<str>, Q: Serialize + ?Sized>(
         &self,
         endpoint: S,
         params: &Q,
-    ) -> reqwest::Result<reqwest::Response> {
+    ) -> Result<reqwest::Response, Box<dyn stdErr>> {
+        let url = self.get_url()?.join(endpoint.as_ref())?;
+        let client = self.get_client();
+
         Ok(self
-            .get_client()
-            .post(self.get_url().join(endpoint.as_ref()).unwrap())
-            .query(params)
-            .send()
+            .send_with_retries(endpoint.as_ref(), || client.post(url.clone()).query(params))
             .await?)
     }
 
+    /// Send the request built by request. Network errors, rate limits
+    /// and server errors are retried with exponential backoff. The last
+    /// response or error is returned once all attempts failed. Requests
+    /// are rebuilt for every attempt since multipart forms can't be reused.
+    async fn send_with_retries<F: Fn() -> RequestBuilder>(
+        &self,
+        endpoint: &str,
+        request: F,
+    ) -> reqwest::Result<reqwest::Response> {
+        let mut attempt = 1;
+
+        loop {
+            let res = request().send().await;
+
+            let retry_after = match res {
+                Ok(ref r) if r.status() == StatusCode::TOO_MANY_REQUESTS => r
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|i| i.to_str().ok())
+                    .and_then(|i| i.parse().ok()),
+                Ok(ref r) if r.status().is_server_error() => None,
+                Ok(_) => return res,
+                Err(_) => None,
+            };
+
+            if attempt >= self.max_attempts {
+                return res;
+            }
+
+            let delay = retry_delay(attempt, retry_after);
+            warn!(
+                "Telegram request {} failed, retrying in {} ({}/{})",
+                endpoint,
+                humantime::format_duration(delay),
+                attempt,
+                self.max_attempts
+            );
+            task::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Do an API request and parse its result.
+    async fn api_call<T: DeserializeOwned, S: AsRef<str>, Q: Serialize + ?Sized>(
+        &self,
+        endpoint: S,
+        params: &Q,
+    ) -> Result<T, Box<dyn stdErr>> {
+        let res: ApiResponse<T> = self.api_request(endpoint, params).await?.json().await?;
+
+        match res.result {
+            Some(result) if res.ok => Ok(result),
+            _ => Err(Box::new(Error::TelegramError(
+                res.description.unwrap_or_default(),
+            ))),
+        }
+    }
+
     pub async fn send_message<S: AsRef<str>>(
         &self,
-        chat_id: u64,
+        chat_id: i64,
         text: S,
-    ) -> reqwest::Result<reqwest::Response> {
-        Ok(self
-            .api_request(
-                "sendMessage",
-                &[
-                    ("chat_id", chat_id.to_string().as_str()),
-                    ("text", text.as_ref()),
-                ],
-            )
-            .await?)
+    ) -> Result<reqwest::Response, Box<dyn stdErr>> {
+        self.send_formatted_message(chat_id, text, None).await
+    }
+
+    /// Send a message formatted with the given parse mode. Dynamic
+    /// content has to be escaped, see escape_markdown_v2 and escape_html.
+    /// Texts exceeding the message size limit are sent as multiple
+    /// messages. Sending stops at the first unsuccessful response.
+    pub async fn send_formatted_message<S: AsRef<str>>(
+        &self,
+        chat_id: i64,
+        text: S,
+        parse_mode: Option<ParseMode>,
+    ) -> Result<reqwest::Response, Box<dyn stdErr>> {
+        let mut chunks = split_message(text.as_ref(), MAX_MESSAGE_LEN).into_iter();
+        let last = chunks.next_back().unwrap_or_default();
+
+        for chunk in chunks {
+            let res = self.send_chunk(chat_id, chunk, parse_mode).await?;
+            if !res.status().is_success() {
+                return Ok(res);
+            }
+        }
+
+        self.send_chunk(chat_id, last, parse_mode).await
+    }
+
+    async fn send_chunk(
+        &self,
+        chat_id: i64,
+        text: String,
+        parse_mode: Option<ParseMode>,
+    ) -> Result<reqwest::Response, Box<dyn stdErr>> {
+        let mut params = vec![("chat_id", chat_id.to_string()), ("text", text)];
+        if let Some(parse_mode) = parse_mode {
+            params.push(("parse_mode", parse_mode.as_str().to_owned()));
+        }
+
+        self.api_request("sendMessage", &params).await
+    }
+
+    /// Send a message with an inline keyboard. Each button is a
+    /// (text, callback_data) pair. Long texts are split into multiple
+    /// messages, the keyboard is attached to the last one.
+    pub async fn send_message_with_keyboard<S: AsRef<str>>(
+        &self,
+        chat_id: i64,
+        text: S,
+        parse_mode: Option<ParseMode>,
+        buttons: &[(&str, &str)],
+    ) -> Result<Message, Box<dyn stdErr>> {
+        let mut chunks = split_message(text.as_ref(), MAX_MESSAGE_LEN).into_iter();
+        let last = chunks.next_back().unwrap_or_default();
+
+        for chunk in chunks {
+            self.send_chunk(chat_id, chunk, parse_mode)
+                .await?
+                .error_for_status()?;
+        }
+
+        let keyboard: Vec<_> = buttons
+            .iter()
+            .map(|(text, data)| json!({ "text": text, "callback_data": data }))
+            .collect();
+        let reply_markup = json!({ "inline_keyboard": [keyboard] }).to_string();
+
+        let mut params = vec![
+            ("chat_id", chat_id.to_string()),
+            ("text", last),
+            ("reply_markup", reply_markup),
+        ];
+        if let Some(parse_mode) = parse_mode {
+            params.push(("parse_mode", parse_mode.as_str().to_owned()));
+        }
+
+        self.api_call("sendMessage", &params).await
+    }
+
+    /// Upload content as a document named file_name, e.g. a diff or a log.
+    pub async fn send_document(
+        &self,
+        chat_id: i64,
+        file_name: &str,
+        content: &[u8],
+        caption: Option<&str>,
+    ) -> Result<Message, Box<dyn stdErr>> {
+        let url = self.get_url()?.join("sendDocument")?;
+        let client = self.get_client();
+
+        let form = || {
+            let part = Part::bytes(content.to_vec()).file_name(file_name.to_owned());
+            let form = Form::new()
+                .text("chat_id", chat_id.to_string())
+                .part("document", part);
+            match caption {
+                Some(caption) => form.text("caption", caption.to_owned()),
+                None => form,
+            }
+        };
+
+        let res: ApiResponse<Message> = self
+            .send_with_retries("sendDocument", || {
+                client.post(url.clone()).multipart(form())
+            })
+            .await?
+            .json()
+            .await?;
+
+        match res.result {
+            Some(result) if res.ok => Ok(result),
+            _ => Err(Box::new(Error::TelegramError(
+                res.description.unwrap_or_default(),
+            ))),
+        }
+    }
+
+    /// Returns the bot's own user. Fails if the token is invalid.
+    pub async fn get_me(&self) -> Result<User, Box<dyn stdErr>> {
+        self.api_call("getMe", &[] as &[(&str, &str)]).await
+    }
+
+    /// Look up a chat. Fails if the bot can't reach it, e.g.
+    /// because the user never started a conversation.
+    pub async fn get_chat(&self, chat_id: i64) -> Result<Chat, Box<dyn stdErr>> {
+        self.api_call("getChat", &[("chat_id", chat_id.to_string())])
+            .await
+    }
+
+    /// Check the token, the chat passed to new and the
+    /// chats notifications are sent to.
+    pub async fn check(&self) -> Result<(), Box<dyn stdErr>> {
+        let me = self
+            .get_me()
+            .await
+            .map_err(|e| format!("invalid bot_token: {}", e))?;
+
+        let mut chats = vec![self.chat_id as i64];
+        chats.extend(
+            self.recipients
+                .iter()
+                .filter(|i| **i != self.chat_id as i64),
+        );
+
+        for chat_id in chats {
+            self.get_chat(chat_id)
+                .await
+                .map_err(|e| format!("can't reach chat {}: {}", chat_id, e))?;
+        }
+
+        let id = me.id;
+        info!(
+            "Telegram bot @{} ready",
+            me.username.unwrap_or_else(|| id.to_string())
+        );
+        Ok(())
+    }
+
+    /// Acknowledge a callback query so the client
+    /// stops showing a loading indicator.
+    pub async fn answer_callback_query(&self, id: &str) -> Result<bool, Box<dyn stdErr>> {
+        self.api_call("answerCallbackQuery", &[("callback_query_id", id)])
+            .await
+    }
+
+    /// Get all updates with an update_id greater or equal to offset.
+    pub async fn get_updates(&self, offset: i64) -> Result<Vec<Update>, Box<dyn stdErr>> {
+        self.api_call(
+            "getUpdates",
+            &[
+                ("offset", offset.to_string().as_str()),
+                ("timeout", POLL_TIMEOUT.to_string().as_str()),
+            ],
+        )
+        .await
+    }
+
+    /// Wait for new messages sent to the bot.
+    pub async fn get_messages(&self) -> Result<Vec<Message>, Box<dyn stdErr>> {
+        let mut state = self.updates.lock().await;
+        self.poll_updates(&mut state).await?;
+
+        Ok(state.messages.drain(..).collect())
+    }
+
+    /// Wait until a button of the given message got pressed. Returns
+    /// None if nothing was pressed within the timeout.
+    pub async fn wait_for_callback(
+        &self,
+        chat_id: i64,
+        message_id: i64,
+        timeout: Duration,
+    ) -> Result<Option<CallbackQuery>, Box<dyn stdErr>> {
+        let key = (chat_id, message_id);
+        self.updates.lock().await.awaited.insert(key);
+        let res = self.poll_callback(key, timeout).await;
+
+        // Presses coming in after the timeout are dropped
+        let unawaited = {
+            let mut state = self.updates.lock().await;
+            state.awaited.remove(&key);
+            state.take_unawaited()
+        };
+        self.answer_unawaited(unawaited).await;
+
+        res
+    }
+
+    /// Poll until a button of a message got pressed.
+    async fn poll_callback(
+        &self,
+        (chat_id, message_id): (i64, i64),
+        timeout: Duration,
+    ) -> Result<Option<CallbackQuery>, Box<dyn stdErr>> {
+        let start = Instant::now();
+
+        while start.elapsed() < timeout {
+            {
+                let mut state = self.updates.lock().await;
+                self.poll_updates(&mut state).await?;
+
+                let pos = state
+                    .callback_queries
+                    .iter()
+                    .position(|i| match &i.message {
+                        Some(m) => m.message_id == message_id && m.chat.id == chat_id,
+                        None => false,
+                    });
+
+                if let Some(pos) = pos {
+                    return Ok(Some(state.callback_queries.remove(pos)));
+                }
+            }
+
+            task::sleep(Duration::from_secs(2)).await;
+        }
+
+        Ok(None)
+    }
+
+    /// Fetch new updates and store them in state. Updates have
+    /// to be stored since multiple callbacks and messages can
+    /// be awaited at the same time.
+    async fn poll_updates(&self, state: &mut UpdateState) -> Result<(), Box<dyn stdErr>> {
+        for update in self.get_updates(state.offset).await? {
+            state.offset = update.update_id + 1;
+
+            if let Some(query) = update.callback_query {
+                state.callback_queries.push(query);
+            }
+
+            if let Some(message) = update.message {
+                state.messages.push(message);
+            }
+        }
+
+        let unawaited = state.take_unawaited();
+        self.answer_unawaited(unawaited).await;
+
+        Ok(())
     }
 
-    pub fn get_url(&self) -> Url {
-        Url::parse(format!("https://api.telegram.org/bot{}/", self.token).as_str()).unwrap()
+    /// Answer callback queries nobody waits for, so
+    /// the client stops showing a loading indicator.
+    async fn answer_unawaited(&self, queries: Vec<CallbackQuery>) {
+        for query in queries {
+            info!("Ignoring pressed button of a message not awaiting an answer");
+            if let Err(e) = self.answer_callback_query(&query.id).await {
+                warn!("Can't answer callback query: {}", e);
+            }
+        }
+    }
+
+    pub fn get_url(&self) -> Result<Url, Box<dyn stdErr>> {
+        Ok(Url::parse(&format!(
+            "{}/bot{}/",
+            self.api_base_url.trim_end_matches('/'),
+            self.token
+        ))?)
+    }
+}
+
+/// Split text into chunks of at most max_len characters. Chunks end at
+/// line boundaries if possible. Code blocks spanning multiple chunks are
+/// closed at the end of a chunk and reopened in the next one.
+fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    // Leave room to close and reopen a code block
+    let limit = max_len - 2 * (CODE_FENCE.len() + 1);
+
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_len = 0;
+    let mut in_code = false;
+
+    for line in text.split('\n').flat_map(|i| split_line(i, limit)) {
+        let line_len = line.chars().count();
+
+        if !chunk.is_empty() && chunk_len + 1 + line_len > limit {
+            if in_code {
+                chunk.push('\n');
+                chunk.push_str(CODE_FENCE);
+            }
+            chunks.push(chunk);
+
+            chunk = String::new();
+            chunk_len = 0;
+            if in_code {
+                chunk.push_str(CODE_FENCE);
+                chunk.push('\n');
+                chunk_len = CODE_FENCE.len() + 1;
+            }
+        } else if !chunk.is_empty() {
+            chunk.push('\n');
+            chunk_len += 1;
+        }
+
+        chunk.push_str(line);
+        chunk_len += line_len;
+
+        if line.matches(CODE_FENCE).count() % 2 == 1 {
+            in_code = !in_code;
+        }
+    }
+
+    if !chunk.is_empty() || chunks.is_empty() {
+        chunks.push(chunk);
+    }
+
+    chunks
+}
+
+/// Split a line into pieces of at most max_len characters. Pieces
+/// don't end with a backslash so escape sequences stay intact.
+fn split_line(line: &str, max_len: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = line;
+
+    while rest.chars().count() > max_len {
+        let mut end = rest.char_indices().nth(max_len).map(|(i, _)| i).unwrap();
+        if rest[..end].ends_with('\\') && end > 1 {
+            end -= 1;
+        }
+
+        pieces.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    pieces.push(rest);
+    pieces
+}
+
+/// Escape text to appear literally in a MarkdownV2 message.
+pub fn escape_markdown_v2(text: &str) -> String {
+    escape_chars(text, MARKDOWN_V2_SPECIAL)
+}
+
+/// Escape text to appear literally in a MarkdownV2 code block.
+pub fn escape_markdown_v2_code(text: &str) -> String {
+    escape_chars(text, "`\\")
+}
+
+/// Escape text to appear literally in a HTML message.
+#[allow(dead_code)]
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_chars(text: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Returns the delay before the next attempt. The delay doubles with each
+/// attempt unless the API asked to wait retry_after seconds.
+fn retry_delay(attempt: u32, retry_after: Option<u64>) -> Duration {
+    match retry_after {
+        Some(secs) => Duration::from_secs(secs),
+        None => RETRY_DELAY * 2u32.pow(attempt - 1),
     }
 }
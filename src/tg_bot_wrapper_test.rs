@@ -0,0 +1,120 @@
+use super::*;
+
+#[test]
+fn check_retry_delay() {
+    assert_eq!(retry_delay(1, None), Duration::from_secs(1));
+    assert_eq!(retry_delay(3, None), Duration::from_secs(4));
+    assert_eq!(retry_delay(3, Some(30)), Duration::from_secs(30));
+}
+
+#[test]
+fn check_escape_markdown_v2() {
+    assert_eq!(escape_markdown_v2("foo-bar 1.2-1"), "foo\\-bar 1\\.2\\-1");
+    assert_eq!(escape_markdown_v2("a_b*(c)"), "a\\_b\\*\\(c\\)");
+    assert_eq!(escape_markdown_v2("\\"), "\\\\");
+    assert_eq!(
+        escape_markdown_v2_code("pkgver=1.2 `x`"),
+        "pkgver=1.2 \\`x\\`"
+    );
+}
+
+#[test]
+fn check_escape_html() {
+    assert_eq!(escape_html("<b>a & b</b>"), "&lt;b&gt;a &amp; b&lt;/b&gt;");
+}
+
+#[test]
+fn split_short_message() {
+    assert_eq!(split_message("short\ntext", 100), vec!["short\ntext"]);
+    assert_eq!(split_message("", 100), vec![""]);
+}
+
+#[test]
+fn split_message_on_lines() {
+    let text = "aaaa\nbbbb\ncccc";
+    // 4 characters of room per chunk
+    assert_eq!(split_message(text, 12), vec!["aaaa", "bbbb", "cccc"]);
+
+    // Too long lines get split
+    assert_eq!(split_message("aaaaaaaaa", 12), vec!["aaaa", "aaaa", "a"]);
+    assert_eq!(split_message("aaa\\-b", 12), vec!["aaa", "\\-b"]);
+}
+
+#[test]
+fn split_message_code_blocks() {
+    let text = "diff:\n```\n-aaa\n+bbb\n```\ndone";
+    let chunks = split_message(text, 22);
+    assert_eq!(
+        chunks,
+        vec!["diff:\n```\n-aaa\n```", "```\n+bbb\n```", "done"]
+    );
+
+    for chunk in chunks {
+        assert_eq!(chunk.matches(CODE_FENCE).count() % 2, 0);
+    }
+}
+
+#[test]
+fn check_api_url() {
+    let bot = TgBot::new("token".to_owned(), 1);
+    assert_eq!(
+        bot.get_url().unwrap().as_str(),
+        "https://api.telegram.org/bottoken/"
+    );
+
+    let bot = bot.with_api_base_url("http://localhost:8081/");
+    assert_eq!(
+        bot.get_url().unwrap().as_str(),
+        "http://localhost:8081/bottoken/"
+    );
+
+    assert!(bot.with_api_base_url("not a url").get_url().is_err());
+}
+
+#[test]
+fn check_recipients() {
+    let bot = TgBot::new("token".to_owned(), 1);
+    assert_eq!(bot.recipients(), &[1]);
+
+    let bot = bot.with_recipients(vec![]);
+    assert_eq!(bot.recipients(), &[1]);
+
+    let bot = bot.with_recipients(vec![1, -100123]);
+    assert_eq!(bot.recipients(), &[1, -100123]);
+}
+
+#[test]
+fn drop_unawaited_callbacks() {
+    let query = |id: &str, message_id| CallbackQuery {
+        id: id.to_owned(),
+        message: Some(Message {
+            message_id,
+            chat: Chat { id: 1 },
+            text: None,
+        }),
+        data: None,
+    };
+
+    let mut state = UpdateState::default();
+    state.awaited.insert((1, 10));
+    state.callback_queries = vec![
+        query("awaited", 10),
+        query("timed out", 11),
+        CallbackQuery {
+            id: "no message".to_owned(),
+            message: None,
+            data: None,
+        },
+    ];
+
+    let ids = |queries: &[CallbackQuery]| queries.iter().map(|i| i.id.clone()).collect::<Vec<_>>();
+    assert_eq!(
+        ids(&state.take_unawaited()),
+        vec!["timed out", "no message"]
+    );
+    assert_eq!(ids(&state.callback_queries), vec!["awaited"]);
+
+    state.awaited.clear();
+    assert_eq!(ids(&state.take_unawaited()), vec!["awaited"]);
+    assert!(state.callback_queries.is_empty());
+}
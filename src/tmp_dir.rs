@@ -0,0 +1,49 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::error;
+use tokio::sync::watch;
+
+#[cfg(test)]
+#[path = "tmp_dir_test.rs"]
+mod tmp_dir_test;
+
+/// TmpDirGuard removes the tmp dir of a package update once
+/// it gets dropped, unless it was disarmed. Dirs are kept while
+/// shutting down, allowing interrupted updates to be resumed.
+pub struct TmpDirGuard<'a> {
+    path: PathBuf,
+    armed: bool,
+    shutdown: &'a watch::Receiver<bool>,
+}
+
+impl<'a> TmpDirGuard<'a> {
+    pub fn new(path: PathBuf, shutdown: &'a watch::Receiver<bool>) -> Self {
+        TmpDirGuard {
+            path,
+            armed: true,
+            shutdown,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keep the dir when the guard gets dropped.
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for TmpDirGuard<'_> {
+    fn drop(&mut self) {
+        if !self.armed || *self.shutdown.borrow() || !self.path.exists() {
+            return;
+        }
+
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            error!("Can't remove tmp dir {}: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use super::*;
+
+use crate::test_dir::TestDir;
+
+#[test]
+fn remove_on_drop() {
+    let (_tx, shutdown) = watch::channel(false);
+    let dir = TestDir::new("guard_drop");
+
+    drop(TmpDirGuard::new(dir.to_path_buf(), &shutdown));
+    assert!(!dir.exists());
+}
+
+#[test]
+fn keep_disarmed() {
+    let (_tx, shutdown) = watch::channel(false);
+    let dir = TestDir::new("guard_disarmed");
+
+    let mut guard = TmpDirGuard::new(dir.to_path_buf(), &shutdown);
+    guard.disarm();
+    drop(guard);
+    assert!(dir.exists());
+}
+
+#[test]
+fn keep_on_shutdown() {
+    let (_tx, shutdown) = watch::channel(true);
+    let dir = TestDir::new("guard_shutdown");
+
+    drop(TmpDirGuard::new(dir.to_path_buf(), &shutdown));
+    assert!(dir.exists());
+}
@@ -0,0 +1,33 @@
+use std::cmp::Ordering;
+
+#[cfg(test)]
+#[path = "version_test.rs"]
+mod version_test;
+
+/// Bring a version into epoch:pkgver-pkgrel form. The pkgver of
+/// pkginfo and the AUR both carry the epoch if the package has one,
+/// versions without epoch get the default epoch 0.
+pub fn normalize(version: &str) -> String {
+    if has_epoch(version) {
+        version.to_owned()
+    } else {
+        format!("0:{}", version)
+    }
+}
+
+/// Compare two versions the way pacman does.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    alpm::vercmp(normalize(a), normalize(b))
+}
+
+/// Returns true if the AUR version is newer than the local one.
+pub fn is_newer(local: &str, aur: &str) -> bool {
+    compare(local, aur) == Ordering::Less
+}
+
+fn has_epoch(version: &str) -> bool {
+    match version.find(':') {
+        Some(pos) => pos > 0 && version[..pos].chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
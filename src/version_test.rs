@@ -0,0 +1,25 @@
+use super::*;
+
+#[test]
+fn normalize_version() {
+    assert_eq!(normalize("2.0-1"), "0:2.0-1");
+    assert_eq!(normalize("1:1.0-1"), "1:1.0-1");
+    assert_eq!(normalize("0:1.0-1"), "0:1.0-1");
+}
+
+#[test]
+fn compare_epoch() {
+    assert_eq!(compare("1:1.0-1", "2.0-1"), Ordering::Greater);
+    assert_eq!(compare("2.0-1", "1:1.0-1"), Ordering::Less);
+    assert_eq!(compare("0:2.0-1", "2.0-1"), Ordering::Equal);
+
+    assert!(is_newer("2.0-1", "1:1.0-1"));
+    assert!(!is_newer("1:1.0-1", "2.0-1"));
+}
+
+#[test]
+fn compare_versions() {
+    assert!(is_newer("1.9-1", "1.10-1"));
+    assert!(is_newer("1.0-1", "1.0-2"));
+    assert!(!is_newer("1.0-1", "1.0-1"));
+}
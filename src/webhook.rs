@@ -0,0 +1,147 @@
+use serde::Deserialize;
+
+use crate::api::{self, Response};
+use crate::refresh::Trigger;
+
+#[cfg(test)]
+#[path = "webhook_test.rs"]
+mod webhook_test;
+
+/// Header carrying the shared secret.
+#[cfg_attr(not(feature = "webhook"), allow(dead_code))]
+pub const SECRET_HEADER: &str = "X-AURtomatic-Secret";
+
+/// Maximum accepted size of a request body.
+#[cfg_attr(not(feature = "webhook"), allow(dead_code))]
+const MAX_BODY_SIZE: u64 = 4096;
+
+/// Optional body of a webhook request.
+#[derive(Debug, Default, Deserialize)]
+struct Payload {
+    /// Only refresh this package.
+    package: Option<String>,
+}
+
+/// Handle a webhook request. POST /webhook with the shared secret
+/// triggers a refresh of all packages, or of a single one if the
+/// JSON body names it, e.g. {"package": "foo"}.
+#[cfg_attr(not(feature = "webhook"), allow(dead_code))]
+pub fn handle(
+    trigger: &Trigger,
+    secret: &str,
+    (method, path): (&str, &str),
+    given_secret: Option<&str>,
+    body: &[u8],
+) -> Response {
+    if path.trim_end_matches('/') != "/webhook" {
+        return api::error(404, "not found");
+    }
+
+    if method != "POST" {
+        return api::error(405, "method not allowed");
+    }
+
+    if given_secret != Some(secret) {
+        return api::error(401, "invalid secret");
+    }
+
+    let payload: Payload = if body.iter().all(|i| i.is_ascii_whitespace()) {
+        Payload::default()
+    } else {
+        match serde_json::from_slice(body) {
+            Ok(payload) => payload,
+            Err(e) => return api::error(400, &format!("invalid body: {}", e)),
+        }
+    };
+
+    match payload.package {
+        Some(ref pkg_name) if !pkg_name.is_empty() => trigger.package(pkg_name),
+        _ => trigger.all(),
+    }
+
+    api::json(
+        202,
+        &serde_json::json!({ "refresh": payload.package.as_deref().unwrap_or("all") }),
+    )
+}
+
+/// Serve the webhook at the given port.
+#[cfg(feature = "webhook")]
+pub async fn serve(
+    trigger: std::sync::Arc<Trigger>,
+    port: u16,
+    secret: String,
+) -> Result<(), hyper::Error> {
+    use hyper::body::HttpBody;
+    use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Server, StatusCode};
+    use std::convert::Infallible;
+    use std::sync::Arc;
+
+    let secret = Arc::new(secret);
+    let make_service = make_service_fn(move |_| {
+        let (trigger, secret) = (trigger.clone(), secret.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let (trigger, secret) = (trigger.clone(), secret.clone());
+                async move {
+                    let header = |name| req.headers().get(name).and_then(|i| i.to_str().ok());
+                    let length =
+                        header(CONTENT_LENGTH.as_str()).and_then(|i| i.parse::<u64>().ok());
+                    let given_secret = header(SECRET_HEADER).map(|i| i.to_owned());
+
+                    // Unauthorized bodies aren't read at all, others only up to
+                    // MAX_BODY_SIZE, no matter what their Content-Length claims
+                    let res = if given_secret.as_deref() != Some(secret.as_str()) {
+                        api::error(401, "invalid secret")
+                    } else if matches!(length, Some(length) if length > MAX_BODY_SIZE) {
+                        api::error(413, "body too large")
+                    } else {
+                        let method = req.method().as_str().to_owned();
+                        let path = req.uri().path().to_owned();
+
+                        let mut body = req.into_body();
+                        let mut data = Vec::new();
+                        let mut res = None;
+                        while let Some(chunk) = body.data().await {
+                            match chunk {
+                                Ok(chunk) if (data.len() + chunk.len()) as u64 > MAX_BODY_SIZE => {
+                                    res = Some(api::error(413, "body too large"));
+                                    break;
+                                }
+                                Ok(chunk) => data.extend_from_slice(&chunk),
+                                Err(e) => {
+                                    res = Some(api::error(400, &e.to_string()));
+                                    break;
+                                }
+                            }
+                        }
+
+                        res.unwrap_or_else(|| {
+                            handle(
+                                &trigger,
+                                &secret,
+                                (&method, &path),
+                                given_secret.as_deref(),
+                                &data,
+                            )
+                        })
+                    };
+
+                    let mut response = hyper::Response::new(Body::from(res.body));
+                    *response.status_mut() = StatusCode::from_u16(res.status)
+                        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    response
+                        .headers_mut()
+                        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+
+    Server::bind(&([0, 0, 0, 0], port).into())
+        .serve(make_service)
+        .await
+}
@@ -0,0 +1,40 @@
+use super::*;
+
+use crate::refresh::Scope;
+
+fn post(trigger: &Trigger, secret: Option<&str>, body: &str) -> Response {
+    handle(
+        trigger,
+        "secret",
+        ("POST", "/webhook"),
+        secret,
+        body.as_bytes(),
+    )
+}
+
+#[test]
+fn trigger_refresh() {
+    let trigger = Trigger::default();
+
+    assert_eq!(post(&trigger, Some("secret"), "").status, 202);
+    assert_eq!(trigger.take(false), Scope::All);
+
+    let res = post(&trigger, Some("secret"), r#"{"package": "foo"}"#);
+    assert_eq!(res.status, 202);
+    assert_eq!(res.body, r#"{"refresh":"foo"}"#);
+    assert_eq!(trigger.take(false), Scope::Packages(vec!["foo".to_owned()]));
+}
+
+#[test]
+fn reject_invalid_requests() {
+    let trigger = Trigger::default();
+
+    assert_eq!(post(&trigger, None, "").status, 401);
+    assert_eq!(post(&trigger, Some("wrong"), "").status, 401);
+    assert_eq!(post(&trigger, Some("secret"), "foo").status, 400);
+
+    let res = handle(&trigger, "secret", ("GET", "/webhook"), Some("secret"), b"");
+    assert_eq!(res.status, 405);
+    let res = handle(&trigger, "secret", ("POST", "/"), Some("secret"), b"");
+    assert_eq!(res.status, 404);
+}